@@ -1,19 +1,59 @@
+use crate::error::Limit;
+use crate::error::ResultExt;
+use crate::format::{content_offset, decode_compressed_prefix, decode_prefix, PREFIX_LEN};
 use crate::header::{Directory, Entry, FileMetadata};
+use crate::info::ArchiveInfo;
+use crate::list::{render_index, IndexFormat, ListFormat, Listing};
+use crate::manifest::{EmbeddedManifest, Manifest, ManifestMismatch, MANIFEST_PATH};
 use crate::private::Sealed;
-use crate::{cfg_fs, cfg_integrity, split_path};
+use crate::read_dir::AsarReadDir;
+use crate::timeout::TimeoutReader;
+use crate::transform::{TransformReader, Transforms};
+use crate::walk::{Walk, WalkOptions};
+use crate::{
+  cfg_compressed_header, cfg_fs, cfg_integrity, cfg_stream, normalize_path, split_path,
+  AsArchivePath, Error,
+};
 use async_trait::async_trait;
 use pin_project::pin_project;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
 use std::io::{Cursor, SeekFrom};
+use std::ops::Deref;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, Take};
 
+cfg_compressed_header! {
+  use crate::format::HeaderCodec;
+}
+
 cfg_fs! {
+  use crate::extract::{extraction_root, ExtractOptions, ExtractState, PlannedEntry};
+  use crate::header::FilePosition;
+  use crate::UnpackedLayout;
   use std::path::{Path, PathBuf};
-  use tokio::fs::File as TokioFile;
+  use tokio::fs::{File as TokioFile, OpenOptions};
+  use tokio::io::AsyncWrite;
+}
+
+/// Default maximum buffer size used by sequential reads during extraction.
+const DEFAULT_MAX_READAHEAD: usize = 4 << 20;
+
+/// Maximum size of files whose content is preloaded by [`Archive::pin`].
+pub const MAX_PINNED_SIZE: u64 = 1 << 20;
+
+cfg_stream! {
+  use futures_core::Stream;
+  use futures_util::stream::{self, StreamExt};
 }
 
 cfg_integrity! {
+  use crate::header::{Hash, Integrity};
+  use std::ops::Range;
+  use std::task::ready;
   use sha2::digest::Digest;
   use sha2::Sha256;
 }
@@ -26,57 +66,595 @@ cfg_integrity! {
 #[derive(Debug)]
 pub struct Archive<R: AsyncRead + AsyncSeek + Unpin> {
   pub(crate) offset: u64,
+  pub(crate) len: u64,
   pub(crate) header: Directory,
   pub(crate) reader: R,
+  pub(crate) on_access: Option<AccessHook>,
+  pub(crate) max_readahead: usize,
+  pub(crate) pinned: HashMap<Box<str>, Pinned>,
+  pub(crate) transforms: Transforms,
 }
 
-/// Checks if a file is in asar format by reading and checking first 16 bytes.
+/// A file pinned by [`Archive::pin`].
+#[derive(Debug, Clone)]
+pub(crate) struct Pinned {
+  metadata: FileMetadata,
+  content: Option<Arc<[u8]>>,
+}
+
+type AccessFn = dyn Fn(&str, u64, Duration) + Send + Sync;
+
+/// Callback invoked when a [`File`] is dropped.
+#[derive(Clone)]
+pub(crate) struct AccessHook(Arc<AccessFn>);
+
+impl AccessHook {
+  fn start(&self, path: &str) -> AccessGuard {
+    AccessGuard {
+      hook: self.clone(),
+      path: path.into(),
+      start: Instant::now(),
+      bytes_read: 0,
+    }
+  }
+}
+
+impl Debug for AccessHook {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str("AccessHook")
+  }
+}
+
+/// Tracks reads of a [`File`], and reports them to [`AccessHook`] on drop.
+#[derive(Debug)]
+pub(crate) struct AccessGuard {
+  hook: AccessHook,
+  path: Box<str>,
+  start: Instant,
+  bytes_read: u64,
+}
+
+impl Drop for AccessGuard {
+  fn drop(&mut self) {
+    (self.hook.0)(&self.path, self.bytes_read, self.start.elapsed())
+  }
+}
+
+/// Limits enforced when parsing an archive with
+/// [`Archive::new_with_options`].
 ///
-/// Returns `Some(header_len)` if it is an asar archive, or `None` if it isn't.
-pub async fn check_asar_format(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<u32>> {
-  let [mut four, mut i1, mut i2, mut header_len] = [0; 4];
-  for x in [&mut four, &mut i1, &mut i2, &mut header_len] {
-    *x = reader.read_u32_le().await?;
+/// Every limit is `None` (unlimited) by default, overlapping files are
+/// accepted, integrity information is not interned, and compressed headers
+/// are not supported.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ArchiveOptions {
+  /// Maximum length of the JSON header in bytes.
+  pub max_header_len: Option<u32>,
+
+  /// Maximum number of entries, including directories.
+  pub max_entries: Option<u64>,
+
+  /// Maximum total size of all files, including unpacked ones.
+  pub max_total_size: Option<u64>,
+
+  /// Maximum length of an entry's full path in bytes.
+  pub max_path_len: Option<u64>,
+
+  /// Maximum length of a single component of an entry's path in bytes, e.g.
+  /// [`MAX_COMPONENT_LEN`](crate::format::MAX_COMPONENT_LEN).
+  ///
+  /// Exceeding it fails with [`Error::LimitExceeded`] wrapped in
+  /// [`Error::Entry`] naming the entry.
+  pub max_component_len: Option<u64>,
+
+  /// Whether to reject archives with files overlapping each other.
+  ///
+  /// See [`Archive::validate`].
+  pub reject_overlapping: bool,
+
+  /// Whether to share identical integrity block lists between files while
+  /// parsing the header.
+  ///
+  /// Unlike [`Archive::intern_integrity`] after parsing, duplicated lists are
+  /// freed as soon as they are read, so they are never all held at once.
+  /// Shared lists are copied on write by [`Arc::make_mut`].
+  pub intern_integrity: bool,
+
+  /// Codec decompressing headers stored compressed, a non-standard
+  /// extension described in [`format`](crate::format).
+  ///
+  /// Archives with compressed headers fail with
+  /// [`Error::UnsupportedCompressedHeader`] if this is `None`. Standard
+  /// archives are parsed as usual either way. [`max_header_len`] limits both
+  /// the compressed and the decompressed header.
+  ///
+  /// [`max_header_len`]: ArchiveOptions::max_header_len
+  #[cfg(feature = "compressed-header")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "compressed-header")))]
+  pub header_codec: Option<&'static dyn HeaderCodec>,
+}
+
+impl ArchiveOptions {
+  fn check(&self, header: &Directory) -> io::Result<()> {
+    let (mut entries, mut total_size, mut path_len) = (0u64, 0u64, 0u64);
+    let mut long_component = None::<(String, u64)>;
+    header.visit(&mut |path, entry| {
+      entries += 1;
+      path_len = path_len.max(path.len() as u64);
+      if let Entry::File(file) = entry {
+        total_size = total_size.saturating_add(file.size);
+      }
+      if let Some(max) = self.max_component_len {
+        let len = path.rsplit('/').next().unwrap_or_default().len() as u64;
+        if len > max && long_component.is_none() {
+          long_component = Some((path.to_owned(), len));
+        }
+      }
+    });
+    check_limit(Limit::Entries, entries, self.max_entries)?;
+    check_limit(Limit::TotalSize, total_size, self.max_total_size)?;
+    check_limit(Limit::PathLen, path_len, self.max_path_len)?;
+    if let (Some((path, value)), Some(max)) = (long_component, self.max_component_len) {
+      let source = Error::LimitExceeded {
+        limit: Limit::ComponentLen,
+        value,
+        max,
+      }
+      .into();
+      return Err(
+        Error::Entry {
+          path: path.into(),
+          source,
+        }
+        .into(),
+      );
+    }
+    if self.reject_overlapping {
+      validate(header)?;
+    }
+    Ok(())
   }
+}
 
-  let padding = match header_len % 4 {
-    0 => 0,
-    r => 4 - r,
+fn validate(header: &Directory) -> io::Result<()> {
+  match header.find_overlap() {
+    Some((first, second)) => Err(
+      Error::Overlap {
+        first: first.into(),
+        second: second.into(),
+      }
+      .into(),
+    ),
+    None => Ok(()),
+  }
+}
+
+fn format_error() -> io::Error {
+  io::Error::other("file format check failed")
+}
+
+/// Checks the prefix of an archive whose header is read as is.
+fn check_prefix(prefix: &[u8; PREFIX_LEN]) -> io::Result<u32> {
+  match decode_prefix(prefix) {
+    Some(header_len) => Ok(header_len),
+    None if decode_compressed_prefix(prefix).is_some() => {
+      Err(Error::UnsupportedCompressedHeader.into())
+    }
+    None => Err(format_error()),
+  }
+}
+
+#[cfg(feature = "compressed-header")]
+fn decompress_header(data: &[u8], options: &ArchiveOptions) -> io::Result<Vec<u8>> {
+  let Some(codec) = options.header_codec else {
+    return Err(Error::UnsupportedCompressedHeader.into());
   };
+  let max_len = options.max_header_len.map_or(usize::MAX, |x| x as _);
+  let header = codec.decompress(data, max_len)?;
+  check_limit(
+    Limit::HeaderLen,
+    header.len() as u64,
+    options.max_header_len,
+  )?;
+  Ok(header)
+}
+
+#[cfg(not(feature = "compressed-header"))]
+fn decompress_header(_data: &[u8], _options: &ArchiveOptions) -> io::Result<Vec<u8>> {
+  Err(Error::UnsupportedCompressedHeader.into())
+}
+
+fn check_limit(limit: Limit, value: u64, max: Option<impl Into<u64>>) -> io::Result<()> {
+  match max.map(Into::into) {
+    Some(max) if value > max => Err(Error::LimitExceeded { limit, value, max }.into()),
+    _ => Ok(()),
+  }
+}
+
+/// Checks if a file is in asar format by reading and checking first 16 bytes.
+///
+/// Returns `Some(header_len)` if it is an asar archive, or `None` if it isn't.
+/// The reader is left at the start of the header. See [`probe_asar_format`]
+/// for a stricter check.
+pub async fn check_asar_format(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<u32>> {
+  let mut prefix = [0; PREFIX_LEN];
+  reader.read_exact(&mut prefix).await?;
+  Ok(decode_prefix(&prefix))
+}
 
-  let i1_e = header_len + padding + 8;
-  let i2_e = header_len + padding + 4;
+/// Maximum number of header bytes read by [`probe_asar_format`].
+const PROBE_LEN: u32 = 64;
 
-  if four == 4 && i1 == i1_e && i2 == i2_e {
-    Ok(Some(header_len))
-  } else {
-    Ok(None)
+/// Checks if a file is in asar format, like [`check_asar_format`], and also
+/// that the header starts like a JSON directory, i.e. `{"files":` or `{}`.
+///
+/// Meant for content sniffing: random data rarely passes the prefix check,
+/// but this makes false positives much less likely while reading at most 64
+/// bytes of the header, however large it is. Inputs shorter than 16 bytes
+/// are not asar archives, instead of failing with
+/// [`UnexpectedEof`](io::ErrorKind::UnexpectedEof).
+///
+/// Returns `Some(header_len)` if it is an asar archive, or `None` if it isn't.
+/// The reader is left somewhere inside the header.
+pub async fn probe_asar_format(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<u32>> {
+  let mut prefix = [0; PREFIX_LEN];
+  match reader.read_exact(&mut prefix).await {
+    Ok(_) => {}
+    Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(error) => return Err(error),
   }
+  let Some(header_len) = decode_prefix(&prefix) else {
+    return Ok(None);
+  };
+  let mut probe = Vec::with_capacity(header_len.min(PROBE_LEN) as _);
+  (&mut *reader)
+    .take(header_len.min(PROBE_LEN).into())
+    .read_to_end(&mut probe)
+    .await?;
+  Ok(starts_like_directory(&probe).then_some(header_len))
+}
+
+/// Whether `bytes` start with `{"files":` or `{}`, ignoring JSON whitespace.
+fn starts_like_directory(bytes: &[u8]) -> bool {
+  let mut rest = bytes;
+  let mut expect = |token: &[u8]| {
+    let start = (rest.iter())
+      .position(|x| !matches!(x, b' ' | b'\t' | b'\n' | b'\r'))
+      .unwrap_or(rest.len());
+    rest = &rest[start..];
+    let matched = rest.starts_with(token);
+    if matched {
+      rest = &rest[token.len()..];
+    }
+    matched
+  };
+  expect(b"{") && (expect(b"}") || expect(b"\"files\"") && expect(b":"))
 }
 
 impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
   /// Parses an asar archive into `Archive`.
-  pub async fn new(mut reader: R) -> io::Result<Self> {
-    let header_len = check_asar_format(&mut reader)
-      .await?
-      .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "file format check failed"))?;
+  pub async fn new(reader: R) -> io::Result<Self> {
+    Self::new_with_options(reader, Default::default()).await
+  }
+
+  /// Parses an asar archive into `Archive`, enforcing limits in `options`.
+  ///
+  /// Useful for untrusted archives: the header's length is checked before
+  /// it is read, and the other limits right after it is parsed, so resource
+  /// usage is bounded before any file is read or extracted. Exceeding a limit
+  /// fails with [`Error::LimitExceeded`].
+  pub async fn new_with_options(mut reader: R, options: ArchiveOptions) -> io::Result<Self> {
+    let mut prefix = [0; PREFIX_LEN];
+    reader.read_exact(&mut prefix).await?;
+    let (header_len, compressed) = match decode_prefix(&prefix) {
+      Some(header_len) => (header_len, false),
+      None => (
+        decode_compressed_prefix(&prefix).ok_or_else(format_error)?,
+        true,
+      ),
+    };
+    check_limit(Limit::HeaderLen, header_len as u64, options.max_header_len)?;
 
     let mut header_bytes = vec![0; header_len as _];
     reader.read_exact(&mut header_bytes).await?;
+    if compressed {
+      header_bytes = decompress_header(&header_bytes, &options)?;
+    }
 
-    let header = serde_json::from_slice(&header_bytes).map_err(io::Error::from)?;
-    let offset = match header_len % 4 {
-      0 => header_len + 16,
-      r => header_len + 16 + 4 - r,
-    } as u64;
+    let header = Directory::from_json(&header_bytes, options.intern_integrity)?;
+    options.check(&header)?;
+    let offset = content_offset(header_len);
+    let len = reader.seek(SeekFrom::End(0)).await?;
 
     Ok(Self {
       offset,
+      len,
       header,
       reader,
+      on_access: None,
+      max_readahead: DEFAULT_MAX_READAHEAD,
+      pinned: HashMap::new(),
+      transforms: Default::default(),
     })
   }
 
+  /// Checks if the archive contains every file's content described in the
+  /// header.
+  ///
+  /// This only compares the header against the archive's length, and is useful
+  /// for quickly validating downloads.
+  pub fn is_complete(&self) -> bool {
+    self.offset.saturating_add(self.content_len()) <= self.len
+  }
+
+  /// Returns the length of the content region, computed from the header.
+  ///
+  /// The content region starts right after the header and ends at the end of
+  /// the furthest file's content.
+  pub fn content_len(&self) -> u64 {
+    self.header.content_len()
+  }
+
+  /// Returns the length of data after the content region.
+  ///
+  /// Some tools append signatures or other metadata after the archive's
+  /// content; these bytes can be read using [`Archive::trailing_reader`].
+  pub fn trailing_len(&self) -> u64 {
+    self
+      .len
+      .saturating_sub(self.offset.saturating_add(self.content_len()))
+  }
+
+  pub(crate) fn check_truncated(&self, path: &str, metadata: &FileMetadata) -> io::Result<()> {
+    let start = self.offset.checked_add(metadata.offset()?);
+    let available = start.map_or(0, |start| self.len.saturating_sub(start));
+    if start.is_none() || available < metadata.size {
+      return Err(
+        Error::Truncated {
+          path: path.into(),
+          expected: metadata.size,
+          available,
+        }
+        .into(),
+      );
+    }
+    Ok(())
+  }
+
+  /// Checks that no two files' content overlap.
+  ///
+  /// Archives written by this crate or `@electron/asar` never have
+  /// overlapping files. Maliciously crafted ones may, to alias content or to
+  /// make a small archive extract into a huge amount of data. Fails with
+  /// [`Error::Overlap`] naming two of the overlapping files.
+  ///
+  /// To check this when parsing, set [`ArchiveOptions::reject_overlapping`].
+  pub fn validate(&self) -> io::Result<()> {
+    validate(&self.header)
+  }
+
+  /// Reads the header's JSON bytes exactly as stored in the archive.
+  ///
+  /// Unlike [`Archive::header`], which is parsed into a typed model that may
+  /// normalize or drop unknown fields, this shows exactly what the archive
+  /// claims, e.g. for debugging tools. The header is read again from the
+  /// reader.
+  pub async fn raw_header(&mut self) -> io::Result<Vec<u8>> {
+    self.reader.seek(SeekFrom::Start(0)).await?;
+    let mut prefix = [0; PREFIX_LEN];
+    self.reader.read_exact(&mut prefix).await?;
+    let header_len = check_prefix(&prefix)?;
+    let mut header_bytes = vec![0; header_len as _];
+    self.reader.read_exact(&mut header_bytes).await?;
+    Ok(header_bytes)
+  }
+
+  /// Reads the header as an untyped JSON value.
+  ///
+  /// See [`Archive::raw_header`].
+  pub async fn raw_header_json(&mut self) -> io::Result<serde_json::Value> {
+    let header_bytes = self.raw_header().await?;
+    serde_json::from_slice(&header_bytes).map_err(io::Error::from)
+  }
+
+  cfg_integrity! {
+    /// Computes the SHA256 hash of the header as stored in the archive, as
+    /// checked by Electron's ASAR integrity fuse.
+    ///
+    /// The header is read again from the reader, so the hash covers its exact
+    /// bytes rather than a reserialization. Its [`Display`] output is the hex
+    /// string expected in `Info.plist`'s `ElectronAsarIntegrity`.
+    ///
+    /// [`Display`]: std::fmt::Display
+    pub async fn header_hash(&mut self) -> io::Result<Hash> {
+      self.reader.seek(SeekFrom::Start(0)).await?;
+      let mut prefix = [0; PREFIX_LEN];
+      self.reader.read_exact(&mut prefix).await?;
+      let header_len = check_prefix(&prefix)?;
+      let mut header = (&mut self.reader).take(header_len.into());
+      let mut digest = Sha256::new();
+      let mut buf = vec![0; 65536];
+      loop {
+        let len = header.read(&mut buf).await?;
+        if len == 0 {
+          break;
+        }
+        digest.update(&buf[..len]);
+      }
+      if header.limit() > 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+      }
+      Ok(Hash::Sha256(digest.finalize().into()))
+    }
+  }
+
+  /// Returns the header of the archive.
+  pub fn header(&self) -> &Directory {
+    &self.header
+  }
+
+  /// Returns a deep copy of the header of the archive.
+  ///
+  /// Unlike [`Archive::header`], the snapshot does not borrow the archive, and
+  /// stays the same even if the archive's header is modified later, e.g. by
+  /// [`EditableArchive::overwrite`](crate::EditableArchive::overwrite).
+  pub fn header_snapshot(&self) -> Directory {
+    self.header.clone()
+  }
+
+  /// Summarizes the archive, e.g. for reporting in CLIs.
+  pub fn info(&self) -> ArchiveInfo {
+    ArchiveInfo::new(&self.header, self.offset)
+  }
+
+  /// Lists every entry in the archive, formatted with `format` when displayed.
+  pub fn listing(&self, format: ListFormat) -> Listing {
+    Listing::new(&self.header, format)
+  }
+
+  /// Walks every entry in the archive recursively.
+  ///
+  /// See [`WalkOptions`] for filtering and ordering.
+  pub fn walk_with(&self, options: WalkOptions) -> Walk<'_> {
+    Walk::new(&self.header, options)
+  }
+
+  /// Returns every packed file in the archive along with its path, sorted by
+  /// offset.
+  ///
+  /// Reading files in this order touches the archive sequentially, which is
+  /// much faster than the header's arbitrary order on spinning disks and
+  /// network file systems. Extraction uses the same order.
+  pub fn entries_by_offset(&self) -> Vec<(String, &FileMetadata)> {
+    let mut files: Vec<_> = (self.walk_with(WalkOptions::default()))
+      .filter_map(|item| match item.entry {
+        Entry::File(file) => Some((file.offset().ok()?, item.path, file)),
+        Entry::Directory(_) => None,
+      })
+      .collect();
+    files.sort_unstable_by_key(|(offset, ..)| *offset);
+    files
+      .into_iter()
+      .map(|(_, path, file)| (path, file))
+      .collect()
+  }
+
+  /// Reads entries of the directory at `path`, like [`tokio::fs::read_dir`].
+  ///
+  /// An empty path refers to the root directory.
+  pub fn read_dir(&self, path: &str) -> io::Result<AsarReadDir<'_>> {
+    let (path, dir) = self.search_dir(path)?;
+    Ok(AsarReadDir::new(path, dir))
+  }
+
+  /// Renders the directory at `path` as an autoindex-style page, e.g. for
+  /// serving directory URLs over HTTP like nginx does.
+  ///
+  /// An empty path refers to the root directory. See [`render_index`] for
+  /// details of the output.
+  pub fn render_dir_listing(&self, path: &str, format: IndexFormat) -> io::Result<String> {
+    let (path, dir) = self.search_dir(path)?;
+    Ok(render_index(dir, &path, format))
+  }
+
+  /// Returns the total size of every file under the directory at `path`,
+  /// recursively, from the header alone.
+  ///
+  /// An empty path refers to the root directory. See
+  /// [`Directory::size_recursive`].
+  pub fn dir_size(&self, path: &str) -> io::Result<u64> {
+    Ok(self.search_dir(path)?.1.size_recursive())
+  }
+
+  /// Finds the directory at `path`, returning its normalized path.
+  fn search_dir(&self, path: &str) -> io::Result<(String, &Directory)> {
+    let segments = split_path(path);
+    let dir = match self.header.search_segments(&segments) {
+      _ if segments.is_empty() => &self.header,
+      Some(Entry::Directory(dir)) => dir,
+      Some(Entry::File(_)) => return Err(io::Error::from_raw_os_error(libc::ENOTDIR)),
+      None => return Err(io::ErrorKind::NotFound.into()),
+    };
+    Ok((segments.join("/"), dir))
+  }
+
+  /// Exports paths, sizes and hashes of every file into a [`Manifest`].
+  pub fn export_manifest(&self) -> Manifest {
+    Manifest::from_header(&self.header)
+  }
+
+  /// Compares the archive's files against a previously exported manifest.
+  ///
+  /// Only the header is compared. To also make sure the content matches the
+  /// hashes, use [`File::check_integrity`] on each file.
+  pub fn verify_manifest(&self, manifest: &Manifest) -> Vec<ManifestMismatch> {
+    manifest.compare(&self.export_manifest())
+  }
+
+  /// Finds files with content hash `hash`, using integrity information in the
+  /// header.
+  ///
+  /// This is useful for checking whether some content already exists in the
+  /// archive without reading it. See [`Directory::find_by_hash`].
+  pub fn find_by_hash(&self, hash: &[u8]) -> Vec<String> {
+    self.header.find_by_hash(hash)
+  }
+
+  /// Shares identical integrity block lists between files in the header.
+  ///
+  /// See [`Directory::intern_integrity`] for more information.
+  pub fn intern_integrity(&mut self) {
+    self.header.intern_integrity()
+  }
+
+  /// Sets the maximum buffer size for sequential reads during extraction.
+  ///
+  /// When consecutive files are stored next to each other, extraction reads
+  /// them without seeking, and increases its buffer size up to this limit.
+  /// Larger values improve throughput on spinning disks and network file
+  /// systems. Defaults to 4 MiB.
+  pub fn set_readahead(&mut self, max_readahead: usize) {
+    self.max_readahead = max_readahead;
+  }
+
+  /// Sets a callback invoked whenever a file from the archive is dropped.
+  ///
+  /// The callback receives the path used to open the file, total bytes read
+  /// from it, and the time elapsed since it was opened. It can be used to
+  /// gather usage telemetry of an asar-backed virtual file system, e.g. to
+  /// find out hot files.
+  ///
+  /// Only files opened after setting the callback are tracked.
+  pub fn on_access(&mut self, hook: impl Fn(&str, u64, Duration) + Send + Sync + 'static) {
+    self.on_access = Some(AccessHook(Arc::new(hook)));
+  }
+
+  /// Registers a read transform for files whose extension field `key` equals
+  /// `value`, e.g. a zstd decoder for files with `"x-encoding": "zstd"`.
+  ///
+  /// Archives produced by writers that encode content and record it with
+  /// [`Writer::attributes_mut`](crate::Writer::attributes_mut) are then
+  /// readable with [`Archive::get_transformed`]. Transforms of every
+  /// matching field are applied in order of registration; registering one
+  /// for the same field and value again replaces it.
+  ///
+  /// ```
+  /// # fn f(archive: &mut hive_asar::Archive<std::io::Cursor<Vec<u8>>>) {
+  /// archive.register_transform("x-encoding", "identity", |reader| Ok(reader));
+  /// # }
+  /// ```
+  pub fn register_transform(
+    &mut self,
+    key: &str,
+    value: impl Into<serde_json::Value>,
+    transform: impl for<'a> Fn(TransformReader<'a>) -> io::Result<TransformReader<'a>>
+      + Send
+      + Sync
+      + 'static,
+  ) {
+    self.transforms.register(key, value.into(), transform);
+  }
+
   /// Returns a reference to its inner reader.
   pub fn reader(&self) -> &R {
     &self.reader
@@ -95,6 +673,30 @@ impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
   pub fn into_reader(self) -> R {
     self.reader
   }
+
+  /// Converts the archive into a cheaply cloneable, read-only
+  /// [`SharedArchive`].
+  pub fn into_shared(self) -> SharedArchive<R> {
+    self.into()
+  }
+
+  /// Fails reads and seeks of the underlying reader that take longer than
+  /// `timeout`, with [`io::ErrorKind::TimedOut`].
+  ///
+  /// Useful for archives on flaky network file systems, so that a hanging
+  /// read does not block forever. See [`TimeoutReader`] for more information.
+  pub fn with_io_timeout(self, timeout: Duration) -> Archive<TimeoutReader<R>> {
+    Archive {
+      offset: self.offset,
+      len: self.len,
+      header: self.header,
+      reader: TimeoutReader::new(self.reader, timeout),
+      on_access: self.on_access,
+      max_readahead: self.max_readahead,
+      pinned: self.pinned,
+      transforms: self.transforms,
+    }
+  }
 }
 
 cfg_fs! {
@@ -103,22 +705,188 @@ cfg_fs! {
     pub async fn new_from_file(path: impl Into<PathBuf>) -> io::Result<Self> {
       Self::new(DuplicableFile::open(path).await?).await
     }
+
+    /// Opens a file and parses it into [`Archive`], enforcing limits in
+    /// `options`.
+    ///
+    /// See [`Archive::new_with_options`] for more information.
+    pub async fn new_from_file_with_options(
+      path: impl Into<PathBuf>,
+      options: ArchiveOptions,
+    ) -> io::Result<Self> {
+      Self::new_with_options(DuplicableFile::open(path).await?, options).await
+    }
+
+    /// Copies a whole archive from a non-seekable reader into a file at
+    /// `spool_path`, and then opens it.
+    ///
+    /// Like [`Archive::from_reader`], but spools the archive to disk. The
+    /// file at `spool_path` is created or truncated, and is not removed
+    /// afterwards.
+    pub async fn from_reader_spooled(
+      mut reader: impl AsyncRead + Unpin,
+      spool_path: impl Into<PathBuf>,
+    ) -> io::Result<Self> {
+      let spool_path = spool_path.into();
+      let mut file = TokioFile::create(&spool_path).await.with_path(&spool_path)?;
+      io::copy(&mut reader, &mut file).await.with_path(&spool_path)?;
+      file.sync_all().await.with_path(&spool_path)?;
+      drop(file);
+      Self::new_from_file(spool_path).await
+    }
+
+    /// Returns where the unpacked file `path` is located, according to
+    /// `layout`.
+    ///
+    /// Fails if `path` does not exist in the archive or is not unpacked.
+    pub fn unpacked_path(&self, path: &str, layout: &UnpackedLayout) -> io::Result<PathBuf> {
+      match self.get_entry(path) {
+        Some(Entry::File(FileMetadata {
+          pos: FilePosition::Unpacked,
+          ..
+        })) => Ok(layout.file_path(&self.reader.path, path)),
+        Some(Entry::File(_)) => Err(io::Error::new(
+          io::ErrorKind::InvalidInput,
+          format!("'{path}' is not unpacked"),
+        )),
+        Some(Entry::Directory(_)) => Err(io::Error::from_raw_os_error(libc::EISDIR)),
+        None => Err(io::ErrorKind::NotFound.into()),
+      }
+    }
+
+    /// Opens the unpacked file `path`, according to `layout`.
+    pub async fn get_unpacked(&self, path: &str, layout: &UnpackedLayout) -> io::Result<TokioFile> {
+      TokioFile::open(self.unpacked_path(path, layout)?).await
+    }
+
+    /// Hints that files at `paths` will be read soon, warming the page cache.
+    ///
+    /// On Linux, Android and FreeBSD, this issues
+    /// `posix_fadvise(POSIX_FADV_WILLNEED)` for the files' byte ranges and
+    /// returns almost immediately. On other platforms, the ranges are read
+    /// ahead through a duplicated handle.
+    ///
+    /// Paths that do not exist, directories and unpacked files are skipped.
+    pub async fn prefetch<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+      let mut ranges: Vec<(u64, u64)> = (paths.into_iter())
+        .filter_map(|path| match self.get_entry(path)? {
+          Entry::File(FileMetadata {
+            pos: FilePosition::Offset(offset),
+            size,
+            ..
+          }) if *size > 0 => Some((self.offset + offset, *size)),
+          _ => None,
+        })
+        .collect();
+      ranges.sort_unstable();
+
+      // Merge adjacent or overlapping ranges, so sequential files are
+      // prefetched at once.
+      let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+      for (start, len) in ranges {
+        match merged.last_mut() {
+          Some((last_start, last_len)) if *last_start + *last_len >= start => {
+            *last_len = (*last_len).max(start + len - *last_start);
+          }
+          _ => merged.push((start, len)),
+        }
+      }
+
+      for (start, len) in merged {
+        self.reader.prefetch(start, len).await?;
+      }
+      Ok(())
+    }
+  }
+}
+
+impl Archive<Cursor<Vec<u8>>> {
+  /// Reads a whole archive from a non-seekable reader into memory, and then
+  /// parses it.
+  ///
+  /// This is useful for compressed archives (`.asar.gz`, `.asar.zst`, etc.):
+  /// wrap the compressed file in a decoder, for example from
+  /// [`async-compression`], and pass the decoder here. For archives too large
+  /// to fit in memory, see `Archive::from_reader_spooled`.
+  ///
+  /// [`async-compression`]: https://docs.rs/async-compression
+  pub async fn from_reader(mut reader: impl AsyncRead + Unpin) -> io::Result<Self> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    Self::new(Cursor::new(data)).await
+  }
+}
+
+impl<'a> Archive<Cursor<&'a [u8]>> {
+  /// Parses an asar archive in memory, without any I/O.
+  ///
+  /// This is convenient for archives embedded with [`include_bytes!`], and
+  /// allows borrowing files' content directly with [`Archive::get_slice`].
+  pub fn parse_slice(data: &'a [u8]) -> io::Result<Self> {
+    let eof = || io::Error::from(io::ErrorKind::UnexpectedEof);
+    let prefix = data.get(..PREFIX_LEN).ok_or_else(eof)?;
+    let header_len = check_prefix(prefix.try_into().unwrap())?;
+
+    let header_bytes = (data.get(PREFIX_LEN..PREFIX_LEN + header_len as usize)).ok_or_else(eof)?;
+    let header = serde_json::from_slice(header_bytes).map_err(io::Error::from)?;
+
+    Ok(Self {
+      offset: content_offset(header_len),
+      len: data.len() as u64,
+      header,
+      reader: Cursor::new(data),
+      on_access: None,
+      max_readahead: DEFAULT_MAX_READAHEAD,
+      pinned: HashMap::new(),
+      transforms: Default::default(),
+    })
+  }
+
+  /// Borrows the content of file `path` from the underlying slice, without
+  /// copying.
+  pub fn get_slice(&self, path: &str) -> io::Result<&'a [u8]> {
+    match self.get_entry(path) {
+      Some(Entry::File(metadata)) => {
+        self.check_truncated(path, metadata)?;
+        let data: &'a [u8] = self.reader.get_ref();
+        let start = (self.offset + metadata.offset()?) as usize;
+        Ok(&data[start..start + metadata.size as usize])
+      }
+      Some(Entry::Directory(_)) => Err(io::Error::from_raw_os_error(libc::EISDIR)),
+      None => Err(io::ErrorKind::NotFound.into()),
+    }
+  }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Archive<Cursor<&'a [u8]>> {
+  type Error = io::Error;
+
+  fn try_from(data: &'a [u8]) -> io::Result<Self> {
+    Self::parse_slice(data)
   }
 }
 
 impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
   /// Returns a file from the archive by taking mutable reference.
-  pub async fn get(&mut self, path: &str) -> io::Result<File<&mut R>> {
-    let entry = self.header.search_segments(&split_path(path));
+  ///
+  /// `path` is normalized unless it is an [`ArchivePath`](crate::ArchivePath)
+  /// or already normalized.
+  pub async fn get(&mut self, path: impl AsArchivePath) -> io::Result<File<&mut R>> {
+    let (normalized, path) = (path.to_archive_path(), path.as_path_str());
+    let segments: Vec<_> = normalized.segments().collect();
+    let entry = self.header.search_segments(&segments);
     match entry {
       Some(Entry::File(metadata)) => {
+        self.check_truncated(path, metadata)?;
         (self.reader)
           .seek(SeekFrom::Start(self.offset + metadata.offset()?))
           .await?;
         Ok(File {
           offset: self.offset,
+          path: normalized.as_str().into(),
           metadata: metadata.clone(),
           content: (&mut self.reader).take(metadata.size),
+          access: self.on_access.as_ref().map(|x| x.start(path)),
         })
       }
       Some(Entry::Directory(_)) => Err(io::Error::from_raw_os_error(libc::EISDIR)),
@@ -126,9 +894,147 @@ impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
     }
   }
 
+  /// Returns a file from the archive, with transforms registered with
+  /// [`Archive::register_transform`] matching its extension fields applied.
+  ///
+  /// Files no transform matches are read as is.
+  pub async fn get_transformed(&mut self, path: &str) -> io::Result<TransformReader<'_>>
+  where
+    R: Send,
+  {
+    let transforms = self.transforms.clone();
+    let file = self.get(path).await?;
+    let metadata = file.metadata.clone();
+    transforms.apply(&metadata, Box::pin(file))
+  }
+
+  /// Reads the manifest embedded at [`MANIFEST_PATH`] when the archive was
+  /// packed, e.g. with
+  /// [`Writer::set_embed_manifest`](crate::Writer::set_embed_manifest).
+  ///
+  /// Returns `None` if there is none, and fails with
+  /// [`InvalidData`](io::ErrorKind::InvalidData) if it is malformed. To check
+  /// the archive against it, compare [`EmbeddedManifest::manifest`] with
+  /// [`Archive::export_manifest`], which also lists the manifest itself.
+  pub async fn manifest(&mut self) -> io::Result<Option<EmbeddedManifest>> {
+    let mut file = match self.get(MANIFEST_PATH).await {
+      Ok(file) => file,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(error) => return Err(error),
+    };
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).await?;
+    Ok(Some(
+      serde_json::from_slice(&content).map_err(io::Error::from)?,
+    ))
+  }
+
+  cfg_integrity! {
+    /// Returns a file from the archive whose content is verified against its
+    /// integrity information while being read.
+    ///
+    /// See [`VerifiedFile`] for more information. Fails with
+    /// [`InvalidData`](io::ErrorKind::InvalidData) if the file has no
+    /// integrity information.
+    pub async fn read_verified(
+      &mut self,
+      path: impl AsArchivePath,
+    ) -> io::Result<VerifiedFile<&mut R>> {
+      VerifiedFile::new(self.get(path).await?)
+    }
+  }
+
+  /// Returns a reader of data after the content region.
+  ///
+  /// See [`Archive::trailing_len`] for more information.
+  pub async fn trailing_reader(&mut self) -> io::Result<Take<&mut R>> {
+    let len = self.trailing_len();
+    let start = self.offset.saturating_add(self.content_len());
+    self.reader.seek(SeekFrom::Start(start)).await?;
+    Ok((&mut self.reader).take(len))
+  }
+
   /// Returns the entry ("metadata") of specified path.
-  pub fn get_entry(&self, path: &str) -> Option<&Entry> {
-    self.header.search_segments(&split_path(path))
+  pub fn get_entry(&self, path: impl AsArchivePath) -> Option<&Entry> {
+    let path = path.to_archive_path();
+    self
+      .header
+      .search_segments(&path.segments().collect::<Vec<_>>())
+  }
+
+  /// Returns a view of the directory at `prefix`, resolving every path
+  /// relative to it.
+  ///
+  /// Useful when only a nested directory matters, e.g. `resources/app`.
+  /// Paths cannot leave the subtree, since `..` never goes above its root.
+  pub fn scoped(&mut self, prefix: &str) -> io::Result<ScopedArchive<'_, R>> {
+    let (prefix, _) = self.search_dir(prefix)?;
+    Ok(ScopedArchive {
+      archive: self,
+      prefix,
+    })
+  }
+
+  /// Pins startup-critical files, resolving their metadata and preloading
+  /// content of those no larger than [`MAX_PINNED_SIZE`] into memory.
+  ///
+  /// Pinned files are then served by [`Archive::read_pinned`] and
+  /// [`Archive::pinned_metadata`] without touching the reader, which helps
+  /// cold starts where a few files gate everything else, like Electron apps'
+  /// entry scripts. Files are read in the order of their offsets, so that
+  /// adjacent ones are loaded without seeking back and forth.
+  ///
+  /// Fails if any path is not a packed file. Pinning a file again reloads it.
+  pub async fn pin<'a>(&mut self, paths: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+    let mut files = Vec::new();
+    for path in paths {
+      let path = normalize_path(path);
+      let metadata = match self.get_entry(&path) {
+        Some(Entry::File(metadata)) => metadata,
+        Some(_) => return Err(io::Error::from_raw_os_error(libc::EISDIR)),
+        None => return Err(io::Error::from_raw_os_error(libc::ENOENT)),
+      };
+      let offset = metadata.offset().with_entry(&path)?;
+      files.push((offset, path));
+    }
+    files.sort_unstable();
+
+    for (_, path) in files {
+      let mut file = self.get(&path).await?;
+      let metadata = file.metadata().clone();
+      let content = if metadata.size <= MAX_PINNED_SIZE {
+        let mut content = Vec::with_capacity(metadata.size as _);
+        file.read_to_end(&mut content).await?;
+        Some(content.into())
+      } else {
+        None
+      };
+      self
+        .pinned
+        .insert(path.into(), Pinned { metadata, content });
+    }
+    Ok(())
+  }
+
+  /// Returns the preloaded content of a file pinned by [`Archive::pin`].
+  ///
+  /// Returns `None` if the file is not pinned, or is too large to be
+  /// preloaded; use [`Archive::get`] or [`Archive::get_owned`] then.
+  pub fn read_pinned(&self, path: &str) -> Option<Arc<[u8]>> {
+    let pinned = self.pinned.get(&*normalize_path(path))?;
+    pinned.content.clone()
+  }
+
+  /// Returns the metadata of a file pinned by [`Archive::pin`], without
+  /// searching the header.
+  pub fn pinned_metadata(&self, path: &str) -> Option<&FileMetadata> {
+    let pinned = self.pinned.get(&*normalize_path(path))?;
+    Some(&pinned.metadata)
+  }
+
+  /// Unpins every file, dropping preloaded content.
+  pub fn unpin_all(&mut self) {
+    self.pinned.clear();
   }
 }
 
@@ -136,83 +1042,408 @@ macro_rules! impl_get_owned {
   (
     $(#[$attr:ident $($args:tt)*])*
     $get_owned:ident,
+    $verify_isolation:ident,
     $duplicate:ident $(,)?
   ) => {
     impl<R: AsyncRead + AsyncSeek + $duplicate + Unpin> Archive<R> {
       $(#[$attr $($args)*])*
-      pub async fn $get_owned(&self, path: &str) -> io::Result<File<R>> {
-        let entry = self.header.search_segments(&split_path(path));
+      pub async fn $get_owned(&self, path: impl AsArchivePath) -> io::Result<File<R>> {
+        let (normalized, path) = (path.to_archive_path(), path.as_path_str());
+        let segments: Vec<_> = normalized.segments().collect();
+        let entry = self.header.search_segments(&segments);
         match entry {
           Some(Entry::File(metadata)) => {
+            self.check_truncated(path, metadata)?;
             let mut file = self.reader.duplicate().await?;
             let seek_from = SeekFrom::Start(self.offset + metadata.offset()?);
             file.seek(seek_from).await?;
             Ok(File {
               offset: self.offset,
+              path: normalized.as_str().into(),
               metadata: metadata.clone(),
               content: file.take(metadata.size),
+              access: self.on_access.as_ref().map(|x| x.start(path)),
             })
           }
           Some(_) => Err(io::Error::from_raw_os_error(libc::EISDIR)),
           None => Err(io::Error::from_raw_os_error(libc::ENOENT)),
         }
       }
+
+      /// Checks that readers duplicated from the inner reader do not share
+      /// their position.
+      ///
+      /// Two duplicates are seeked to different positions, and the first one
+      /// must still read the archive's prefix afterwards. Useful in debug
+      /// builds and tests when implementing a new duplicable reader, since a
+      /// shared cursor silently corrupts concurrent reads.
+      pub async fn $verify_isolation(&self) -> io::Result<()> {
+        let mut first = self.reader.duplicate().await?;
+        let mut second = self.reader.duplicate().await?;
+        first.seek(SeekFrom::Start(0)).await?;
+        second.seek(SeekFrom::End(0)).await?;
+
+        let mut prefix = [0; PREFIX_LEN];
+        let isolated = first.stream_position().await? == 0
+          && first.read_exact(&mut prefix).await.is_ok()
+          && decode_prefix(&prefix).is_some()
+          && second.stream_position().await? != 0;
+        if isolated {
+          Ok(())
+        } else {
+          Err(io::Error::other("duplicated readers share their position"))
+        }
+      }
+    }
+  }
+}
+
+impl_get_owned! {
+  /// Returns a file from the archive by duplicating the inner reader.
+  ///
+  /// Contrary to [`Archive::get`], it allows multiple read access over a single
+  /// archive by creating a new file handle for every file. Useful when building a
+  /// virtual file system like how Electron does.
+  get_owned,
+  verify_isolation,
+  Duplicable,
+}
+
+impl_get_owned! {
+  /// Returns a file from the archive by duplicating the inner reader, without `Sync`.
+  ///
+  /// See [`Archive::get_owned`] for more information.
+  get_owned_local,
+  verify_isolation_local,
+  LocalDuplicable,
+}
+
+cfg_stream! {
+  impl<R: AsyncRead + AsyncSeek + Duplicable + Unpin> Archive<R> {
+    /// Streams every packed file in the archive along with its path, in the
+    /// order of their offsets.
+    ///
+    /// Files are opened lazily with [`Archive::get_owned`] as the stream is
+    /// polled, and each has its own duplicated reader, so they can be
+    /// processed concurrently, e.g. with `StreamExt::for_each_concurrent`.
+    /// Unpacked files are skipped.
+    pub fn entry_stream(&self) -> impl Stream<Item = io::Result<(String, File<R>)>> + '_ {
+      let files: Vec<_> = (self.entries_by_offset().into_iter())
+        .map(|(path, _)| path)
+        .collect();
+      stream::iter(files).then(move |path| async move {
+        let file = self.get_owned(&path).await?;
+        Ok((path, file))
+      })
+    }
+  }
+}
+
+macro_rules! impl_archive_try_clone {
+  (
+    $(#[$attr:ident $($args:tt)*])*
+    $try_clone:ident,
+    $duplicate:ident $(,)?
+  ) => {
+    impl<R: AsyncRead + AsyncSeek + $duplicate + Unpin> Archive<R> {
+      $(#[$attr $($args)*])*
+      pub async fn $try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+          offset: self.offset,
+          len: self.len,
+          header: self.header.clone(),
+          reader: self.reader.duplicate().await?,
+          on_access: self.on_access.clone(),
+          max_readahead: self.max_readahead,
+          pinned: self.pinned.clone(),
+          transforms: self.transforms.clone(),
+        })
+      }
     }
   }
 }
 
-impl_get_owned! {
-  /// Returns a file from the archive by duplicating the inner reader.
+impl_archive_try_clone! {
+  /// Creates a new archive by duplicating the inner reader and cloning the
+  /// header.
   ///
-  /// Contrary to [`Archive::get`], it allows multiple read access over a single
-  /// archive by creating a new file handle for every file. Useful when building a
-  /// virtual file system like how Electron does.
-  get_owned,
+  /// The new archive is independent of the original one, so it can take a
+  /// consistent view of the archive (e.g. in tests or tools) while the
+  /// original continues serving reads.
+  try_clone,
   Duplicable,
 }
 
-impl_get_owned! {
-  /// Returns a file from the archive by duplicating the inner reader, without `Sync`.
+impl_archive_try_clone! {
+  /// Creates a new archive by duplicating the inner reader and cloning the
+  /// header, without `Sync`.
   ///
-  /// See [`Archive::get_owned`] for more information.
-  get_owned_local,
+  /// See [`Archive::try_clone`] for more information.
+  try_clone_local,
   LocalDuplicable,
 }
 
+impl<R: AsyncRead + AsyncSeek + Clone + Unpin> Clone for Archive<R> {
+  fn clone(&self) -> Self {
+    Self {
+      offset: self.offset,
+      len: self.len,
+      header: self.header.clone(),
+      reader: self.reader.clone(),
+      on_access: self.on_access.clone(),
+      max_readahead: self.max_readahead,
+      pinned: self.pinned.clone(),
+      transforms: self.transforms.clone(),
+    }
+  }
+}
+
 cfg_fs! {
   impl<R: AsyncRead + AsyncSeek + Send + Unpin> Archive<R> {
     /// Extracts the archive to a folder.
+    ///
+    /// On Windows, paths are automatically converted to extended-length
+    /// paths, so that deep trees longer than 260 characters can be extracted.
+    /// Paths exceeding the platform's limits fail with
+    /// [`Error::PathTooLong`], and files whose content is cut short fail with
+    /// [`Error::Truncated`].
     pub async fn extract(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
-      let path = path.as_ref();
-      for (name, entry) in self.header.files.iter() {
-        crate::extract::extract_entry(&mut self.reader, self.offset, name, entry, path).await?;
-      }
-      Ok(())
+      self.extract_with_options(path, Default::default()).await
+    }
+
+    /// Extracts the archive to a folder, with options.
+    ///
+    /// See [`Archive::extract`] for more information.
+    pub async fn extract_with_options(
+      &mut self,
+      path: impl AsRef<Path>,
+      options: ExtractOptions,
+    ) -> io::Result<()> {
+      let path = extraction_root(path.as_ref())?;
+      let mut state = ExtractState::new(options, self.offset, self.max_readahead);
+      crate::extract::extract(&mut self.reader, &mut state, &self.header, &path).await
     }
   }
 
   impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
+    /// Lists directories and files that [`Archive::extract`] would create in
+    /// `path`, without extracting anything.
+    ///
+    /// Destinations are resolved exactly as extraction does, so paths that
+    /// fail during extraction (e.g. [`Error::PathTooLong`]) fail here as well.
+    /// Whether each destination already exists is reported, so that
+    /// installers can show a preview or ask for confirmation.
+    pub async fn extract_plan(
+      &self,
+      path: impl AsRef<Path>,
+      options: ExtractOptions,
+    ) -> io::Result<Vec<PlannedEntry>> {
+      crate::extract::plan(&self.header, &extraction_root(path.as_ref())?, options).await
+    }
+
     /// Extracts the archive to a folder.
     ///
     /// This method is intended for `R: !Send`. Otherwise, use
     /// [`Archive::extract`] instead.
     pub async fn extract_local(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
-      let path = path.as_ref();
-      for (name, entry) in self.header.files.iter() {
-        crate::extract::extract_entry_local(&mut self.reader, self.offset, name, entry, path).await?;
-      }
-      Ok(())
+      self.extract_local_with_options(path, Default::default()).await
+    }
+
+    /// Extracts the archive to a folder, with options.
+    ///
+    /// This method is intended for `R: !Send`. Otherwise, use
+    /// [`Archive::extract_with_options`] instead.
+    pub async fn extract_local_with_options(
+      &mut self,
+      path: impl AsRef<Path>,
+      options: ExtractOptions,
+    ) -> io::Result<()> {
+      let path = extraction_root(path.as_ref())?;
+      let mut state = ExtractState::new(options, self.offset, self.max_readahead);
+      crate::extract::extract(&mut self.reader, &mut state, &self.header, &path).await
+    }
+  }
+}
+
+/// View of a subtree of an [`Archive`], returned by [`Archive::scoped`].
+///
+/// Paths given to and returned by the view are relative to the subtree,
+/// except for [`File::path`], which is always the full path in the archive.
+#[derive(Debug)]
+pub struct ScopedArchive<'a, R: AsyncRead + AsyncSeek + Unpin> {
+  archive: &'a mut Archive<R>,
+  prefix: String,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> ScopedArchive<'_, R> {
+  /// Returns the normalized path of the subtree in the archive.
+  pub fn prefix(&self) -> &str {
+    &self.prefix
+  }
+
+  /// Returns the full path in the archive of `path` in the subtree.
+  pub fn full_path(&self, path: &str) -> String {
+    let path = normalize_path(path);
+    match (&*self.prefix, &*path) {
+      ("", _) => path,
+      (prefix, "") => prefix.into(),
+      (prefix, path) => format!("{prefix}/{path}"),
+    }
+  }
+
+  /// Returns the subtree's directory.
+  pub fn header(&self) -> &Directory {
+    match self.archive.get_entry(&self.prefix) {
+      _ if self.prefix.is_empty() => &self.archive.header,
+      Some(Entry::Directory(dir)) => dir,
+      _ => unreachable!("scoped directory should exist"),
+    }
+  }
+
+  /// Returns the entry at `path`.
+  ///
+  /// See [`Archive::get_entry`].
+  pub fn get_entry(&self, path: &str) -> Option<&Entry> {
+    self.header().search_segments(&split_path(path))
+  }
+
+  /// Returns a file in the subtree by taking mutable reference.
+  ///
+  /// See [`Archive::get`].
+  pub async fn get(&mut self, path: &str) -> io::Result<File<&mut R>> {
+    let path = self.full_path(path);
+    self.archive.get(&path).await
+  }
+
+  /// Reads entries of the directory at `path` in the subtree.
+  ///
+  /// See [`Archive::read_dir`].
+  pub fn read_dir(&self, path: &str) -> io::Result<AsarReadDir<'_>> {
+    let segments = split_path(path);
+    match self.header().search_segments(&segments) {
+      _ if segments.is_empty() => Ok(AsarReadDir::new(String::new(), self.header())),
+      Some(Entry::Directory(dir)) => Ok(AsarReadDir::new(segments.join("/"), dir)),
+      Some(Entry::File(_)) => Err(io::Error::from_raw_os_error(libc::ENOTDIR)),
+      None => Err(io::ErrorKind::NotFound.into()),
+    }
+  }
+
+  /// Lists every entry in the subtree.
+  ///
+  /// See [`Archive::listing`].
+  pub fn listing(&self, format: ListFormat) -> Listing {
+    Listing::new(self.header(), format)
+  }
+
+  /// Walks every entry in the subtree recursively.
+  ///
+  /// See [`Archive::walk_with`].
+  pub fn walk_with(&self, options: WalkOptions) -> Walk<'_> {
+    Walk::new(self.header(), options)
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Duplicable + Unpin> ScopedArchive<'_, R> {
+  /// Returns a file in the subtree with a duplicated reader.
+  ///
+  /// See [`Archive::get_owned`].
+  pub async fn get_owned(&self, path: &str) -> io::Result<File<R>> {
+    self.archive.get_owned(&self.full_path(path)).await
+  }
+}
+
+cfg_fs! {
+  impl<R: AsyncRead + AsyncSeek + Send + Unpin> ScopedArchive<'_, R> {
+    /// Extracts the subtree to a folder.
+    ///
+    /// See [`Archive::extract`].
+    pub async fn extract(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+      self.extract_with_options(path, Default::default()).await
+    }
+
+    /// Extracts the subtree to a folder, with options.
+    ///
+    /// See [`Archive::extract_with_options`].
+    pub async fn extract_with_options(
+      &mut self,
+      path: impl AsRef<Path>,
+      options: ExtractOptions,
+    ) -> io::Result<()> {
+      let path = extraction_root(path.as_ref())?;
+      let archive = &mut *self.archive;
+      let dir = match archive.header.search_segments(&split_path(&self.prefix)) {
+        _ if self.prefix.is_empty() => &archive.header,
+        Some(Entry::Directory(dir)) => dir,
+        _ => unreachable!("scoped directory should exist"),
+      };
+      let mut state = ExtractState::new(options, archive.offset, archive.max_readahead);
+      crate::extract::extract(&mut archive.reader, &mut state, dir, &path).await
+    }
+  }
+}
+
+/// Read-only asar archive handle that can be cheaply cloned and shared.
+///
+/// `SharedArchive` is an [`Arc`] over [`Archive`], dereferencing to it. Since
+/// [`Archive::get_owned`] only requires `&self`, every clone can read files
+/// concurrently without locking, which makes it suitable to be put directly
+/// into shared state of web frameworks like axum, instead of wrapping
+/// [`Archive`] in `Arc<Mutex<_>>` that serialises all reads.
+#[derive(Debug)]
+pub struct SharedArchive<R: AsyncRead + AsyncSeek + Unpin>(Arc<Archive<R>>);
+
+impl<R: AsyncRead + AsyncSeek + Unpin> SharedArchive<R> {
+  /// Parses an asar archive into `SharedArchive`.
+  pub async fn new(reader: R) -> io::Result<Self> {
+    Archive::new(reader).await.map(Self::from)
+  }
+
+  /// Returns the inner [`Archive`] if this is the only handle left.
+  ///
+  /// Otherwise, the handle is returned back as `Err`.
+  pub fn try_unwrap(self) -> Result<Archive<R>, Self> {
+    Arc::try_unwrap(self.0).map_err(Self)
+  }
+}
+
+cfg_fs! {
+  impl SharedArchive<DuplicableFile> {
+    /// Opens a file and parses it into [`SharedArchive`].
+    pub async fn new_from_file(path: impl Into<PathBuf>) -> io::Result<Self> {
+      Archive::new_from_file(path).await.map(Self::from)
     }
   }
 }
 
+impl<R: AsyncRead + AsyncSeek + Unpin> Clone for SharedArchive<R> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Deref for SharedArchive<R> {
+  type Target = Archive<R>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> From<Archive<R>> for SharedArchive<R> {
+  fn from(archive: Archive<R>) -> Self {
+    Self(Arc::new(archive))
+  }
+}
+
 /// File from an asar archive.
 #[pin_project]
 pub struct File<R: AsyncRead + AsyncSeek + Unpin> {
   pub(crate) offset: u64,
+  pub(crate) path: Arc<str>,
   pub(crate) metadata: FileMetadata,
   #[pin]
   pub(crate) content: Take<R>,
+  pub(crate) access: Option<AccessGuard>,
 }
 
 impl<R: AsyncRead + AsyncSeek + Unpin> File<R> {
@@ -221,46 +1452,237 @@ impl<R: AsyncRead + AsyncSeek + Unpin> File<R> {
     &self.metadata
   }
 
+  /// Gets the normalized full path of the file in the archive, separated by
+  /// `/` and without leading `/`.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Gets the path of the file's parent directory in the archive.
+  ///
+  /// Returns an empty string for files at the root of the archive.
+  pub fn parent(&self) -> &str {
+    self.path.rsplit_once('/').map_or("", |(parent, _)| parent)
+  }
+
+  /// Gets the name of the file, i.e. the last segment of its path.
+  pub fn file_name(&self) -> &str {
+    self
+      .path
+      .rsplit_once('/')
+      .map_or(&self.path, |(_, name)| name)
+  }
+
   cfg_integrity! {
+    /// Checks the file's content against its integrity information.
+    ///
+    /// Returns `true` if the content matches, or if there is no integrity
+    /// information at all. Use [`File::verify`] to tell these two apart.
     pub async fn check_integrity(&mut self) -> io::Result<bool> {
-      if let Some(integrity) = &self.metadata.integrity {
-        let block_size = integrity.block_size;
-        let mut block = Vec::with_capacity(block_size as _);
-        let mut global_state = Sha256::new();
-        let mut size = 0;
-
-        for block_hash in integrity.blocks.iter() {
-          let read_size = (&mut self.content)
-            .take(block_size as _)
-            .read_to_end(&mut block)
-            .await?;
-          if read_size == 0 || *Sha256::digest(&block) != **block_hash {
-            self.rewind().await?;
-            return Ok(false);
-          }
-          size += read_size;
-          global_state.update(&block);
-          block.clear();
-        }
-        if self.metadata.size != size as u64 || *global_state.finalize() != *integrity.hash {
+      Ok(!matches!(self.verify().await?, Verification::Mismatch { .. }))
+    }
+
+    /// Verifies the file's content against its integrity information.
+    ///
+    /// Unlike [`File::check_integrity`], a file without integrity information
+    /// is reported as [`Verification::NoIntegrityData`], so that callers can
+    /// enforce that every file must have integrity information.
+    ///
+    /// The file is read from the start, and rewinded afterwards.
+    pub async fn verify(&mut self) -> io::Result<Verification> {
+      if self.metadata.integrity.is_none() {
+        return Ok(Verification::NoIntegrityData);
+      }
+      self.rewind().await?;
+      let integrity = self.metadata.integrity.as_ref().unwrap();
+
+      let block_size = integrity.block_size;
+      let mut block = Vec::with_capacity(block_size as _);
+      let mut global_state = Sha256::new();
+      let mut size = 0;
+
+      for (index, block_hash) in integrity.blocks.iter().enumerate() {
+        let read_size = (&mut self.content)
+          .take(block_size as _)
+          .read_to_end(&mut block)
+          .await?;
+        let actual = Hash::Sha256(Sha256::digest(&block).into());
+        if read_size == 0 || actual != *block_hash {
+          let result = Verification::Mismatch {
+            block: Some(index),
+            expected: block_hash.clone(),
+            actual,
+          };
           self.rewind().await?;
-          return Ok(false);
+          return Ok(result);
+        }
+        size += read_size;
+        global_state.update(&block);
+        block.clear();
+      }
+      let actual = Hash::Sha256(global_state.finalize().into());
+      let result = if self.metadata.size != size as u64 || actual != integrity.hash {
+        Verification::Mismatch {
+          block: None,
+          expected: integrity.hash.clone(),
+          actual,
+        }
+      } else {
+        Verification::Verified
+      };
+
+      self.rewind().await?;
+      Ok(result)
+    }
+
+    /// Returns indices of every block whose content does not match its
+    /// integrity information.
+    ///
+    /// Unlike [`File::verify`], which stops at the first mismatch, this reads
+    /// the whole file, so that only damaged regions need to be repaired.
+    /// Blocks missing because the file is too short are reported as well.
+    /// Files without integrity information have no blocks to report.
+    ///
+    /// The file is read from the start, and rewinded afterwards.
+    pub async fn mismatched_blocks(&mut self) -> io::Result<Vec<usize>> {
+      let Some(integrity) = self.metadata.integrity.clone() else {
+        return Ok(Vec::new());
+      };
+      self.rewind().await?;
+
+      let mut block = Vec::with_capacity(integrity.block_size as _);
+      let mut mismatched = Vec::new();
+      for (index, block_hash) in integrity.blocks.iter().enumerate() {
+        let read_size = (&mut self.content)
+          .take(integrity.block_size as _)
+          .read_to_end(&mut block)
+          .await?;
+        if read_size == 0 || Hash::Sha256(Sha256::digest(&block).into()) != *block_hash {
+          mismatched.push(index);
+        }
+        block.clear();
+      }
+
+      self.rewind().await?;
+      Ok(mismatched)
+    }
+
+    /// Reads `range` of the file's content, verifying every block it touches
+    /// against its integrity information.
+    ///
+    /// This lets HTTP range requests be served with verified bytes, without
+    /// reading the whole file. Whole blocks covering the range are read, and
+    /// the range is clamped to the file's size. Fails with
+    /// [`Error::IntegrityMismatch`] on the first mismatched block, or with
+    /// [`InvalidData`](io::ErrorKind::InvalidData) if the file has no
+    /// integrity information.
+    ///
+    /// The file's position is left after the last block read.
+    pub async fn read_range_verified(&mut self, range: Range<u64>) -> io::Result<Vec<u8>> {
+      let Some(integrity) = self.metadata.integrity.clone() else {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("'{}' has no integrity information", self.path),
+        ));
+      };
+      integrity.validate_structure()?;
+      let (start, end) = (range.start.min(self.metadata.size), range.end.min(self.metadata.size));
+      let mut result = Vec::with_capacity(end.saturating_sub(start) as _);
+      if start >= end {
+        return Ok(result);
+      }
+
+      let block_size = u64::from(integrity.block_size);
+      let blocks = integrity.blocks_for_range(start..end);
+      self.seek(SeekFrom::Start(blocks.start as u64 * block_size)).await?;
+      let mut block = Vec::with_capacity(integrity.block_size as _);
+      for index in blocks {
+        let block_start = index as u64 * block_size;
+        let expected_len = block_size.min(self.metadata.size - block_start);
+        (&mut self.content)
+          .take(block_size)
+          .read_to_end(&mut block)
+          .await?;
+        let valid = block.len() as u64 == expected_len
+          && (integrity.blocks.get(index))
+            .is_some_and(|x| Hash::Sha256(Sha256::digest(&block).into()) == *x);
+        if !valid {
+          return Err(
+            Error::IntegrityMismatch {
+              path: (*self.path).into(),
+              block: Some(index),
+            }
+            .into(),
+          );
         }
+        let from = start.saturating_sub(block_start) as usize;
+        let to = (end - block_start).min(expected_len) as usize;
+        result.extend_from_slice(&block[from..to]);
+        block.clear();
+      }
+      Ok(result)
+    }
+  }
+}
 
-        self.rewind().await?;
+macro_rules! impl_try_clone {
+  (
+    $(#[$attr:ident $($args:tt)*])*
+    $try_clone:ident,
+    $duplicate:ident $(,)?
+  ) => {
+    impl<R: AsyncRead + AsyncSeek + $duplicate + Unpin> File<R> {
+      $(#[$attr $($args)*])*
+      pub async fn $try_clone(&self) -> io::Result<Self> {
+        let limit = self.content.limit();
+        let relative_pos = self.metadata.size - limit;
+        let mut reader = self.content.get_ref().duplicate().await?;
+        let seek_from = SeekFrom::Start(self.offset + self.metadata.offset()? + relative_pos);
+        reader.seek(seek_from).await?;
+        Ok(Self {
+          offset: self.offset,
+          path: self.path.clone(),
+          metadata: self.metadata.clone(),
+          content: reader.take(limit),
+          access: self.access.as_ref().map(|x| x.hook.start(&x.path)),
+        })
       }
-      Ok(true)
     }
   }
 }
 
+impl_try_clone! {
+  /// Creates a new handle of the file by duplicating the inner reader.
+  ///
+  /// The new handle shares the same metadata and starts at the same position,
+  /// but is otherwise independent of the original one. This allows handing a
+  /// resolved file to multiple consumers without resolving its path again.
+  try_clone,
+  Duplicable,
+}
+
+impl_try_clone! {
+  /// Creates a new handle of the file by duplicating the inner reader, without
+  /// `Sync`.
+  ///
+  /// See [`File::try_clone`] for more information.
+  try_clone_local,
+  LocalDuplicable,
+}
+
 impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for File<R> {
   fn poll_read(
     self: Pin<&mut Self>,
     cx: &mut Context<'_>,
     buf: &mut io::ReadBuf<'_>,
   ) -> Poll<io::Result<()>> {
-    self.project().content.poll_read(cx, buf)
+    let this = self.project();
+    let filled = buf.filled().len();
+    let result = this.content.poll_read(cx, buf);
+    if let Some(access) = this.access {
+      access.bytes_read += (buf.filled().len() - filled) as u64;
+    }
+    result.map(|x| x.with_entry(this.path))
   }
 }
 
@@ -300,6 +1722,141 @@ impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for File<R> {
   }
 }
 
+cfg_integrity! {
+  /// Result of [`File::verify`].
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  pub enum Verification {
+    /// The file's content matches its integrity information.
+    Verified,
+
+    /// The file has no integrity information.
+    NoIntegrityData,
+
+    /// The file's content does not match its integrity information.
+    Mismatch {
+      /// Index of the first mismatched block, or `None` if every block matches
+      /// but the file's size or whole-file hash does not.
+      block: Option<usize>,
+
+      /// Expected hash of the mismatched block, or of the whole file if
+      /// `block` is `None`.
+      expected: Hash,
+
+      /// Actual hash of the mismatched block or the whole file.
+      actual: Hash,
+    },
+  }
+
+  /// File from an asar archive whose content is verified while being read.
+  ///
+  /// Content is read one block at a time, and a block is only handed out
+  /// after it matches its hash, so no unverified byte is ever returned.
+  /// Reading fails with [`Error::IntegrityMismatch`] at the first mismatched
+  /// or missing block, and at the end of the file if the whole-file hash does
+  /// not match.
+  ///
+  /// At most one block is buffered, unlike checking the whole file with
+  /// [`File::verify`] before reading it.
+  pub struct VerifiedFile<R: AsyncRead + AsyncSeek + Unpin> {
+    file: File<R>,
+    integrity: Integrity,
+    digest: Sha256,
+    block: Vec<u8>,
+    index: usize,
+    filled: usize,
+    consumed: usize,
+    verified: bool,
+  }
+
+  impl<R: AsyncRead + AsyncSeek + Unpin> VerifiedFile<R> {
+    fn new(file: File<R>) -> io::Result<Self> {
+      let Some(integrity) = file.metadata.integrity.clone() else {
+        return Err(io::Error::new(
+          io::ErrorKind::InvalidData,
+          format!("'{}' has no integrity information", file.path),
+        ));
+      };
+      integrity.validate_structure()?;
+      Ok(Self {
+        file,
+        integrity,
+        digest: Sha256::new(),
+        block: Vec::new(),
+        index: 0,
+        filled: 0,
+        consumed: 0,
+        verified: true,
+      })
+    }
+
+    /// Gets the metadata of the file.
+    pub fn metadata(&self) -> &FileMetadata {
+      &self.file.metadata
+    }
+
+    /// Gets the normalized full path of the file in the archive.
+    pub fn path(&self) -> &str {
+      &self.file.path
+    }
+
+    fn mismatch(&self, block: Option<usize>) -> io::Error {
+      let path = (*self.file.path).into();
+      Error::IntegrityMismatch { path, block }.into()
+    }
+  }
+
+  impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for VerifiedFile<R> {
+    fn poll_read(
+      self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+      buf: &mut io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+      let this = self.get_mut();
+      let integrity = &this.integrity;
+      let block_size = u64::from(integrity.block_size);
+      loop {
+        if this.verified {
+          if this.consumed < this.block.len() {
+            let len = buf.remaining().min(this.block.len() - this.consumed);
+            buf.put_slice(&this.block[this.consumed..this.consumed + len]);
+            this.consumed += len;
+            return Poll::Ready(Ok(()));
+          }
+          let start = this.index as u64 * block_size;
+          if start >= this.file.metadata.size {
+            let actual = Hash::Sha256(this.digest.clone().finalize().into());
+            if this.index != integrity.blocks.len() || actual != integrity.hash {
+              return Poll::Ready(Err(this.mismatch(None)));
+            }
+            return Poll::Ready(Ok(()));
+          }
+          let len = block_size.min(this.file.metadata.size - start);
+          this.block.resize(len as _, 0);
+          (this.filled, this.consumed, this.verified) = (0, 0, false);
+        }
+
+        while this.filled < this.block.len() {
+          let mut block_buf = io::ReadBuf::new(&mut this.block[this.filled..]);
+          ready!(Pin::new(&mut this.file).poll_read(cx, &mut block_buf))?;
+          let len = block_buf.filled().len();
+          if len == 0 {
+            return Poll::Ready(Err(this.mismatch(Some(this.index))));
+          }
+          this.filled += len;
+        }
+        let valid = (integrity.blocks.get(this.index))
+          .is_some_and(|x| Hash::Sha256(Sha256::digest(&this.block).into()) == *x);
+        if !valid {
+          return Poll::Ready(Err(this.mismatch(Some(this.index))));
+        }
+        this.digest.update(&this.block);
+        this.index += 1;
+        this.verified = true;
+      }
+    }
+  }
+}
+
 /// Ability to duplicate asynchronously.
 ///
 /// [`Duplicable`] is like `Clone` with `async` and [`io::Result`]. However,
@@ -334,6 +1891,71 @@ impl<T: Clone> LocalDuplicable for Cursor<T> {
   }
 }
 
+/// Reader that implements [`Duplicable`] and [`LocalDuplicable`] by cloning.
+///
+/// Useful for readers with cheap clones, e.g. ones over `Arc`-backed memory.
+/// Each clone **must** have its own position, so that reading or seeking one
+/// does not affect the others.
+#[pin_project]
+#[derive(Debug, Clone, Default)]
+pub struct CloneDuplicable<T>(#[pin] T);
+
+impl<T> CloneDuplicable<T> {
+  /// Wraps `inner`.
+  pub fn new(inner: T) -> Self {
+    Self(inner)
+  }
+
+  /// Gets a reference to the underlying reader.
+  pub fn get_ref(&self) -> &T {
+    &self.0
+  }
+
+  /// Gets a mutable reference to the underlying reader.
+  pub fn get_mut(&mut self) -> &mut T {
+    &mut self.0
+  }
+
+  /// Unwraps the underlying reader.
+  pub fn into_inner(self) -> T {
+    self.0
+  }
+}
+
+impl<T: AsyncRead> AsyncRead for CloneDuplicable<T> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut io::ReadBuf<'_>,
+  ) -> Poll<std::io::Result<()>> {
+    self.project().0.poll_read(cx, buf)
+  }
+}
+
+impl<T: AsyncSeek> AsyncSeek for CloneDuplicable<T> {
+  fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+    self.project().0.start_seek(position)
+  }
+
+  fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+    self.project().0.poll_complete(cx)
+  }
+}
+
+#[async_trait]
+impl<T: Clone + Sync> Duplicable for CloneDuplicable<T> {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+}
+
+#[async_trait(?Send)]
+impl<T: Clone> LocalDuplicable for CloneDuplicable<T> {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+}
+
 cfg_fs! {
   /// [`TokioFile`] with path that implements [`Duplicable`].
   ///
@@ -342,27 +1964,82 @@ cfg_fs! {
   /// and thus cannot be [`Duplicable`]. `TokioFileWithPath`, however, opens a
   /// new file handle every time [`Duplicable::duplicate`] is called.
   #[pin_project]
+  #[derive(Debug)]
   pub struct DuplicableFile {
     #[pin]
     inner: TokioFile,
     path: PathBuf,
+    options: OpenOptions,
   }
 
   impl DuplicableFile {
+    /// Opens a file in read-only mode.
     pub async fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+      let mut options = OpenOptions::new();
+      options.read(true);
+      Self::open_with(path, options).await
+    }
+
+    /// Opens a file with custom [`OpenOptions`].
+    ///
+    /// The options are kept and used again for every
+    /// [`duplicate`](Duplicable::duplicate), so platform-specific settings
+    /// like Windows share mode or Linux `O_NOATIME`/`O_DIRECT` flags apply to
+    /// all handles.
+    ///
+    /// The options should not enable `truncate` or `create_new`, since the
+    /// file will be opened multiple times. `write` may be enabled to use
+    /// [`EditableArchive`](crate::EditableArchive).
+    pub async fn open_with(path: impl Into<PathBuf>, options: OpenOptions) -> io::Result<Self> {
       let path = path.into();
-      let inner = TokioFile::open(&path).await?;
-      Ok(Self { inner, path })
+      let inner = options.open(&path).await.with_path(&path)?;
+      Ok(Self {
+        inner,
+        path,
+        options,
+      })
     }
 
     pub async fn path(&self) -> &Path {
       &self.path
     }
 
+    /// Returns the options used to open the file.
+    pub fn options(&self) -> &OpenOptions {
+      &self.options
+    }
+
     pub fn into_inner(self) -> (TokioFile, PathBuf) {
       (self.inner, self.path)
     }
 
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    async fn prefetch(&self, start: u64, len: u64) -> io::Result<()> {
+      use std::os::unix::io::AsRawFd;
+      let fd = self.inner.as_raw_fd();
+      // SAFETY: `fd` is a valid file descriptor owned by `self.inner`.
+      let result = unsafe {
+        libc::posix_fadvise(
+          fd,
+          start as libc::off_t,
+          len as libc::off_t,
+          libc::POSIX_FADV_WILLNEED,
+        )
+      };
+      match result {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+      }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+    async fn prefetch(&self, start: u64, len: u64) -> io::Result<()> {
+      let mut file = Duplicable::duplicate(self).await?;
+      file.seek(SeekFrom::Start(start)).await?;
+      io::copy(&mut file.take(len), &mut io::sink()).await?;
+      Ok(())
+    }
+
     pub async fn rename(&mut self, new_path: impl Into<PathBuf>) -> io::Result<()> {
       let new_path = new_path.into();
       tokio::fs::rename(&self.path, &new_path).await?;
@@ -391,12 +2068,31 @@ cfg_fs! {
     }
   }
 
+  impl AsyncWrite for DuplicableFile {
+    fn poll_write(
+      self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+      buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+      self.project().inner.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+      self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+      self.project().inner.poll_shutdown(cx)
+    }
+  }
+
   #[async_trait]
   impl Duplicable for DuplicableFile {
     async fn duplicate(&self) -> io::Result<Self> {
       Ok(Self {
-        inner: TokioFile::open(&self.path).await?,
+        inner: self.options.open(&self.path).await.with_path(&self.path)?,
         path: self.path.clone(),
+        options: self.options.clone(),
       })
     }
   }
@@ -405,8 +2101,9 @@ cfg_fs! {
   impl LocalDuplicable for DuplicableFile {
     async fn duplicate(&self) -> io::Result<Self> {
       Ok(Self {
-        inner: TokioFile::open(&self.path).await?,
+        inner: self.options.open(&self.path).await.with_path(&self.path)?,
         path: self.path.clone(),
+        options: self.options.clone(),
       })
     }
   }
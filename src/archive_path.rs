@@ -0,0 +1,283 @@
+use crate::{normalize_path, Error};
+use std::borrow::{Borrow, Cow};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::Deref;
+use tokio::io;
+
+/// A normalized path inside an archive, like [`Path`](std::path::Path) for
+/// the file system.
+///
+/// Segments are separated by `/`, with no empty, `.` or `..` segments, and no
+/// leading or trailing `/`; see [`normalize_path`]. The empty path refers to
+/// the root directory.
+///
+/// APIs looking up or adding entries, e.g. [`Archive::get`](crate::Archive::get)
+/// and [`Writer::add`](crate::Writer::add), take [`AsArchivePath`], and use
+/// an `ArchivePath` as is instead of normalizing it again. It also
+/// dereferences to `str` for every other API taking a path string.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct ArchivePath(str);
+
+impl ArchivePath {
+  /// Checks that `path` is normalized.
+  ///
+  /// Fails with [`Error::InvalidPath`] otherwise.
+  pub fn new(path: &str) -> io::Result<&Self> {
+    if is_normalized(path) {
+      Ok(Self::new_unchecked(path))
+    } else {
+      Err(invalid(path))
+    }
+  }
+
+  fn new_unchecked(path: &str) -> &Self {
+    // Safety: `ArchivePath` is a `repr(transparent)` wrapper of `str`.
+    unsafe { &*(path as *const str as *const Self) }
+  }
+
+  /// Returns the path as a string.
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// Whether this is the root directory.
+  pub fn is_root(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Iterates over the path's segments.
+  pub fn segments(&self) -> impl DoubleEndedIterator<Item = &str> {
+    self.0.split('/').filter(|x| !x.is_empty())
+  }
+
+  /// Returns the last segment, or `None` for the root directory.
+  pub fn file_name(&self) -> Option<&str> {
+    self.segments().next_back()
+  }
+
+  /// Returns the parent directory, or `None` for the root directory.
+  pub fn parent(&self) -> Option<&Self> {
+    if self.is_root() {
+      return None;
+    }
+    let parent = self.0.rfind('/').map_or("", |i| &self.0[..i]);
+    Some(Self::new_unchecked(parent))
+  }
+
+  /// Appends `path` to this one, normalizing the result.
+  ///
+  /// `..` segments in `path` may remove segments of this path, but never go
+  /// above the root.
+  pub fn join(&self, path: &str) -> ArchivePathBuf {
+    ArchivePathBuf::normalize(&format!("{}/{path}", &self.0))
+  }
+}
+
+impl Deref for ArchivePath {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl AsRef<str> for ArchivePath {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl ToOwned for ArchivePath {
+  type Owned = ArchivePathBuf;
+
+  fn to_owned(&self) -> ArchivePathBuf {
+    ArchivePathBuf(self.0.to_owned())
+  }
+}
+
+impl Debug for ArchivePath {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    Debug::fmt(&self.0, f)
+  }
+}
+
+impl Display for ArchivePath {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+/// An owned, normalized path inside an archive.
+///
+/// See [`ArchivePath`].
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ArchivePathBuf(String);
+
+impl ArchivePathBuf {
+  /// Normalizes `path` with [`normalize_path`].
+  pub fn normalize(path: &str) -> Self {
+    Self(normalize_path(path))
+  }
+
+  /// Checks that `path` is normalized.
+  ///
+  /// Fails with [`Error::InvalidPath`] otherwise.
+  pub fn new(path: String) -> io::Result<Self> {
+    if is_normalized(&path) {
+      Ok(Self(path))
+    } else {
+      Err(invalid(&path))
+    }
+  }
+
+  /// Appends `path`, normalizing the result.
+  ///
+  /// See [`ArchivePath::join`].
+  pub fn push(&mut self, path: &str) {
+    *self = self.join(path);
+  }
+
+  /// Unwraps the path string.
+  pub fn into_string(self) -> String {
+    self.0
+  }
+}
+
+impl Deref for ArchivePathBuf {
+  type Target = ArchivePath;
+
+  fn deref(&self) -> &ArchivePath {
+    ArchivePath::new_unchecked(&self.0)
+  }
+}
+
+impl Borrow<ArchivePath> for ArchivePathBuf {
+  fn borrow(&self) -> &ArchivePath {
+    self
+  }
+}
+
+impl AsRef<ArchivePath> for ArchivePathBuf {
+  fn as_ref(&self) -> &ArchivePath {
+    self
+  }
+}
+
+impl AsRef<str> for ArchivePathBuf {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl From<&ArchivePath> for ArchivePathBuf {
+  fn from(path: &ArchivePath) -> Self {
+    path.to_owned()
+  }
+}
+
+impl From<ArchivePathBuf> for String {
+  fn from(path: ArchivePathBuf) -> Self {
+    path.0
+  }
+}
+
+impl Debug for ArchivePathBuf {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    Debug::fmt(&self.0, f)
+  }
+}
+
+impl Display for ArchivePathBuf {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+/// A path to an entry in an archive.
+///
+/// Implemented for strings, which are normalized with [`normalize_path`]
+/// unless they already are, and for [`ArchivePath`] and [`ArchivePathBuf`],
+/// which are used without any check.
+pub trait AsArchivePath {
+  /// Returns the path as given.
+  fn as_path_str(&self) -> &str;
+
+  /// Returns the normalized path, borrowing it if possible.
+  fn to_archive_path(&self) -> Cow<'_, ArchivePath> {
+    let path = self.as_path_str();
+    match is_normalized(path) {
+      true => Cow::Borrowed(ArchivePath::new_unchecked(path)),
+      false => Cow::Owned(ArchivePathBuf::normalize(path)),
+    }
+  }
+}
+
+impl AsArchivePath for str {
+  fn as_path_str(&self) -> &str {
+    self
+  }
+}
+
+impl AsArchivePath for String {
+  fn as_path_str(&self) -> &str {
+    self
+  }
+}
+
+impl AsArchivePath for Box<str> {
+  fn as_path_str(&self) -> &str {
+    self
+  }
+}
+
+impl AsArchivePath for Cow<'_, str> {
+  fn as_path_str(&self) -> &str {
+    self
+  }
+}
+
+impl AsArchivePath for ArchivePath {
+  fn as_path_str(&self) -> &str {
+    &self.0
+  }
+
+  fn to_archive_path(&self) -> Cow<'_, ArchivePath> {
+    Cow::Borrowed(self)
+  }
+}
+
+impl AsArchivePath for ArchivePathBuf {
+  fn as_path_str(&self) -> &str {
+    &self.0
+  }
+
+  fn to_archive_path(&self) -> Cow<'_, ArchivePath> {
+    Cow::Borrowed(self)
+  }
+}
+
+impl<T: AsArchivePath + ?Sized> AsArchivePath for &T {
+  fn as_path_str(&self) -> &str {
+    (**self).as_path_str()
+  }
+
+  fn to_archive_path(&self) -> Cow<'_, ArchivePath> {
+    (**self).to_archive_path()
+  }
+}
+
+pub(crate) fn is_normalized(path: &str) -> bool {
+  path.is_empty()
+    || path
+      .split('/')
+      .all(|x| !x.is_empty() && x != "." && x != "..")
+}
+
+fn invalid(path: &str) -> io::Error {
+  Error::InvalidPath {
+    path: path.into(),
+    normalized: normalize_path(path).into(),
+  }
+  .into()
+}
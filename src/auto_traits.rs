@@ -0,0 +1,92 @@
+//! Compile-time assertions of auto traits.
+//!
+//! Public types are commonly stored in shared state of web frameworks (e.g.
+//! axum's `State`), which requires them to be `Send + Sync`, and their futures
+//! to be `Send`. Nothing here is ever called; it only has to compile in every
+//! feature combination.
+
+#![allow(dead_code)]
+
+use crate::{
+  Archive, CloneDuplicable, ConcurrentWriter, Duplicable, File, SharedArchive, WritePlan, Writer,
+};
+use std::io::Cursor;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite};
+
+fn send_sync<T: Send + Sync>() {}
+
+fn send<T: Send>(_: &T) {}
+
+fn types() {
+  send_sync::<Archive<Cursor<Vec<u8>>>>();
+  send_sync::<Archive<Cursor<&'static [u8]>>>();
+  send_sync::<SharedArchive<Cursor<Vec<u8>>>>();
+  send_sync::<SharedArchive<CloneDuplicable<Cursor<std::sync::Arc<[u8]>>>>>();
+  send_sync::<File<Cursor<Vec<u8>>>>();
+  send_sync::<crate::FileMut<Cursor<Vec<u8>>>>();
+  send_sync::<crate::EditableArchive<Cursor<Vec<u8>>>>();
+  send_sync::<Writer<Cursor<Vec<u8>>>>();
+  send_sync::<ConcurrentWriter<Cursor<Vec<u8>>>>();
+  send_sync::<WritePlan>();
+  send_sync::<crate::MountTable<CloneDuplicable<Cursor<std::sync::Arc<[u8]>>>>>();
+  send_sync::<crate::Error>();
+  #[cfg(feature = "integrity")]
+  send_sync::<crate::VerifiedFile<Cursor<Vec<u8>>>>();
+  #[cfg(feature = "stream")]
+  send_sync::<SharedArchive<crate::SegmentedReader>>();
+}
+
+/// Read paths taking `&self` only need `R: Sync` for their futures to be
+/// `Send`, and must not require `&mut` anywhere.
+fn shared_futures<R>(archive: &Archive<R>, shared: &SharedArchive<R>)
+where
+  R: AsyncRead + AsyncSeek + Duplicable + Send + Sync + Unpin,
+{
+  send(&archive.get_owned(""));
+  send(&archive.try_clone());
+  send(&archive.verify_isolation());
+  send(&shared.get_owned(""));
+}
+
+fn owned_futures<R>(archive: &mut Archive<R>, file: &mut File<R>)
+where
+  R: AsyncRead + AsyncSeek + Send + Unpin,
+{
+  send(&archive.get(""));
+  send(&archive.pin([""]));
+  #[cfg(feature = "integrity")]
+  send(&file.check_integrity());
+  let _ = file;
+}
+
+fn writer_futures<F, W>(writer: Writer<F>, dest: &mut W)
+where
+  F: AsyncRead + Send + Unpin,
+  W: AsyncWrite + Send + Unpin,
+{
+  send(&writer.write(dest));
+}
+
+#[cfg(feature = "fs")]
+fn fs() {
+  use crate::DuplicableFile;
+  use tokio::fs::File as TokioFile;
+
+  send_sync::<DuplicableFile>();
+  send_sync::<Archive<DuplicableFile>>();
+  send_sync::<SharedArchive<DuplicableFile>>();
+  send_sync::<File<DuplicableFile>>();
+  send_sync::<Writer<TokioFile>>();
+}
+
+#[cfg(feature = "fs")]
+fn fs_futures(
+  archive: &mut Archive<crate::DuplicableFile>,
+  writer: Writer<tokio::fs::File>,
+  dest: &mut tokio::fs::File,
+) {
+  send(&archive.prefetch([""]));
+  send(&archive.extract(""));
+  send(&writer.write_file(""));
+  send(&crate::pack_dir("", dest));
+}
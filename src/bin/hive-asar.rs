@@ -0,0 +1,88 @@
+use hive_asar::list::ListFormat;
+use hive_asar::Archive;
+use std::process::exit;
+use tokio::io;
+
+const USAGE: &str = "\
+usage: hive-asar <command> [options]
+
+commands:
+  list [-l] [-h] [--json] <archive>
+      List entries of the archive.
+      -l      long format, with flags and sizes
+      -h      human-readable sizes (implies -l)
+      --json  machine-readable JSON output
+  info [--json] <archive>
+      Summarize the archive.
+      --json  machine-readable JSON output";
+
+async fn list(args: &[String]) -> io::Result<()> {
+  let mut long = false;
+  let mut human_readable = false;
+  let mut json = false;
+  let mut path = None;
+  for arg in args {
+    match &**arg {
+      "-l" => long = true,
+      "-h" => human_readable = true,
+      "-lh" | "-hl" => (long, human_readable) = (true, true),
+      "--json" => json = true,
+      _ if arg.starts_with('-') => usage(),
+      _ if path.is_none() => path = Some(arg),
+      _ => usage(),
+    }
+  }
+  let path = path.unwrap_or_else(|| usage());
+  let format = if json {
+    ListFormat::Json
+  } else if long || human_readable {
+    ListFormat::Long { human_readable }
+  } else {
+    ListFormat::Short
+  };
+
+  let archive = Archive::new_from_file(path).await?;
+  print!("{}", archive.listing(format));
+  Ok(())
+}
+
+async fn info(args: &[String]) -> io::Result<()> {
+  let mut json = false;
+  let mut path = None;
+  for arg in args {
+    match &**arg {
+      "--json" => json = true,
+      _ if arg.starts_with('-') => usage(),
+      _ if path.is_none() => path = Some(arg),
+      _ => usage(),
+    }
+  }
+  let path = path.unwrap_or_else(|| usage());
+
+  let info = Archive::new_from_file(path).await?.info();
+  if json {
+    println!("{}", serde_json::to_string(&info)?);
+  } else {
+    println!("{info}");
+  }
+  Ok(())
+}
+
+fn usage() -> ! {
+  eprintln!("{USAGE}");
+  exit(2)
+}
+
+#[tokio::main]
+async fn main() {
+  let args: Vec<_> = std::env::args().skip(1).collect();
+  let result = match args.first().map(|x| &**x) {
+    Some("list") => list(&args[1..]).await,
+    Some("info") => info(&args[1..]).await,
+    _ => usage(),
+  };
+  if let Err(error) = result {
+    eprintln!("hive-asar: {error}");
+    exit(1);
+  }
+}
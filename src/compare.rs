@@ -0,0 +1,147 @@
+//! Comparison of two archives.
+
+use crate::header::{Directory, Entry, FileMetadata, FilePosition, Integrity};
+use crate::Archive;
+use std::collections::BTreeMap;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek};
+
+/// Size of chunks compared in [`CompareMode::FullBytes`].
+const CHUNK_SIZE: u64 = 65536;
+
+/// How thoroughly [`compare`] checks two archives.
+///
+/// Every mode requires the same directories and files at the same paths,
+/// with the same sizes and executable flags, and stored either both in the
+/// archive or both unpacked. Offsets are never compared, so archives with
+/// files in different orders can be equivalent. Content of unpacked files is
+/// not in the archives, and is never compared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompareMode {
+  /// Compares headers only: integrity information, permission bits and
+  /// extension fields must also be the same. Nothing else is read.
+  #[default]
+  HeaderOnly,
+
+  /// Compares content by the whole-file hashes in integrity information.
+  /// Files without integrity information on either side are compared byte
+  /// by byte instead.
+  Hashes,
+
+  /// Compares content byte by byte.
+  FullBytes,
+}
+
+/// Checks whether archives `a` and `b` are equivalent under `mode`.
+///
+/// Useful in tests, or for deciding whether a rebuilt archive needs to be
+/// uploaded again. Fails only if reading an archive fails, e.g. if one is
+/// truncated.
+pub async fn compare<A, B>(
+  a: &mut Archive<A>,
+  b: &mut Archive<B>,
+  mode: CompareMode,
+) -> io::Result<bool>
+where
+  A: AsyncRead + AsyncSeek + Unpin,
+  B: AsyncRead + AsyncSeek + Unpin,
+{
+  let (entries_a, entries_b) = (entries(a), entries(b));
+  if entries_a.len() != entries_b.len() {
+    return Ok(false);
+  }
+  let mut to_read = Vec::new();
+  for ((path_a, entry_a), (path_b, entry_b)) in entries_a.iter().zip(&entries_b) {
+    if path_a != path_b {
+      return Ok(false);
+    }
+    let (file_a, file_b) = match (entry_a, entry_b) {
+      (Entry::Directory(_), Entry::Directory(_)) => continue,
+      (Entry::File(file_a), Entry::File(file_b)) => (file_a, file_b),
+      _ => return Ok(false),
+    };
+    if !same_file(file_a, file_b) {
+      return Ok(false);
+    }
+    if let FilePosition::Unpacked = file_a.pos {
+      continue;
+    }
+    match (mode, &file_a.integrity, &file_b.integrity) {
+      (CompareMode::HeaderOnly, ..) => {
+        let same = same_integrity(file_a.integrity.as_ref(), file_b.integrity.as_ref())
+          && file_a.mode == file_b.mode
+          && file_a.attributes == file_b.attributes;
+        if !same {
+          return Ok(false);
+        }
+      }
+      (CompareMode::Hashes, Some(x), Some(y)) if x.algorithm.name() == y.algorithm.name() => {
+        if x.hash != y.hash {
+          return Ok(false);
+        }
+      }
+      _ => to_read.push((*path_a).to_owned()),
+    }
+  }
+
+  let (mut buf_a, mut buf_b) = (Vec::new(), Vec::new());
+  for path in to_read {
+    let (mut file_a, mut file_b) = (a.get(&path).await?, b.get(&path).await?);
+    loop {
+      buf_a.clear();
+      buf_b.clear();
+      let len = (&mut file_a)
+        .take(CHUNK_SIZE)
+        .read_to_end(&mut buf_a)
+        .await?;
+      (&mut file_b)
+        .take(CHUNK_SIZE)
+        .read_to_end(&mut buf_b)
+        .await?;
+      if buf_a != buf_b {
+        return Ok(false);
+      }
+      if len == 0 {
+        break;
+      }
+    }
+  }
+  Ok(true)
+}
+
+/// Collects every entry of the archive, keyed by full path.
+fn entries<R: AsyncRead + AsyncSeek + Unpin>(archive: &Archive<R>) -> BTreeMap<String, &Entry> {
+  fn collect<'a>(dir: &'a Directory, prefix: &str, entries: &mut BTreeMap<String, &'a Entry>) {
+    for (name, entry) in dir.files.iter() {
+      let path = match prefix {
+        "" => name.to_string(),
+        _ => format!("{prefix}/{name}"),
+      };
+      if let Entry::Directory(dir) = entry {
+        collect(dir, &path, entries);
+      }
+      entries.insert(path, entry);
+    }
+  }
+
+  let mut entries = BTreeMap::new();
+  collect(archive.header(), "", &mut entries);
+  entries
+}
+
+fn same_file(a: &FileMetadata, b: &FileMetadata) -> bool {
+  let unpacked = |x: &FileMetadata| matches!(x.pos, FilePosition::Unpacked);
+  a.size == b.size && a.executable == b.executable && unpacked(a) == unpacked(b)
+}
+
+fn same_integrity(a: Option<&Integrity>, b: Option<&Integrity>) -> bool {
+  match (a, b) {
+    (None, None) => true,
+    (Some(a), Some(b)) => {
+      a.algorithm.name() == b.algorithm.name()
+        && a.hash == b.hash
+        && a.block_size == b.block_size
+        && a.blocks == b.blocks
+    }
+    _ => false,
+  }
+}
@@ -0,0 +1,414 @@
+//! In-place editing of archives.
+
+use crate::format::{encode_prefix, PREFIX_LEN};
+use crate::header::{Entry, FileMetadata, SerializeOptions};
+use crate::{cfg_fs, cfg_integrity, split_path, Archive};
+use std::io::SeekFrom;
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::{
+  self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+};
+
+cfg_fs! {
+  use crate::DuplicableFile;
+  use std::path::PathBuf;
+  use tokio::fs::OpenOptions;
+}
+
+cfg_integrity! {
+  use crate::header::Hash;
+  use sha2::digest::Digest;
+  use sha2::Sha256;
+}
+
+/// Size of chunks written by [`EditableArchive::overwrite`] for files without
+/// integrity information.
+const DEFAULT_OVERWRITE_BLOCK_SIZE: usize = 65536;
+
+/// A change of an entry in the header made by [`EditableArchive`].
+#[derive(Debug, Clone)]
+pub struct HeaderChange {
+  /// Normalized path of the entry.
+  pub path: String,
+
+  /// The entry before the change.
+  pub before: Entry,
+
+  /// The entry after the change, or `None` if it was removed.
+  pub after: Option<Entry>,
+}
+
+/// Archive opened for editing in place.
+///
+/// Content is patched right away, without moving any file. Header changes,
+/// i.e. removed entries and recomputed integrity information, are only made
+/// in memory, and written into the original header region by
+/// [`EditableArchive::commit`], so several edits cost a single header write.
+/// Dropping the archive without committing discards them, but not the
+/// content already written.
+///
+/// Every removal and overwrite is recorded as a [`HeaderChange`], and the
+/// journal of committed changes is returned by `commit`, e.g. for undo or
+/// audit logs in editors.
+///
+/// Entries are read through [`Deref`] to the wrapped [`Archive`], and see
+/// uncommitted changes.
+///
+/// ```
+/// # async fn f() -> std::io::Result<()> {
+/// use hive_asar::EditableArchive;
+///
+/// let mut archive = EditableArchive::open("app.asar").await?;
+/// archive.remove("secrets.json").await?;
+/// archive.commit().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct EditableArchive<R: AsyncRead + AsyncWrite + AsyncSeek + Unpin> {
+  archive: Archive<R>,
+  /// Changes since the last commit.
+  journal: Vec<HeaderChange>,
+  /// Whether the header needs to be rewritten by the next commit.
+  dirty: bool,
+}
+
+impl<R: AsyncRead + AsyncWrite + AsyncSeek + Unpin> EditableArchive<R> {
+  /// Parses an archive from a reader that can also be written, for editing.
+  pub async fn new(reader: R) -> io::Result<Self> {
+    Ok(Archive::new(reader).await?.into())
+  }
+
+  /// Returns the wrapped archive, with uncommitted header changes made in
+  /// memory only.
+  pub fn into_inner(self) -> Archive<R> {
+    self.archive
+  }
+
+  /// Returns file `path` for patching its content in place.
+  ///
+  /// See [`FileMut`] for more information. Integrity information is not
+  /// updated; use [`EditableArchive::overwrite`] to replace the whole content
+  /// while keeping it valid.
+  pub async fn get_mut(&mut self, path: &str) -> io::Result<FileMut<&mut R>> {
+    let archive = &mut self.archive;
+    let segments = split_path(path);
+    let path: Arc<str> = segments.join("/").into();
+    archive.pinned.remove(&*path);
+    let metadata = match archive.header.search_segments(&segments) {
+      Some(Entry::File(metadata)) => metadata,
+      Some(Entry::Directory(_)) => return Err(io::Error::from_raw_os_error(libc::EISDIR)),
+      None => return Err(io::ErrorKind::NotFound.into()),
+    };
+    archive.check_truncated(&path, metadata)?;
+    let start = archive.offset + metadata.offset()?;
+    archive.reader.seek(SeekFrom::Start(start)).await?;
+    Ok(FileMut {
+      start,
+      pos: 0,
+      path,
+      metadata: metadata.clone(),
+      inner: &mut archive.reader,
+    })
+  }
+
+  /// Overwrites the content of file `path` in place with `content`.
+  ///
+  /// `content` must provide exactly as many bytes as the file's original
+  /// size, so that no other file needs to be moved. If the file has
+  /// integrity information, it is recomputed with the same block size, to be
+  /// written by [`EditableArchive::commit`]; this fails before any content is
+  /// written if the header would no longer fit in its region. Without
+  /// `integrity` feature, integrity information is left unchanged. The
+  /// overwrite is recorded as a [`HeaderChange`] either way, with the same
+  /// entry before and after if the header did not change.
+  ///
+  /// The size of `content` can only be checked while writing. If it is
+  /// shorter or longer, an error is returned, and the file is left partially
+  /// overwritten.
+  pub async fn overwrite(&mut self, path: &str, content: impl AsyncRead + Unpin) -> io::Result<()> {
+    let archive = &mut self.archive;
+    let segments = split_path(path);
+    archive.pinned.remove(&*segments.join("/"));
+    let metadata = match archive.header.search_segments(&segments) {
+      Some(Entry::File(metadata)) => metadata,
+      Some(Entry::Directory(_)) => return Err(io::Error::from_raw_os_error(libc::EISDIR)),
+      None => return Err(io::ErrorKind::NotFound.into()),
+    };
+    archive.check_truncated(path, metadata)?;
+    let before = Entry::File(metadata.clone());
+    let start = archive.offset + metadata.offset()?;
+    let size = metadata.size;
+    let has_integrity = metadata.integrity.is_some();
+    if has_integrity {
+      header_fit(archive)?;
+    }
+    let block_size =
+      (metadata.integrity.as_ref()).map_or(DEFAULT_OVERWRITE_BLOCK_SIZE, |x| x.block_size as usize);
+
+    archive.reader.seek(SeekFrom::Start(start)).await?;
+    let mut content = content.take(size);
+    let mut buf = Vec::with_capacity(block_size);
+    let mut written = 0;
+    #[cfg(feature = "integrity")]
+    let (mut global_state, mut blocks) = (Sha256::new(), Vec::new());
+    loop {
+      let len = (&mut content)
+        .take(block_size as _)
+        .read_to_end(&mut buf)
+        .await?;
+      if len == 0 {
+        break;
+      }
+      archive.reader.write_all(&buf).await?;
+      written += len as u64;
+      #[cfg(feature = "integrity")]
+      if has_integrity {
+        blocks.push(Hash::Sha256(Sha256::digest(&buf).into()));
+        global_state.update(&buf);
+      }
+      buf.clear();
+    }
+    let extra = content.into_inner().read(&mut [0]).await?;
+    if written != size || extra != 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("content of '{path}' does not match its size {size}"),
+      ));
+    }
+
+    let metadata = (archive.header)
+      .search_segments_mut(&segments)
+      .expect("file should exist in header");
+    #[cfg(feature = "integrity")]
+    if let Some(integrity) = metadata.integrity.as_mut() {
+      integrity.hash = global_state.finalize().to_vec().into();
+      integrity.blocks = Arc::new(blocks);
+      self.dirty = true;
+    }
+    self.journal.push(HeaderChange {
+      path: segments.join("/"),
+      before,
+      after: Some(Entry::File(metadata.clone())),
+    });
+    archive.reader.flush().await
+  }
+
+  /// Removes entry `path` from the archive's header.
+  ///
+  /// Removing a directory removes everything inside. Content of removed files
+  /// is left in place as dead bytes, and no other file is moved, which makes
+  /// this a fast way to strip entries (e.g. secrets) from an archive without
+  /// repacking. Note that the removed content can still be recovered from the
+  /// archive; repack it with [`Writer`](crate::Writer) if that matters.
+  ///
+  /// Since the shortened header is padded to its original region, this only
+  /// fails if the header cannot be re-serialized within it, in which case
+  /// the entry is kept.
+  pub async fn remove(&mut self, path: &str) -> io::Result<Entry> {
+    let archive = &mut self.archive;
+    let segments = split_path(path);
+    if segments.is_empty() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "cannot remove the root directory",
+      ));
+    }
+    let entry = (archive.header)
+      .remove_segments(&segments)
+      .ok_or(io::ErrorKind::NotFound)?;
+    if let Err(error) = header_fit(archive) {
+      let (name, parents) = segments.split_last().unwrap();
+      let parent = (archive.header.search_dir_mut(parents)).expect("parent should exist in header");
+      parent.files.insert((*name).into(), entry);
+      return Err(error);
+    }
+    let prefix = segments.join("/");
+    (archive.pinned).retain(|path, _| {
+      path
+        .strip_prefix(&*prefix)
+        .is_none_or(|rest| !rest.is_empty() && !rest.starts_with('/'))
+    });
+    self.journal.push(HeaderChange {
+      path: prefix,
+      before: entry.clone(),
+      after: None,
+    });
+    self.dirty = true;
+    Ok(entry)
+  }
+
+  /// Returns changes made since the last commit, oldest first.
+  pub fn pending_changes(&self) -> &[HeaderChange] {
+    &self.journal
+  }
+
+  /// Writes header changes made since the last commit into the original
+  /// header region, padding it with trailing whitespace if it became
+  /// shorter, so that content stays in place.
+  ///
+  /// Returns the committed changes, oldest first. If writing fails, they are
+  /// kept pending.
+  pub async fn commit(&mut self) -> io::Result<Vec<HeaderChange>> {
+    if self.dirty {
+      let archive = &mut self.archive;
+      let options = header_fit(archive)?;
+      let mut header_bytes = serde_json::to_vec(&archive.header.serialize_with(options))?;
+      let region = (archive.offset - PREFIX_LEN as u64) as usize;
+      header_bytes.resize(region, b' ');
+      let header_len = header_bytes.len() as u32;
+      archive.reader.seek(SeekFrom::Start(0)).await?;
+      archive.reader.write_all(&encode_prefix(header_len)).await?;
+      archive.reader.write_all(&header_bytes).await?;
+      self.dirty = false;
+    }
+    self.archive.reader.flush().await?;
+    Ok(std::mem::take(&mut self.journal))
+  }
+}
+
+cfg_fs! {
+  impl EditableArchive<DuplicableFile> {
+    /// Opens a file for reading and writing, and parses it for editing.
+    pub async fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+      let mut options = OpenOptions::new();
+      options.read(true).write(true);
+      Self::new(DuplicableFile::open_with(path, options).await?).await
+    }
+  }
+}
+
+impl<R: AsyncRead + AsyncWrite + AsyncSeek + Unpin> From<Archive<R>> for EditableArchive<R> {
+  fn from(archive: Archive<R>) -> Self {
+    Self {
+      archive,
+      journal: Vec::new(),
+      dirty: false,
+    }
+  }
+}
+
+impl<R: AsyncRead + AsyncWrite + AsyncSeek + Unpin> Deref for EditableArchive<R> {
+  type Target = Archive<R>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.archive
+  }
+}
+
+impl<R: AsyncRead + AsyncWrite + AsyncSeek + Unpin> DerefMut for EditableArchive<R> {
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.archive
+  }
+}
+
+/// Finds serialization options with which the header fits in the original
+/// header region.
+fn header_fit<R: AsyncRead + AsyncSeek + Unpin>(
+  archive: &Archive<R>,
+) -> io::Result<SerializeOptions> {
+  let region = archive.offset - PREFIX_LEN as u64;
+  for skip_none_integrity in [false, true] {
+    let options = SerializeOptions {
+      skip_none_integrity,
+      ..Default::default()
+    };
+    let len = archive.header.serialized_len(options)?;
+    if len <= region {
+      return Ok(options);
+    }
+  }
+  Err(io::Error::new(
+    io::ErrorKind::Unsupported,
+    "header cannot be rewritten in place",
+  ))
+}
+
+/// File from a writable archive, returned by [`EditableArchive::get_mut`],
+/// whose content can be overwritten in place.
+///
+/// Writes and seeks are bounded to the file's content: seeking past either
+/// end stops there, and writing past the file's size fails with
+/// [`FileTooLarge`](io::ErrorKind::FileTooLarge), writing as much as fits
+/// first. No other file or the header is ever touched, so the archive stays
+/// well-formed, although integrity information of the file no longer
+/// matches if it is changed.
+#[derive(Debug)]
+pub struct FileMut<R: AsyncWrite + AsyncSeek + Unpin> {
+  /// Offset of the file's content from the start of the archive.
+  start: u64,
+  /// Position relative to the file's start.
+  pos: u64,
+  path: Arc<str>,
+  metadata: FileMetadata,
+  inner: R,
+}
+
+impl<R: AsyncWrite + AsyncSeek + Unpin> FileMut<R> {
+  /// Gets the metadata of the file.
+  pub fn metadata(&self) -> &FileMetadata {
+    &self.metadata
+  }
+
+  /// Returns the normalized path of the file in the archive.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns the current position relative to the file's start.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+}
+
+impl<R: AsyncWrite + AsyncSeek + Unpin> AsyncWrite for FileMut<R> {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    let this = self.get_mut();
+    let remaining = this.metadata.size - this.pos;
+    if buf.is_empty() {
+      return Poll::Ready(Ok(0));
+    }
+    if remaining == 0 {
+      return Poll::Ready(Err(io::Error::new(
+        io::ErrorKind::FileTooLarge,
+        format!("write past the end of '{}'", this.path),
+      )));
+    }
+    let buf = &buf[..buf.len().min(remaining.try_into().unwrap_or(usize::MAX))];
+    let written = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+    this.pos += written as u64;
+    Poll::Ready(Ok(written))
+  }
+
+  fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Pin::new(&mut self.inner).poll_flush(cx)
+  }
+
+  /// Flushes the archive, without shutting down the underlying writer.
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    self.poll_flush(cx)
+  }
+}
+
+impl<R: AsyncWrite + AsyncSeek + Unpin> AsyncSeek for FileMut<R> {
+  fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+    let size = self.metadata.size;
+    let relative_pos = match position {
+      SeekFrom::Start(pos) => Some(pos.min(size)),
+      SeekFrom::Current(pos) => self.pos.checked_add_signed(pos).map(|x| x.min(size)),
+      SeekFrom::End(pos) => size.checked_add_signed(pos.min(0)),
+    };
+    let relative_pos = relative_pos.ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+    let start = self.start;
+    Pin::new(&mut self.inner).start_seek(SeekFrom::Start(start + relative_pos))
+  }
+
+  fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+    let absolute_pos = ready!(Pin::new(&mut self.inner).poll_complete(cx))?;
+    self.pos = absolute_pos - self.start;
+    Poll::Ready(Ok(self.pos))
+  }
+}
@@ -0,0 +1,38 @@
+//! Embedding archives into binaries at build time.
+//!
+//! Pack a directory in the build script with [`embed_dir`]:
+//!
+//! ```no_run
+//! // in build.rs
+//! hive_asar::embed::embed_dir("assets", "assets.asar").unwrap();
+//! ```
+//!
+//! and load it with [`asar_embed!`](crate::asar_embed):
+//!
+//! ```ignore
+//! let archive = hive_asar::asar_embed!("assets.asar").unwrap();
+//! let content: &'static [u8] = archive.get_slice("index.html").unwrap();
+//! ```
+
+use crate::pack_dir_into_writer;
+use std::env;
+use std::path::{Path, PathBuf};
+use tokio::io;
+
+/// Packs directory `src` into `$OUT_DIR/name`, for use in build scripts.
+///
+/// The build script is also told to rerun when anything in `src` changes.
+/// Returns the path of the written archive.
+pub fn embed_dir(src: impl AsRef<Path>, name: &str) -> io::Result<PathBuf> {
+  let src = src.as_ref();
+  let out_dir = env::var_os("OUT_DIR")
+    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "OUT_DIR is not set"))?;
+  let dest = Path::new(&out_dir).join(name);
+  println!("cargo:rerun-if-changed={}", src.display());
+
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()?;
+  runtime.block_on(async { pack_dir_into_writer(src).await?.write_file(&dest).await })?;
+  Ok(dest)
+}
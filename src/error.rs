@@ -0,0 +1,285 @@
+//! Error types specific to asar archives.
+//!
+//! All APIs in this crate return [`io::Result`]. Errors listed here are
+//! wrapped inside [`io::Error`], and can be retrieved using
+//! [`io::Error::get_ref`] and downcasting to [`Error`].
+
+use std::error::Error as StdError;
+use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::path::PathBuf;
+use tokio::io;
+
+/// Asar-specific error.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+  /// The content of an entry extends past the end of the archive.
+  Truncated {
+    /// Path of the entry.
+    path: Box<str>,
+    /// Size of the entry recorded in the header.
+    expected: u64,
+    /// Bytes actually available in the archive for the entry.
+    available: u64,
+  },
+
+  /// A path to be created on the file system is too long.
+  PathTooLong {
+    /// The offending path.
+    path: PathBuf,
+    /// Length of the path, or its longest component.
+    len: usize,
+    /// Maximum length allowed.
+    max: usize,
+  },
+
+  /// Two entries in the same directory have names differing only in case,
+  /// and would overwrite each other on case-insensitive file systems.
+  CaseCollision {
+    /// Destination of the entry kept.
+    first: PathBuf,
+    /// Destination of the colliding entry.
+    second: PathBuf,
+  },
+
+  /// An archive exceeds a limit set in
+  /// [`ArchiveOptions`](crate::ArchiveOptions), or in `ExtractOptions` when
+  /// extracting.
+  LimitExceeded {
+    /// The limit exceeded.
+    limit: Limit,
+    /// The actual value.
+    value: u64,
+    /// Maximum value allowed.
+    max: u64,
+  },
+
+  /// Content of two files overlaps, which legitimate archives never do.
+  ///
+  /// Overlapping entries can alias content, or make an archive expand hugely
+  /// when extracted.
+  Overlap {
+    /// The file with the smaller offset.
+    first: Box<str>,
+    /// The file overlapping it.
+    second: Box<str>,
+  },
+
+  /// An archive being written exceeds the size set in
+  /// [`Writer::set_max_archive_size`](crate::Writer::set_max_archive_size).
+  ArchiveTooLarge {
+    /// The file crossing the limit, or `None` if the header or the trailer
+    /// did.
+    entry: Option<Box<str>>,
+    /// Projected size of the archive up to and including `entry`.
+    size: u64,
+    /// Maximum size allowed.
+    max: u64,
+  },
+
+  /// A path added to a [`Writer`](crate::Writer) with strict paths enabled,
+  /// or checked by [`ArchivePath::new`](crate::ArchivePath::new), is not
+  /// normalized.
+  InvalidPath {
+    /// The offending path.
+    path: Box<str>,
+    /// The path after [`normalize_path`](crate::normalize_path).
+    normalized: Box<str>,
+  },
+
+  /// A path added to a [`Writer`](crate::Writer) contains a name that is
+  /// invalid on Windows, and the writer is set to reject such names with
+  /// [`Writer::set_windows_names`](crate::Writer::set_windows_names).
+  ReservedName {
+    /// The offending path.
+    path: Box<str>,
+    /// The invalid segment of the path.
+    segment: Box<str>,
+  },
+
+  /// Content of a file does not match its integrity information.
+  IntegrityMismatch {
+    /// Path of the file.
+    path: Box<str>,
+    /// Index of the mismatched or missing block, or `None` if every block
+    /// matches but the whole-file hash does not.
+    block: Option<usize>,
+  },
+
+  /// An archive's header is compressed, a non-standard extension, and no
+  /// `HeaderCodec` is set to decompress it, or the `compressed-header`
+  /// feature is disabled.
+  UnsupportedCompressedHeader,
+
+  /// An I/O error on a path on the file system, e.g. when packing or
+  /// extracting.
+  Io {
+    /// The path being accessed.
+    path: PathBuf,
+    /// The underlying error.
+    source: io::Error,
+  },
+
+  /// An I/O error while reading or writing an entry of an archive.
+  Entry {
+    /// Path of the entry in the archive.
+    path: Box<str>,
+    /// The underlying error.
+    source: io::Error,
+  },
+}
+
+impl Error {
+  fn kind(&self) -> io::ErrorKind {
+    match self {
+      Self::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+      Self::PathTooLong { .. } => io::ErrorKind::InvalidInput,
+      Self::CaseCollision { .. } => io::ErrorKind::AlreadyExists,
+      Self::LimitExceeded { .. } => io::ErrorKind::InvalidData,
+      Self::Overlap { .. } => io::ErrorKind::InvalidData,
+      Self::IntegrityMismatch { .. } => io::ErrorKind::InvalidData,
+      Self::UnsupportedCompressedHeader => io::ErrorKind::Unsupported,
+      Self::ArchiveTooLarge { .. } => io::ErrorKind::FileTooLarge,
+      Self::InvalidPath { .. } | Self::ReservedName { .. } => io::ErrorKind::InvalidInput,
+      Self::Io { source, .. } | Self::Entry { source, .. } => source.kind(),
+    }
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::Truncated {
+        path,
+        expected,
+        available,
+      } => write!(
+        f,
+        "archive truncated: '{path}' expected {expected} bytes, only {available} available"
+      ),
+      Self::PathTooLong { path, len, max } => write!(
+        f,
+        "path too long: '{}' has length {len}, exceeding {max}",
+        path.display()
+      ),
+      Self::CaseCollision { first, second } => write!(
+        f,
+        "case collision: '{}' and '{}'",
+        first.display(),
+        second.display()
+      ),
+      Self::LimitExceeded { limit, value, max } => {
+        write!(f, "limit exceeded: {limit} is {value}, exceeding {max}")
+      }
+      Self::Overlap { first, second } => {
+        write!(f, "overlapping entries: '{first}' and '{second}'")
+      }
+      Self::ArchiveTooLarge { entry, size, max } => {
+        write!(f, "archive too large: {size} bytes, exceeding {max}")?;
+        match entry {
+          Some(entry) => write!(f, ", at '{entry}'"),
+          None => Ok(()),
+        }
+      }
+      Self::InvalidPath { path, normalized } => {
+        write!(
+          f,
+          "path not normalized: '{path}', normalized as '{normalized}'"
+        )
+      }
+      Self::ReservedName { path, segment } => {
+        write!(f, "name '{segment}' in '{path}' is invalid on Windows")
+      }
+      Self::IntegrityMismatch { path, block } => match block {
+        Some(block) => write!(f, "integrity mismatch: block {block} of '{path}'"),
+        None => write!(f, "integrity mismatch: content of '{path}'"),
+      },
+      Self::UnsupportedCompressedHeader => f.write_str("compressed header unsupported"),
+      Self::Io { path, source } => write!(f, "'{}': {source}", path.display()),
+      Self::Entry { path, source } => write!(f, "entry '{path}': {source}"),
+    }
+  }
+}
+
+/// Limits in [`ArchiveOptions`](crate::ArchiveOptions) and `ExtractOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Limit {
+  HeaderLen,
+  Entries,
+  TotalSize,
+  PathLen,
+  ComponentLen,
+  ExtractedFiles,
+  ExtractedBytes,
+  ExtractedFileSize,
+}
+
+impl Display for Limit {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str(match self {
+      Self::HeaderLen => "header length",
+      Self::Entries => "number of entries",
+      Self::TotalSize => "total size",
+      Self::PathLen => "path length",
+      Self::ComponentLen => "path component length",
+      Self::ExtractedFiles => "number of extracted files",
+      Self::ExtractedBytes => "total size of extracted files",
+      Self::ExtractedFileSize => "size of an extracted file",
+    })
+  }
+}
+
+impl StdError for Error {
+  fn source(&self) -> Option<&(dyn StdError + 'static)> {
+    match self {
+      Self::Io { source, .. } | Self::Entry { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+impl From<Error> for io::Error {
+  fn from(error: Error) -> Self {
+    io::Error::new(error.kind(), error)
+  }
+}
+
+/// Attaches path context to errors.
+///
+/// Errors that are already [`Error`]s are left untouched, since they carry
+/// their own context.
+pub(crate) trait ResultExt<T> {
+  #[cfg(feature = "fs")]
+  fn with_path(self, path: impl AsRef<Path>) -> io::Result<T>;
+  fn with_entry(self, path: &str) -> io::Result<T>;
+}
+
+impl<T> ResultExt<T> for io::Result<T> {
+  #[cfg(feature = "fs")]
+  fn with_path(self, path: impl AsRef<Path>) -> io::Result<T> {
+    self.map_err(|source| {
+      if is_asar_error(&source) {
+        return source;
+      }
+      let path = path.as_ref().into();
+      Error::Io { path, source }.into()
+    })
+  }
+
+  fn with_entry(self, path: &str) -> io::Result<T> {
+    self.map_err(|source| {
+      if is_asar_error(&source) {
+        return source;
+      }
+      let path = path.into();
+      Error::Entry { path, source }.into()
+    })
+  }
+}
+
+fn is_asar_error(error: &io::Error) -> bool {
+  error.get_ref().is_some_and(|x| x.is::<Error>())
+}
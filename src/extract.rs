@@ -1,73 +1,441 @@
+use crate::error::{Limit, ResultExt};
 use crate::header::{Directory, Entry, FileMetadata};
-use std::future::Future;
+use crate::list::EntryKind;
+use crate::retry::{retry, Retry};
+#[cfg(feature = "xattr")]
+use crate::xattr::XattrPolicy;
+use crate::{Error, RetryPolicy};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::io::SeekFrom;
-use std::path::Path;
-use std::pin::Pin;
+use std::path::{Path, PathBuf};
 use tokio::fs::{create_dir, File as TokioFile};
-use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
-
-macro_rules! impl_extract_entry {
-  (
-    $extract_entry:ident,
-    $extract_dir:ident
-    $(, $send:ident)?
-  ) => {
-    pub fn $extract_entry<'a, R: AsyncRead + AsyncSeek $(+ $send)? + Unpin>(
-      reader: &'a mut R,
-      offset: u64,
-      name: &'a str,
-      entry: &'a Entry,
-      path: &'a Path,
-    ) -> Pin<Box<dyn Future<Output = io::Result<()>> $(+ $send)? + 'a>> {
-      Box::pin(async move {
-        match entry {
-          Entry::File(file) => extract_file(reader, offset, name, file, path).await?,
-          Entry::Directory(dir) => $extract_dir(reader, offset, name, dir, path).await?,
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWriteExt};
+
+/// Initial size of the buffer used to copy files' content.
+const MIN_READAHEAD: usize = 65536;
+
+/// Maximum length of a single path component.
+const MAX_COMPONENT_LEN: usize = 255;
+
+/// Maximum length of a whole path.
+///
+/// On Windows this is the limit of extended-length paths, in UTF-16 code
+/// units. Elsewhere it is Linux's `PATH_MAX`, in bytes.
+#[cfg(windows)]
+const MAX_PATH_LEN: usize = 32767;
+#[cfg(not(windows))]
+const MAX_PATH_LEN: usize = 4096;
+
+/// Prepares the destination directory of an extraction.
+///
+/// On Windows, the path is made absolute and prefixed with `\\?\`, so that
+/// paths longer than 260 characters, common in deep `node_modules` trees, can
+/// be created. Elsewhere the path is returned as is.
+#[cfg(windows)]
+pub fn extraction_root(path: &Path) -> io::Result<PathBuf> {
+  use std::ffi::OsString;
+  use std::path::{Component, Prefix};
+
+  let path = std::path::absolute(path)?;
+  let mut components = path.components();
+  let Some(Component::Prefix(prefix)) = components.next() else {
+    return Ok(path);
+  };
+  let mut prefixed = OsString::new();
+  match prefix.kind() {
+    Prefix::UNC(server, share) => {
+      prefixed.push(r"\\?\UNC\");
+      prefixed.push(server);
+      prefixed.push(r"\");
+      prefixed.push(share);
+    }
+    Prefix::Disk(_) => {
+      prefixed.push(r"\\?\");
+      prefixed.push(prefix.as_os_str());
+    }
+    _ => return Ok(path),
+  }
+  let mut result = PathBuf::from(prefixed);
+  result.extend(components);
+  Ok(result)
+}
+
+/// Prepares the destination directory of an extraction.
+///
+/// On Windows, the path is made absolute and prefixed with `\\?\`, so that
+/// paths longer than 260 characters, common in deep `node_modules` trees, can
+/// be created. Elsewhere the path is returned as is.
+#[cfg(not(windows))]
+pub fn extraction_root(path: &Path) -> io::Result<PathBuf> {
+  Ok(path.to_owned())
+}
+
+fn os_len(s: &std::ffi::OsStr) -> usize {
+  #[cfg(windows)]
+  return std::os::windows::ffi::OsStrExt::encode_wide(s).count();
+  #[cfg(not(windows))]
+  return s.len();
+}
+
+/// Joins `name` to `parent`, checking the resulting path's length.
+fn join_checked(parent: &Path, name: &str) -> io::Result<PathBuf> {
+  let path = parent.join(name);
+  let name_len = os_len(name.as_ref());
+  if name_len > MAX_COMPONENT_LEN {
+    return Err(
+      Error::PathTooLong {
+        path,
+        len: name_len,
+        max: MAX_COMPONENT_LEN,
+      }
+      .into(),
+    );
+  }
+  let path_len = os_len(path.as_os_str());
+  if path_len > MAX_PATH_LEN {
+    return Err(
+      Error::PathTooLong {
+        path,
+        len: path_len,
+        max: MAX_PATH_LEN,
+      }
+      .into(),
+    );
+  }
+  Ok(path)
+}
+
+/// Options for [`Archive::extract_with_options`](crate::Archive::extract_with_options).
+///
+/// Limits guard services extracting untrusted archives against ones that
+/// expand into huge amounts of data. They are checked against the header
+/// before anything is created, and fail with [`Error::LimitExceeded`].
+/// Content is never read past sizes in the header, so they hold for the
+/// bytes actually written as well.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+  /// What to do with entries whose names differ only in case.
+  pub case_collision: CaseCollision,
+
+  /// Maximum number of files to extract.
+  pub max_files: Option<u64>,
+
+  /// Maximum total size of files to extract.
+  pub max_total_bytes: Option<u64>,
+
+  /// Maximum size of a single file to extract.
+  pub max_file_bytes: Option<u64>,
+
+  /// How to retry transient errors reading the archive and writing files.
+  pub retry: Option<RetryPolicy>,
+
+  /// Which extended attributes recorded in the archive to set on extracted
+  /// files. See [`xattr`](crate::xattr).
+  #[cfg(feature = "xattr")]
+  #[cfg_attr(docsrs, doc(cfg(feature = "xattr")))]
+  pub xattrs: XattrPolicy,
+}
+
+impl ExtractOptions {
+  fn check(&self, files: &[PlannedFile<'_>]) -> io::Result<()> {
+    let check = |limit, value, max: Option<u64>| match max {
+      Some(max) if value > max => Err(Error::LimitExceeded { limit, value, max }),
+      _ => Ok(()),
+    };
+    check(Limit::ExtractedFiles, files.len() as u64, self.max_files)?;
+    let total = files
+      .iter()
+      .fold(0u64, |acc, (.., file)| acc.saturating_add(file.size));
+    check(Limit::ExtractedBytes, total, self.max_total_bytes)?;
+    let largest = files.iter().map(|(.., file)| file.size).max().unwrap_or(0);
+    check(Limit::ExtractedFileSize, largest, self.max_file_bytes)?;
+    Ok(())
+  }
+}
+
+/// Policy for entries in the same directory whose names differ only in case,
+/// e.g. `a.txt` and `A.txt`.
+///
+/// On case-insensitive file systems (default on Windows and macOS), such
+/// entries would silently overwrite each other. Colliding names are ordered
+/// bytewise, and the first one is always extracted as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseCollision {
+  /// Do not check for collisions.
+  #[default]
+  Ignore,
+
+  /// Fail with [`Error::CaseCollision`] before extracting the directory.
+  Error,
+
+  /// Rename later entries by adding a suffix, e.g. `A~1.txt`.
+  Rename,
+
+  /// Skip later entries.
+  KeepFirst,
+}
+
+/// Resolves names of entries in `dir` to be created under `path` according to
+/// `policy`.
+pub fn resolve_names<'a>(
+  dir: &'a Directory,
+  path: &Path,
+  policy: CaseCollision,
+) -> io::Result<Vec<(Cow<'a, str>, &'a Entry)>> {
+  if policy == CaseCollision::Ignore {
+    return Ok(
+      dir
+        .files
+        .iter()
+        .map(|(k, v)| (Cow::from(&**k), v))
+        .collect(),
+    );
+  }
+
+  let mut entries: Vec<_> = dir.files.iter().collect();
+  entries.sort_unstable_by_key(|(name, _)| *name);
+  let mut taken = HashSet::new();
+  let mut first_of = HashMap::new();
+  let mut result = Vec::with_capacity(entries.len());
+  let mut collided = Vec::new();
+
+  for (name, entry) in entries {
+    let lower = name.to_lowercase();
+    match first_of.get(&lower) {
+      None => {
+        taken.insert(lower.clone());
+        first_of.insert(lower, &**name);
+        result.push((Cow::from(&**name), entry));
+      }
+      Some(first) => match policy {
+        CaseCollision::Error => {
+          return Err(
+            Error::CaseCollision {
+              first: path.join(first),
+              second: path.join(&**name),
+            }
+            .into(),
+          )
         }
-        Ok(())
+        CaseCollision::KeepFirst => {}
+        _ => collided.push((name, entry)),
+      },
+    }
+  }
+
+  // Renamed later so that they never take names of original entries.
+  for (name, entry) in collided {
+    let (stem, ext) = match name.rfind('.') {
+      Some(i) if i > 0 => name.split_at(i),
+      _ => (&**name, ""),
+    };
+    let renamed = (1..)
+      .map(|i| format!("{stem}~{i}{ext}"))
+      .find(|x| !taken.contains(&x.to_lowercase()))
+      .unwrap();
+    taken.insert(renamed.to_lowercase());
+    result.push((Cow::from(renamed), entry));
+  }
+  Ok(result)
+}
+
+/// A file system change that extraction would make, returned by
+/// [`Archive::extract_plan`](crate::Archive::extract_plan).
+#[derive(Debug, Clone)]
+pub struct PlannedEntry {
+  /// Destination of the entry on the file system.
+  pub path: PathBuf,
+
+  /// Whether a file or a directory would be created.
+  pub kind: EntryKind,
+
+  /// Size of the file, or `0` for directories.
+  pub size: u64,
+
+  /// Whether something already exists at `path`.
+  ///
+  /// Extraction fails on existing directories, and overwrites existing
+  /// files.
+  pub exists: bool,
+}
+
+/// Computes what extracting `dir` into `root` would create, in the order of
+/// extraction, without touching the file system other than checking whether
+/// destinations exist.
+pub async fn plan(
+  dir: &Directory,
+  root: &Path,
+  options: ExtractOptions,
+) -> io::Result<Vec<PlannedEntry>> {
+  let (dirs, files) = collect(dir, root, options.case_collision)?;
+  let dirs = dirs.into_iter().map(|path| PlannedEntry {
+    path,
+    kind: EntryKind::Directory,
+    size: 0,
+    exists: false,
+  });
+  let files = files.into_iter().map(|(_, path, file)| PlannedEntry {
+    path,
+    kind: EntryKind::File,
+    size: file.size,
+    exists: false,
+  });
+  let mut result: Vec<_> = dirs.chain(files).collect();
+  for entry in &mut result {
+    entry.exists = (tokio::fs::symlink_metadata(&entry.path).await)
+      .map(|_| true)
+      .or_else(|error| match error.kind() {
+        io::ErrorKind::NotFound => Ok(false),
+        _ => Err(error),
       })
+      .with_path(&entry.path)?;
+  }
+  Ok(result)
+}
+
+type PlannedFile<'a> = (u64, PathBuf, &'a FileMetadata);
+
+/// Resolves destinations of every entry in `dir` under `root`.
+///
+/// Returns directories in tree order, so that parents come first, and files
+/// sorted by their offsets.
+fn collect<'a>(
+  dir: &'a Directory,
+  root: &Path,
+  policy: CaseCollision,
+) -> io::Result<(Vec<PathBuf>, Vec<PlannedFile<'a>>)> {
+  fn collect_dir<'a>(
+    dir: &'a Directory,
+    path: &Path,
+    policy: CaseCollision,
+    dirs: &mut Vec<PathBuf>,
+    files: &mut Vec<PlannedFile<'a>>,
+  ) -> io::Result<()> {
+    for (name, entry) in resolve_names(dir, path, policy)? {
+      let dest_path = join_checked(path, &name)?;
+      match entry {
+        Entry::File(file) => files.push((file.offset()?, dest_path, file)),
+        Entry::Directory(dir) => {
+          dirs.push(dest_path.clone());
+          collect_dir(dir, &dest_path, policy, dirs, files)?;
+        }
+      }
     }
+    Ok(())
   }
+
+  let (mut dirs, mut files) = (Vec::new(), Vec::new());
+  collect_dir(dir, root, policy, &mut dirs, &mut files)?;
+  files.sort_by_key(|(offset, ..)| *offset);
+  Ok((dirs, files))
 }
 
-impl_extract_entry!(extract_entry, extract_dir, Send);
-impl_extract_entry!(extract_entry_local, extract_dir_local);
+/// State shared across the whole extraction.
+///
+/// A single buffer is used for copying every file. When a file starts right
+/// where the previous one ends, reading is sequential, and the buffer grows
+/// up to `max_readahead` to issue larger reads. Otherwise it shrinks back.
+pub struct ExtractState {
+  options: ExtractOptions,
+  offset: u64,
+  max_readahead: usize,
+  buf: Vec<u8>,
+  next_pos: Option<u64>,
+}
 
-async fn extract_file<R: AsyncRead + AsyncSeek + Unpin>(
+impl ExtractState {
+  pub fn new(options: ExtractOptions, offset: u64, max_readahead: usize) -> Self {
+    Self {
+      options,
+      offset,
+      max_readahead: max_readahead.max(1),
+      buf: Vec::new(),
+      next_pos: None,
+    }
+  }
+
+  pub fn case_collision(&self) -> CaseCollision {
+    self.options.case_collision
+  }
+}
+
+/// Extracts every entry in `dir` into `root`.
+///
+/// All directories are created first. Files are then written in the order of
+/// their offsets, so that the archive is read nearly sequentially instead of
+/// seeking back and forth in the header's arbitrary order.
+pub async fn extract<R: AsyncRead + AsyncSeek + Unpin>(
   reader: &mut R,
-  offset: u64,
-  name: &str,
-  file: &FileMetadata,
-  path: &Path,
+  state: &mut ExtractState,
+  dir: &Directory,
+  root: &Path,
 ) -> io::Result<()> {
-  reader.seek(SeekFrom::Start(offset + file.offset()?)).await?;
-  let mut dest = TokioFile::create(path.join(name)).await?;
-  io::copy(&mut reader.take(file.size), &mut dest).await?;
+  let (dirs, files) = collect(dir, root, state.case_collision())?;
+  state.options.check(&files)?;
+  let policy = state.options.retry;
+  for path in dirs {
+    (retry(policy, || create_dir(&path)).await).with_path(&path)?;
+  }
+  for (_, dest_path, file) in files {
+    extract_file(reader, state, file, root, &dest_path).await?;
+  }
   Ok(())
 }
 
-macro_rules! impl_extract_dir {
-  (
-    $extract_dir:ident,
-    $extract_entry:ident
-    $(, $send:ident)?
-  ) => {
-    async fn $extract_dir<R: AsyncRead + AsyncSeek $(+ $send)? + Unpin>(
-      reader: &mut R,
-      offset: u64,
-      name: &str,
-      dir: &Directory,
-      path: &Path,
-    ) -> io::Result<()> {
-      let new_dir_path = path.join(name);
-      create_dir(&new_dir_path).await?;
-      for (name, entry) in dir.files.iter() {
-        $extract_entry(reader, offset, name, entry, &new_dir_path).await?;
-      }
-      Ok(())
+async fn extract_file<R: AsyncRead + AsyncSeek + Unpin>(
+  reader: &mut R,
+  state: &mut ExtractState,
+  file: &FileMetadata,
+  root: &Path,
+  dest_path: &Path,
+) -> io::Result<()> {
+  let start = state.offset + file.offset()?;
+  let buf_len = if state.next_pos == Some(start) {
+    (state.buf.len() * 2).clamp(MIN_READAHEAD, state.max_readahead)
+  } else {
+    reader
+      .seek(SeekFrom::Start(start))
+      .await
+      .with_path(dest_path)?;
+    MIN_READAHEAD.min(state.max_readahead)
+  };
+  state.buf.resize(buf_len, 0);
+  state.next_pos = None;
+
+  let policy = state.options.retry;
+  let dest = (retry(policy, || TokioFile::create(dest_path)).await).with_path(dest_path)?;
+  let mut dest = Retry::new(dest, policy);
+  let mut content = Retry::new(reader.take(file.size), policy);
+  loop {
+    let len = content.read(&mut state.buf).await.with_path(dest_path)?;
+    if len == 0 {
+      break;
     }
+    dest
+      .write_all(&state.buf[..len])
+      .await
+      .with_path(dest_path)?;
+  }
+  dest.flush().await.with_path(dest_path)?;
+  let missing = content.get_ref().limit();
+  if missing != 0 {
+    let path = dest_path.strip_prefix(root).unwrap_or(dest_path);
+    let path = (path.components())
+      .map(|x| x.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/");
+    return Err(
+      Error::Truncated {
+        path: path.into(),
+        expected: file.size,
+        available: file.size - missing,
+      }
+      .into(),
+    );
   }
+  state.next_pos = Some(start + file.size);
+  #[cfg(feature = "xattr")]
+  (crate::xattr::restore(dest_path, file, &state.options.xattrs).await).with_path(dest_path)?;
+  Ok(())
 }
-
-impl_extract_dir!(extract_dir, extract_entry, Send);
-impl_extract_dir!(extract_dir_local, extract_entry_local);
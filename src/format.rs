@@ -0,0 +1,131 @@
+//! Constants and low-level helpers describing asar's binary layout.
+//!
+//! An asar archive starts with a 16-byte prefix, which is a Chromium pickle
+//! holding the size of the header, followed by the JSON header padded to
+//! [`ALIGNMENT`], and then files' content:
+//!
+//! ```text
+//! | 4 | header_len + padding + 8 | header_len + padding + 4 | header_len |
+//! | header (header_len bytes) | padding | content ... |
+//! ```
+//!
+//! All integers are little-endian `u32`s.
+//!
+//! As a non-standard extension, the header may be stored compressed, which
+//! is marked by [`COMPRESSED_FLAG`] in the first integer. Electron and other
+//! tools reject such archives; see `HeaderCodec` with the
+//! `compressed-header` feature.
+
+/// Length of the prefix before the header.
+pub const PREFIX_LEN: usize = 16;
+
+/// Alignment of the header, padded with zeros.
+pub const ALIGNMENT: u32 = 4;
+
+/// Maximum size of a file, which is JavaScript's `Number.MAX_SAFE_INTEGER`.
+///
+/// See [`FileMetadata::size`](crate::header::FileMetadata::size).
+pub const MAX_SAFE_SIZE: u64 = 9_007_199_254_740_991;
+
+/// Maximum length of a single path component in bytes that file systems
+/// commonly support, and thus Electron can load.
+///
+/// Not enforced by default; see
+/// [`Writer::set_max_component_len`](crate::Writer::set_max_component_len)
+/// and [`ArchiveOptions::max_component_len`](crate::ArchiveOptions::max_component_len).
+pub const MAX_COMPONENT_LEN: u64 = 255;
+
+/// Default block size of integrity information, 4MiB, same as
+/// `@electron/asar`.
+pub const DEFAULT_BLOCK_SIZE: u32 = 4_194_304;
+
+/// Returns the number of zero bytes padding a header of `header_len` bytes.
+pub const fn padding(header_len: u32) -> u32 {
+  match header_len % ALIGNMENT {
+    0 => 0,
+    r => ALIGNMENT - r,
+  }
+}
+
+/// Returns the offset of the content region from the start of the archive,
+/// for a header of `header_len` bytes.
+pub const fn content_offset(header_len: u32) -> u64 {
+  PREFIX_LEN as u64 + header_len as u64 + padding(header_len) as u64
+}
+
+/// Encodes the 16-byte prefix for a header of `header_len` bytes.
+pub fn encode_prefix(header_len: u32) -> [u8; PREFIX_LEN] {
+  let padded = header_len + padding(header_len);
+  let mut prefix = [0; PREFIX_LEN];
+  for (bytes, i) in (prefix.chunks_exact_mut(4)).zip([4, padded + 8, padded + 4, header_len]) {
+    bytes.copy_from_slice(&i.to_le_bytes());
+  }
+  prefix
+}
+
+/// Decodes the 16-byte prefix, returning the header's length.
+///
+/// Returns `None` if the prefix is not valid.
+pub fn decode_prefix(prefix: &[u8; PREFIX_LEN]) -> Option<u32> {
+  let mut words = [0; 4];
+  for (word, bytes) in words.iter_mut().zip(prefix.chunks_exact(4)) {
+    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+  }
+  let [four, i1, i2, header_len] = words;
+  let padded = header_len.checked_add(padding(header_len))?;
+  let valid = four == 4 && Some(i1) == padded.checked_add(8) && Some(i2) == padded.checked_add(4);
+  valid.then_some(header_len)
+}
+
+/// Flag set in the first integer of the prefix if the header is compressed.
+///
+/// The header's length in the prefix is then the length of the compressed
+/// header, followed by padding and files' content as usual. Offsets in the
+/// header stay relative to the start of the content.
+pub const COMPRESSED_FLAG: u32 = 1 << 31;
+
+/// Encodes the 16-byte prefix for a compressed header of `compressed_len`
+/// bytes.
+pub fn encode_compressed_prefix(compressed_len: u32) -> [u8; PREFIX_LEN] {
+  let mut prefix = encode_prefix(compressed_len);
+  prefix[..4].copy_from_slice(&(4 | COMPRESSED_FLAG).to_le_bytes());
+  prefix
+}
+
+/// Decodes the 16-byte prefix of an archive with a compressed header,
+/// returning the compressed header's length.
+///
+/// Returns `None` if the prefix is not valid, or the header is not
+/// compressed.
+pub fn decode_compressed_prefix(prefix: &[u8; PREFIX_LEN]) -> Option<u32> {
+  let mut prefix = *prefix;
+  let four = u32::from_le_bytes(prefix[..4].try_into().unwrap());
+  if four != 4 | COMPRESSED_FLAG {
+    return None;
+  }
+  prefix[..4].copy_from_slice(&4u32.to_le_bytes());
+  decode_prefix(&prefix)
+}
+
+/// Compression of headers stored with [`COMPRESSED_FLAG`].
+///
+/// No codec is built in. Implement this trait for a unit struct, e.g. with
+/// `zstd::bulk::compress` and `zstd::bulk::decompress`, and pass a static
+/// reference to it in [`ArchiveOptions::header_codec`] for reading and to
+/// [`Writer::write_with_header_codec`] for writing. Without one, archives
+/// with compressed headers fail to parse with
+/// [`Error::UnsupportedCompressedHeader`].
+///
+/// [`ArchiveOptions::header_codec`]: crate::ArchiveOptions::header_codec
+/// [`Writer::write_with_header_codec`]: crate::Writer::write_with_header_codec
+/// [`Error::UnsupportedCompressedHeader`]: crate::Error::UnsupportedCompressedHeader
+#[cfg(feature = "compressed-header")]
+#[cfg_attr(docsrs, doc(cfg(feature = "compressed-header")))]
+pub trait HeaderCodec: std::fmt::Debug + Send + Sync {
+  /// Compresses the serialized JSON header.
+  fn compress(&self, header: &[u8]) -> std::io::Result<Vec<u8>>;
+
+  /// Decompresses a header, failing if it would be longer than `max_len`
+  /// bytes.
+  fn decompress(&self, data: &[u8], max_len: usize) -> std::io::Result<Vec<u8>>;
+}
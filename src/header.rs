@@ -3,15 +3,35 @@
 //! Asar's header is represented using a single root [`Directory`], with tree
 //! structures similar to what the file system looks like.
 
+use crate::format::{content_offset, DEFAULT_BLOCK_SIZE};
+use crate::info::DirectoryMetrics;
+use crate::{cfg_integrity, split_path};
 use serde::de::{Error, Unexpected};
+use serde::ser::SerializeMap;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use serde_json::Value;
+use std::cell::RefCell;
+#[cfg(feature = "compact-header")]
+use std::collections::BTreeMap as Map;
+#[cfg(not(feature = "compact-header"))]
+use std::collections::HashMap as Map;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Debug, Display, Formatter};
-use std::ops::Deref;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
 use tokio::io;
 
+cfg_integrity! {
+  use sha2::digest::Digest;
+  use sha2::Sha256;
+  use tokio::io::{AsyncRead, AsyncReadExt};
+}
+
 /// Entry of either a file or a directory.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// When parsing, objects with a `size` field and no `files` field are files,
+/// so that malformed files are rejected rather than read as empty directories.
+#[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
 pub enum Entry {
   /// A file.
@@ -21,6 +41,19 @@ pub enum Entry {
   Directory(Directory),
 }
 
+impl<'de> Deserialize<'de> for Entry {
+  fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+    let value = Value::deserialize(de)?;
+    let is_file = value.get("size").is_some() && value.get("files").is_none();
+    let result = if is_file {
+      FileMetadata::deserialize(value).map(Self::File)
+    } else {
+      Directory::deserialize(value).map(Self::Directory)
+    };
+    result.map_err(Error::custom)
+  }
+}
+
 impl Entry {
   pub(crate) fn search_segments(&self, segments: &[&str]) -> Option<&Entry> {
     match self {
@@ -49,11 +82,49 @@ pub struct FileMetadata {
 
   /// Whether the file is an executable.
   #[serde(default)]
-  #[serde(skip_serializing_if = "bool::clone")]
+  #[serde(skip_serializing_if = "std::ops::Not::not")]
   pub executable: bool,
 
   /// Optional integrity information of the file.
   pub integrity: Option<Integrity>,
+
+  /// Full permission bits of the file on Unix, if recorded when packing.
+  ///
+  /// This is a non-standard extension field, stored as `x-mode`.
+  #[serde(rename = "x-mode", default, skip_serializing_if = "Option::is_none")]
+  pub mode: Option<u32>,
+
+  /// Application-specific extension fields, e.g. content type or source
+  /// commit.
+  ///
+  /// Keys must start with `x-`; other keys are neither serialized nor
+  /// collected when parsing, so they cannot clash with fields of the format.
+  /// `x-mode` is always stored in [`FileMetadata::mode`] instead.
+  #[serde(
+    flatten,
+    serialize_with = "serialize_attributes",
+    deserialize_with = "deserialize_attributes"
+  )]
+  pub attributes: BTreeMap<String, Value>,
+}
+
+fn is_attribute(key: &str) -> bool {
+  key.starts_with("x-") && key != "x-mode"
+}
+
+fn serialize_attributes<S: Serializer>(
+  attributes: &BTreeMap<String, Value>,
+  ser: S,
+) -> Result<S::Ok, S::Error> {
+  ser.collect_map(attributes.iter().filter(|(key, _)| is_attribute(key)))
+}
+
+fn deserialize_attributes<'de, D: Deserializer<'de>>(
+  de: D,
+) -> Result<BTreeMap<String, Value>, D::Error> {
+  let mut attributes = BTreeMap::<String, Value>::deserialize(de)?;
+  attributes.retain(|key, _| is_attribute(key));
+  Ok(attributes)
 }
 
 impl FileMetadata {
@@ -61,12 +132,46 @@ impl FileMetadata {
     if let FilePosition::Offset(x) = self.pos {
       Ok(x)
     } else {
-      Err(io::Error::new(
-        io::ErrorKind::Other,
-        "unpacked file is currently not supported",
-      ))
+      Err(io::Error::other("unpacked file is currently not supported"))
     }
   }
+
+  /// Returns a strong HTTP entity tag of the file's content, derived from
+  /// its integrity hash, e.g. `"sha256-e3b0…"` including the quotes.
+  ///
+  /// Files with the same content always have the same tag. Returns `None`
+  /// for files without integrity information.
+  pub fn etag(&self) -> Option<String> {
+    let integrity = self.integrity.as_ref()?;
+    Some(format!(
+      "\"{}-{}\"",
+      integrity.algorithm.name(),
+      integrity.hash
+    ))
+  }
+
+  /// Whether the file has integrity information.
+  pub fn has_integrity(&self) -> bool {
+    self.integrity.is_some()
+  }
+
+  /// Number of block hashes in the file's integrity information, if any.
+  pub fn block_count(&self) -> Option<usize> {
+    Some(self.integrity.as_ref()?.blocks.len())
+  }
+
+  /// Whether the number of block hashes matches the file's size divided by
+  /// the block size, rounded up.
+  ///
+  /// Files without integrity information are always consistent. A block
+  /// size of `0` is never consistent.
+  pub fn expected_blocks_len_consistent(&self) -> bool {
+    let Some(integrity) = &self.integrity else {
+      return true;
+    };
+    integrity.block_size != 0
+      && self.size.div_ceil(integrity.block_size.into()) == integrity.blocks.len() as u64
+  }
 }
 
 /// Whether the file is stored in the archive or is unpacked.
@@ -81,11 +186,11 @@ pub enum FilePosition {
 
 #[derive(Serialize, Deserialize)]
 #[serde(untagged)]
-enum Helper<'a> {
+enum Helper {
   Offset {
     #[serde(skip_serializing_if = "Option::is_none")]
     unpacked: Option<bool>,
-    offset: &'a str,
+    offset: String,
   },
   Unpacked {
     unpacked: bool,
@@ -94,15 +199,11 @@ enum Helper<'a> {
 
 impl Serialize for FilePosition {
   fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
-    let offset_string;
     let helper = match self {
-      Self::Offset(offset) => {
-        offset_string = offset.to_string();
-        Helper::Offset {
-          unpacked: None,
-          offset: &offset_string,
-        }
-      }
+      Self::Offset(offset) => Helper::Offset {
+        unpacked: None,
+        offset: offset.to_string(),
+      },
       Self::Unpacked => Helper::Unpacked { unpacked: true },
     };
 
@@ -113,13 +214,14 @@ impl Serialize for FilePosition {
 impl<'de> Deserialize<'de> for FilePosition {
   fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
     match Helper::deserialize(de)? {
-      Helper::Offset { unpacked, .. } if matches!(unpacked, Some(true)) => {
-        Err(Error::custom("got both 'unpacked' and 'offset' field"))
-      }
+      Helper::Offset {
+        unpacked: Some(true),
+        ..
+      } => Err(Error::custom("got both 'unpacked' and 'offset' field")),
       Helper::Offset { offset, .. } => offset
         .parse()
         .map(Self::Offset)
-        .map_err(|_| Error::invalid_value(Unexpected::Str(offset), &"valid u64 string")),
+        .map_err(|_| Error::invalid_value(Unexpected::Str(&offset), &"valid u64 string")),
       Helper::Unpacked { unpacked: true } => Ok(Self::Unpacked),
       Helper::Unpacked { unpacked: false } => {
         Err(Error::invalid_value(Unexpected::Bool(false), &"true"))
@@ -129,7 +231,9 @@ impl<'de> Deserialize<'de> for FilePosition {
 }
 
 /// Integrity information of a file.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// When parsing, every hash must have the length of `algorithm`'s output.
+#[derive(Debug, Clone, Serialize)]
 pub struct Integrity {
   /// Hashing algorithm used.
   pub algorithm: Algorithm,
@@ -142,27 +246,231 @@ pub struct Integrity {
   pub block_size: u32,
 
   /// Hashes of blocks.
-  pub blocks: Vec<Hash>,
+  ///
+  /// Identical block lists may be shared between files after
+  /// [`Directory::intern_integrity`]. Use [`Arc::make_mut`] to modify them,
+  /// which clones the list if it is shared.
+  pub blocks: Arc<Vec<Hash>>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct Hash(#[serde(with = "hex::serde")] pub(crate) Vec<u8>);
+impl Integrity {
+  /// Integrity information of the serialized size of a file of `size` bytes,
+  /// with all hashes zeroed.
+  fn placeholder(size: u64) -> Self {
+    let blocks = size.div_ceil(DEFAULT_BLOCK_SIZE.into()) as usize;
+    Self {
+      algorithm: Algorithm::SHA256,
+      hash: Hash::Sha256([0; 32]),
+      block_size: DEFAULT_BLOCK_SIZE,
+      blocks: Arc::new(vec![Hash::Sha256([0; 32]); blocks]),
+    }
+  }
+
+  /// Returns indices of blocks covering bytes in `range` of the file.
+  ///
+  /// Returns an empty range if `range` is empty or the block size is `0`.
+  pub fn blocks_for_range(&self, range: Range<u64>) -> Range<usize> {
+    let block_size = u64::from(self.block_size);
+    if range.is_empty() || block_size == 0 {
+      return 0..0;
+    }
+    let start = range.start / block_size;
+    let end = range.end.div_ceil(block_size);
+    start as usize..end as usize
+  }
+
+  /// Checks that the block size is not `0`, and that every hash has the
+  /// length of the algorithm's output.
+  ///
+  /// Parsed headers always have hashes of the right length. This does not
+  /// check the number of blocks, which depends on the file's size; see
+  /// [`FileMetadata::expected_blocks_len_consistent`].
+  pub fn validate_structure(&self) -> io::Result<()> {
+    if self.block_size == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "block size is 0",
+      ));
+    }
+    let len = self.algorithm.hash_len();
+    if let Some(hash) = (std::iter::once(&self.hash))
+      .chain(&*self.blocks)
+      .find(|x| x.len() != len)
+    {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("hash '{hash}' is not {len} bytes long"),
+      ));
+    }
+    Ok(())
+  }
+
+  /// Creates SHA256 integrity information from hashes computed elsewhere,
+  /// e.g. while uploading the file.
+  ///
+  /// `blocks` are hashes of consecutive `block_size`-byte blocks of the file,
+  /// the last one possibly shorter, and `hash` is the hash of the whole file.
+  /// Fails if [`Integrity::validate_structure`] does.
+  pub fn from_precomputed(blocks: Vec<Hash>, hash: Hash, block_size: u32) -> io::Result<Self> {
+    let integrity = Self {
+      algorithm: Algorithm::SHA256,
+      hash,
+      block_size,
+      blocks: Arc::new(blocks),
+    };
+    integrity.validate_structure()?;
+    Ok(integrity)
+  }
+
+  cfg_integrity! {
+    /// Computes SHA256 integrity information of everything `reader` yields,
+    /// with blocks of `block_size` bytes.
+    ///
+    /// Returns the number of bytes read along with it, i.e. the file's size.
+    /// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) if
+    /// `block_size` is `0`.
+    pub async fn compute_async(
+      reader: &mut (impl AsyncRead + Unpin),
+      block_size: u32,
+    ) -> io::Result<(u64, Self)> {
+      if block_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "block size is 0"));
+      }
+      let mut global_state = Sha256::new();
+      let mut block = Vec::with_capacity(block_size as _);
+      let mut blocks = Vec::new();
+      let mut size = 0;
+      loop {
+        let read_size = (&mut *reader)
+          .take(block_size.into())
+          .read_to_end(&mut block)
+          .await?;
+        if read_size == 0 {
+          break;
+        }
+        size += read_size as u64;
+        blocks.push(Hash::Sha256(Sha256::digest(&block).into()));
+        global_state.update(&block);
+        block.clear();
+      }
+      let integrity = Self {
+        algorithm: Algorithm::SHA256,
+        hash: Hash::Sha256(global_state.finalize().into()),
+        block_size,
+        blocks: Arc::new(blocks),
+      };
+      Ok((size, integrity))
+    }
+  }
+}
+
+#[derive(Deserialize)]
+struct IntegrityHelper {
+  algorithm: Algorithm,
+  hash: Hash,
+  #[serde(rename = "blockSize")]
+  block_size: u32,
+  blocks: Vec<Hash>,
+}
+
+impl<'de> Deserialize<'de> for Integrity {
+  fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+    let helper = IntegrityHelper::deserialize(de)?;
+    let len = helper.algorithm.hash_len();
+    if let Some(hash) = (std::iter::once(&helper.hash))
+      .chain(&helper.blocks)
+      .find(|x| x.len() != len)
+    {
+      return Err(Error::invalid_length(
+        hash.len(),
+        &"hash length matching algorithm",
+      ));
+    }
+    let blocks = INTERNED.with_borrow_mut(|interned| match interned {
+      Some(interned) => intern(interned, &helper.hash, helper.block_size, helper.blocks),
+      None => Arc::new(helper.blocks),
+    });
+    Ok(Self {
+      algorithm: helper.algorithm,
+      hash: helper.hash,
+      block_size: helper.block_size,
+      blocks,
+    })
+  }
+}
+
+/// Block lists seen so far, keyed by whole-file hash and block size.
+type Interned = HashMap<(Hash, u32), Arc<Vec<Hash>>>;
+
+thread_local! {
+  /// Block lists seen while parsing a header with [`Directory::from_json`],
+  /// if interning is enabled.
+  static INTERNED: RefCell<Option<Interned>> = const { RefCell::new(None) };
+}
+
+/// Returns the interned block list equal to `blocks`, interning it if none
+/// was seen before.
+fn intern(
+  interned: &mut Interned,
+  hash: &Hash,
+  block_size: u32,
+  blocks: impl Into<Arc<Vec<Hash>>>,
+) -> Arc<Vec<Hash>> {
+  let blocks = blocks.into();
+  match interned.get(&(hash.clone(), block_size)) {
+    Some(existing) if *existing == blocks => existing.clone(),
+    Some(_) => blocks,
+    None => {
+      interned.insert((hash.clone(), block_size), blocks.clone());
+      blocks
+    }
+  }
+}
+
+/// A hash in integrity information.
+///
+/// SHA256 hashes are stored inline. Comparison takes constant time for hashes
+/// of the same length, so that verifying content does not leak how much of a
+/// hash matched.
+#[derive(Clone, Eq)]
+pub enum Hash {
+  /// A SHA256 hash.
+  Sha256([u8; 32]),
+
+  /// A hash of any other length, e.g. from an unknown algorithm.
+  Raw(Box<[u8]>),
+}
 
 impl From<Vec<u8>> for Hash {
   fn from(x: Vec<u8>) -> Self {
-    Self(x)
+    match x.try_into() {
+      Ok(x) => Self::Sha256(x),
+      Err(x) => Self::Raw(x.into()),
+    }
+  }
+}
+
+impl From<[u8; 32]> for Hash {
+  fn from(x: [u8; 32]) -> Self {
+    Self::Sha256(x)
   }
 }
 
 impl From<Hash> for Vec<u8> {
   fn from(x: Hash) -> Self {
-    x.0
+    match x {
+      Hash::Sha256(x) => x.to_vec(),
+      Hash::Raw(x) => x.into(),
+    }
   }
 }
 
 impl AsRef<[u8]> for Hash {
   fn as_ref(&self) -> &[u8] {
-    &self.0
+    match self {
+      Self::Sha256(x) => x,
+      Self::Raw(x) => x,
+    }
   }
 }
 
@@ -174,6 +482,35 @@ impl Deref for Hash {
   }
 }
 
+impl PartialEq for Hash {
+  fn eq(&self, other: &Self) -> bool {
+    let (a, b) = (self.as_ref(), other.as_ref());
+    if a.len() != b.len() {
+      return false;
+    }
+    let diff = a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y));
+    std::hint::black_box(diff) == 0
+  }
+}
+
+impl std::hash::Hash for Hash {
+  fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    self.as_ref().hash(state)
+  }
+}
+
+impl Serialize for Hash {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    hex::serde::serialize(self, serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Hash {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    hex::serde::deserialize::<_, Vec<u8>>(deserializer).map(Self::from)
+  }
+}
+
 impl Debug for Hash {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     <Self as Display>::fmt(self, f)
@@ -182,7 +519,7 @@ impl Debug for Hash {
 
 impl Display for Hash {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-    f.write_str(&hex::encode(&self.0))
+    f.write_str(&hex::encode(self))
   }
 }
 
@@ -194,16 +531,518 @@ pub enum Algorithm {
   SHA256,
 }
 
+impl Algorithm {
+  /// Lowercase name of the algorithm, e.g. `sha256`.
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::SHA256 => "sha256",
+    }
+  }
+
+  /// Length of hashes produced by the algorithm, in bytes.
+  pub fn hash_len(self) -> usize {
+    match self {
+      Self::SHA256 => 32,
+    }
+  }
+}
+
+/// Map from names to entries in a [`Directory`].
+///
+/// This is a [`HashMap`](std::collections::HashMap) by default. With
+/// `compact-header` feature enabled, it is a
+/// [`BTreeMap`](std::collections::BTreeMap) instead, which has no spare
+/// capacity and thus uses noticeably less memory for parsed headers, at the
+/// cost of slower lookups in huge directories. It also makes iteration order
+/// sorted by name.
+pub type FileMap = Map<Box<str>, Entry>;
+
 /// A directory, containing files.
+///
+/// An empty directory is serialized as `{"files":{}}`, which is also what the
+/// header of an empty archive looks like. A missing `files` field, emitted by
+/// some tools for empty directories, is accepted when parsing.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Directory {
-  pub files: HashMap<Box<str>, Entry>,
+  #[serde(default)]
+  pub files: FileMap,
 }
 
 impl Directory {
+  /// Visits every entry under the directory recursively, in pre-order.
+  ///
+  /// `f` receives each entry's full path, separated by `/`. A single path
+  /// buffer is reused for the whole traversal, so walking huge headers does
+  /// not allocate per entry.
+  pub fn visit(&self, f: &mut impl FnMut(&str, &Entry)) {
+    self.visit_inner(&mut String::new(), f)
+  }
+
+  pub(crate) fn visit_files_mut(&mut self, f: &mut impl FnMut(&mut FileMetadata)) {
+    for entry in self.files.values_mut() {
+      match entry {
+        Entry::File(file) => f(file),
+        Entry::Directory(dir) => dir.visit_files_mut(f),
+      }
+    }
+  }
+
+  /// Shares identical integrity block lists between files.
+  ///
+  /// Archives with many identical files repeat the same block hashes for each
+  /// of them. Interning makes them point to a single allocation, which can
+  /// reduce memory usage of large headers significantly.
+  ///
+  /// To intern while parsing instead, so that duplicated lists are freed as
+  /// soon as they are read, set
+  /// [`ArchiveOptions::intern_integrity`](crate::ArchiveOptions::intern_integrity).
+  pub fn intern_integrity(&mut self) {
+    let mut interned = Interned::new();
+    self.visit_files_mut(&mut |file| {
+      if let Some(integrity) = &mut file.integrity {
+        let blocks = integrity.blocks.clone();
+        integrity.blocks = intern(&mut interned, &integrity.hash, integrity.block_size, blocks);
+      }
+    });
+  }
+
+  /// Parses a header from JSON, sharing identical integrity block lists
+  /// between files as they are parsed if `intern` is set.
+  pub(crate) fn from_json(json: &[u8], intern: bool) -> serde_json::Result<Self> {
+    if !intern {
+      return serde_json::from_slice(json);
+    }
+    struct Reset;
+
+    impl Drop for Reset {
+      fn drop(&mut self) {
+        INTERNED.set(None);
+      }
+    }
+
+    INTERNED.set(Some(Interned::new()));
+    let _reset = Reset;
+    serde_json::from_slice(json)
+  }
+
+  /// Finds files whose content hash, recorded in their integrity
+  /// information, equals `hash`.
+  ///
+  /// Returns full paths of matching files, sorted. Files without integrity
+  /// information never match.
+  pub fn find_by_hash(&self, hash: &[u8]) -> Vec<String> {
+    let mut paths = Vec::new();
+    self.visit(&mut |path, entry| match entry {
+      Entry::File(FileMetadata {
+        integrity: Some(integrity),
+        ..
+      }) if *integrity.hash == *hash => paths.push(path.to_owned()),
+      _ => {}
+    });
+    paths.sort_unstable();
+    paths
+  }
+
+  fn visit_inner(&self, path: &mut String, f: &mut impl FnMut(&str, &Entry)) {
+    for (name, entry) in self.files.iter() {
+      let len = path.len();
+      if len != 0 {
+        path.push('/');
+      }
+      path.push_str(name);
+      f(path, entry);
+      if let Entry::Directory(dir) = entry {
+        dir.visit_inner(path, f);
+      }
+      path.truncate(len);
+    }
+  }
+
+  /// Returns the total size of every file under the directory, recursively.
+  ///
+  /// Computed from the header alone, without reading any content. Unpacked
+  /// files are counted as well.
+  pub fn size_recursive(&self) -> u64 {
+    (self.files.values())
+      .map(|entry| match entry {
+        Entry::File(file) => file.size,
+        Entry::Directory(dir) => dir.size_recursive(),
+      })
+      .fold(0, u64::saturating_add)
+  }
+
+  /// Builds a header from `(path, size)` pairs without any content, e.g. for
+  /// planning an archive from a manifest before reading any file.
+  ///
+  /// Files are laid out one after another in the order given, with empty
+  /// files at offset `0`, like [`Writer`](crate::Writer) does. Missing parent
+  /// directories are created, and paths are normalized as with
+  /// [`normalize_path`](crate::normalize_path). Fails with
+  /// [`InvalidInput`](io::ErrorKind::InvalidInput) if a path is empty, or
+  /// conflicts with an earlier one.
+  pub fn from_listing<'a>(listing: impl IntoIterator<Item = (&'a str, u64)>) -> io::Result<Self> {
+    Self::from_listing_inner(listing, false)
+  }
+
+  fn from_listing_inner<'a>(
+    listing: impl IntoIterator<Item = (&'a str, u64)>,
+    integrity: bool,
+  ) -> io::Result<Self> {
+    let invalid =
+      |path: &str, reason| io::Error::new(io::ErrorKind::InvalidInput, format!("{reason}: {path}"));
+    let mut root = Self::default();
+    let mut offset = 0u64;
+    for (path, size) in listing {
+      let mut segments = split_path(path);
+      let name = segments.pop().ok_or_else(|| invalid(path, "empty path"))?;
+      let mut dir = &mut root;
+      for segment in segments {
+        let entry = (dir.files)
+          .entry(segment.into())
+          .or_insert_with(|| Entry::Directory(Default::default()));
+        dir = match entry {
+          Entry::Directory(dir) => dir,
+          Entry::File(_) => return Err(invalid(path, "parent is a file")),
+        };
+      }
+      if dir.files.contains_key(name) {
+        return Err(invalid(path, "duplicate path"));
+      }
+      let file = FileMetadata {
+        pos: FilePosition::Offset(if size == 0 { 0 } else { offset }),
+        size,
+        executable: false,
+        integrity: integrity.then(|| Integrity::placeholder(size)),
+        mode: None,
+        attributes: BTreeMap::new(),
+      };
+      dir.files.insert(name.into(), Entry::File(file));
+      offset = offset.saturating_add(size);
+    }
+    Ok(root)
+  }
+
+  /// Returns depth, fanout and entry counts of the tree under the
+  /// directory.
+  ///
+  /// Deep or wide trees make lookups and serialization slow; see
+  /// [`DirectoryMetrics`] for what is reported.
+  pub fn metrics(&self) -> DirectoryMetrics {
+    DirectoryMetrics::new(self)
+  }
+
+  /// Returns every directory under this one with its
+  /// [recursive size](Directory::size_recursive), largest first.
+  ///
+  /// Directories of the same size are ordered by path. Useful for reporting
+  /// what takes up space in an archive.
+  pub fn dir_sizes(&self) -> Vec<(String, u64)> {
+    fn collect(dir: &Directory, path: &mut String, result: &mut Vec<(String, u64)>) -> u64 {
+      let mut size = 0u64;
+      for (name, entry) in dir.files.iter() {
+        match entry {
+          Entry::File(file) => size = size.saturating_add(file.size),
+          Entry::Directory(dir) => {
+            let len = path.len();
+            if len != 0 {
+              path.push('/');
+            }
+            path.push_str(name);
+            let index = result.len();
+            result.push((path.clone(), 0));
+            let dir_size = collect(dir, path, result);
+            result[index].1 = dir_size;
+            size = size.saturating_add(dir_size);
+            path.truncate(len);
+          }
+        }
+      }
+      size
+    }
+
+    let mut result = Vec::new();
+    collect(self, &mut String::new(), &mut result);
+    result.sort_unstable_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    result
+  }
+
+  /// Finds two packed files whose content ranges overlap, returning their
+  /// paths in the order of their offsets.
+  ///
+  /// Empty files never overlap anything.
+  pub fn find_overlap(&self) -> Option<(String, String)> {
+    let mut ranges = Vec::new();
+    self.visit(&mut |path, entry| {
+      if let Entry::File(FileMetadata {
+        pos: FilePosition::Offset(offset),
+        size,
+        ..
+      }) = entry
+      {
+        if *size > 0 {
+          ranges.push((*offset, offset.saturating_add(*size), path.to_owned()));
+        }
+      }
+    });
+    ranges.sort_unstable_by_key(|(start, ..)| *start);
+
+    let mut furthest: Option<(u64, &str)> = None;
+    for (start, end, path) in &ranges {
+      match furthest {
+        Some((prev_end, prev)) if *start < prev_end => return Some((prev.into(), path.clone())),
+        Some((prev_end, _)) if prev_end >= *end => {}
+        _ => furthest = Some((*end, path)),
+      }
+    }
+    None
+  }
+
+  /// End of the furthest packed file's content, relative to the start of the
+  /// content region.
+  pub(crate) fn content_len(&self) -> u64 {
+    (self.files.values())
+      .map(|entry| match entry {
+        Entry::File(FileMetadata {
+          pos: FilePosition::Offset(offset),
+          size,
+          ..
+        }) => offset.saturating_add(*size),
+        Entry::File(_) => 0,
+        Entry::Directory(dir) => dir.content_len(),
+      })
+      .max()
+      .unwrap_or(0)
+  }
+
+  pub(crate) fn search_segments_mut(&mut self, segments: &[&str]) -> Option<&mut FileMetadata> {
+    let (last, parents) = segments.split_last()?;
+    let mut dir = self;
+    for segment in parents {
+      match dir.files.get_mut(*segment)? {
+        Entry::Directory(next) => dir = next,
+        Entry::File(_) => return None,
+      }
+    }
+    match dir.files.get_mut(*last)? {
+      Entry::File(file) => Some(file),
+      Entry::Directory(_) => None,
+    }
+  }
+
+  pub(crate) fn search_dir_mut(&mut self, segments: &[&str]) -> Option<&mut Directory> {
+    let mut dir = self;
+    for segment in segments {
+      match dir.files.get_mut(*segment)? {
+        Entry::Directory(next) => dir = next,
+        Entry::File(_) => return None,
+      }
+    }
+    Some(dir)
+  }
+
+  pub(crate) fn remove_segments(&mut self, segments: &[&str]) -> Option<Entry> {
+    let (last, parents) = segments.split_last()?;
+    self.search_dir_mut(parents)?.files.remove(*last)
+  }
+
   pub(crate) fn search_segments(&self, segments: &[&str]) -> Option<&Entry> {
+    let (first, rest) = segments.split_first()?;
     (self.files)
-      .get(segments[0])
-      .and_then(|x| x.search_segments(&segments[1..]))
+      .get(*first)
+      .and_then(|x| x.search_segments(rest))
+  }
+}
+
+/// Options controlling how a header is serialized.
+///
+/// The defaults match the derived [`Serialize`] implementations. Old or
+/// third-party parsers may expect slightly different output; for example,
+/// `@electron/asar` omits `integrity` instead of writing `null`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SerializeOptions {
+  /// Omit `integrity` of files without integrity information, instead of
+  /// writing `"integrity": null`.
+  pub skip_none_integrity: bool,
+
+  /// Always write the `executable` field, even if it is `false`.
+  pub always_emit_executable: bool,
+}
+
+impl Directory {
+  /// Returns a wrapper that serializes the directory using `options`.
+  pub fn serialize_with(&self, options: SerializeOptions) -> impl Serialize + '_ {
+    SerializeWith {
+      value: self,
+      options,
+    }
+  }
+}
+
+impl Directory {
+  /// Returns the length of the header serialized with `options`, without
+  /// allocating it.
+  pub(crate) fn serialized_len(&self, options: SerializeOptions) -> io::Result<u64> {
+    struct Counter(u64);
+
+    impl std::io::Write for Counter {
+      fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+      }
+
+      fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+      }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, &self.serialize_with(options))?;
+    Ok(counter.0)
+  }
+}
+
+/// Returns the size of everything before the content of an archive with
+/// files listed as in [`Directory::from_listing`]: the prefix, the header
+/// and its padding.
+///
+/// With `integrity`, room is made for SHA256 integrity information with
+/// [`DEFAULT_BLOCK_SIZE`] blocks, as recorded by
+/// [`Writer::add_sized_with_integrity`](crate::Writer::add_sized_with_integrity)
+/// and when packing directories. The result is exact for a writer with
+/// default options given the same files; adding the sizes of all files gives
+/// the final archive size.
+pub fn estimate_header_size<'a>(
+  listing: impl IntoIterator<Item = (&'a str, u64)>,
+  integrity: bool,
+) -> io::Result<u64> {
+  let header = Directory::from_listing_inner(listing, integrity)?;
+  let header_len = header.serialized_len(SerializeOptions::default())?;
+  let header_len = u32::try_from(header_len)
+    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "header too large"))?;
+  Ok(content_offset(header_len))
+}
+
+type FileIter<'a> = <&'a FileMap as IntoIterator>::IntoIter;
+
+/// Incremental serializer of a header, producing the same bytes as
+/// [`Directory::serialize_with`] in bounded chunks.
+pub(crate) struct HeaderChunks<'a> {
+  options: SerializeOptions,
+  root: Option<&'a Directory>,
+  // Directories being serialized, and whether any entry has been written in
+  // each.
+  stack: Vec<(FileIter<'a>, bool)>,
+}
+
+impl<'a> HeaderChunks<'a> {
+  pub(crate) fn new(dir: &'a Directory, options: SerializeOptions) -> Self {
+    Self {
+      options,
+      root: Some(dir),
+      stack: Vec::new(),
+    }
+  }
+
+  /// Appends serialized bytes to `buf` until it holds at least `min_len`
+  /// bytes, or the header ends. `buf` is left untouched after the end.
+  pub(crate) fn fill(&mut self, buf: &mut Vec<u8>, min_len: usize) -> io::Result<()> {
+    if let Some(root) = self.root.take() {
+      buf.extend_from_slice(br#"{"files":{"#);
+      self.stack.push((root.files.iter(), false));
+    }
+    while buf.len() < min_len {
+      let Some((iter, written)) = self.stack.last_mut() else {
+        break;
+      };
+      let Some((name, entry)) = iter.next() else {
+        buf.extend_from_slice(b"}}");
+        self.stack.pop();
+        continue;
+      };
+      if *written {
+        buf.push(b',');
+      }
+      *written = true;
+      serde_json::to_writer(&mut *buf, name)?;
+      buf.push(b':');
+      match entry {
+        Entry::File(file) => {
+          let file = SerializeWith {
+            value: file,
+            options: self.options,
+          };
+          serde_json::to_writer(&mut *buf, &file)?;
+        }
+        Entry::Directory(dir) => {
+          buf.extend_from_slice(br#"{"files":{"#);
+          self.stack.push((dir.files.iter(), false));
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+struct SerializeWith<'a, T> {
+  value: &'a T,
+  options: SerializeOptions,
+}
+
+impl<'a, T> SerializeWith<'a, T> {
+  fn wrap<U>(&self, value: &'a U) -> SerializeWith<'a, U> {
+    SerializeWith {
+      value,
+      options: self.options,
+    }
+  }
+}
+
+impl Serialize for SerializeWith<'_, Directory> {
+  fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+    let mut map = ser.serialize_map(Some(1))?;
+    map.serialize_entry("files", &self.wrap(&self.value.files))?;
+    map.end()
+  }
+}
+
+impl Serialize for SerializeWith<'_, FileMap> {
+  fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+    ser.collect_map((self.value.iter()).map(|(name, entry)| (name, self.wrap(entry))))
+  }
+}
+
+impl Serialize for SerializeWith<'_, Entry> {
+  fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+    match self.value {
+      Entry::File(file) => self.wrap(file).serialize(ser),
+      Entry::Directory(dir) => self.wrap(dir).serialize(ser),
+    }
+  }
+}
+
+impl Serialize for SerializeWith<'_, FileMetadata> {
+  fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+    let file = self.value;
+    let mut map = ser.serialize_map(None)?;
+    match file.pos {
+      FilePosition::Offset(offset) => map.serialize_entry("offset", &offset.to_string())?,
+      FilePosition::Unpacked => map.serialize_entry("unpacked", &true)?,
+    }
+    map.serialize_entry("size", &file.size)?;
+    if file.executable || self.options.always_emit_executable {
+      map.serialize_entry("executable", &file.executable)?;
+    }
+    if file.integrity.is_some() || !self.options.skip_none_integrity {
+      map.serialize_entry("integrity", &file.integrity)?;
+    }
+    if let Some(mode) = file.mode {
+      map.serialize_entry("x-mode", &mode)?;
+    }
+    for (key, value) in &file.attributes {
+      if is_attribute(key) {
+        map.serialize_entry(key, value)?;
+      }
+    }
+    map.end()
   }
 }
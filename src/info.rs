@@ -0,0 +1,129 @@
+//! Summaries of archives.
+//!
+//! [`ArchiveInfo`] collects a few facts about an archive in one pass over its
+//! header, for reporting in CLIs and dashboards. It is displayed as
+//! human-readable lines, and serialized as a flat object.
+//!
+//! [`DirectoryMetrics`] describes the shape of a header instead, for
+//! diagnosing archives that are slow to look up or serialize.
+
+use crate::header::{Directory, Entry, FilePosition};
+use serde::Serialize;
+use std::fmt::{self, Display, Formatter};
+
+/// Summary of an archive, returned by [`Archive::info`](crate::Archive::info).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveInfo {
+  /// Number of entries, directories included, excluding the root directory.
+  pub entry_count: u64,
+
+  /// Number of directories, excluding the root directory.
+  pub dir_count: u64,
+
+  /// Length of the content region.
+  ///
+  /// See [`Archive::content_len`](crate::Archive::content_len).
+  pub content_size: u64,
+
+  /// Length of the prefix, header and its padding, i.e. where the content
+  /// region starts.
+  pub header_size: u64,
+
+  /// Whether any file has integrity information.
+  pub has_integrity: bool,
+
+  /// Whether any file is stored outside the archive.
+  pub has_unpacked: bool,
+}
+
+impl ArchiveInfo {
+  pub(crate) fn new(header: &Directory, header_size: u64) -> Self {
+    let mut info = Self {
+      content_size: header.content_len(),
+      header_size,
+      ..Default::default()
+    };
+    header.visit(&mut |_, entry| {
+      info.entry_count += 1;
+      match entry {
+        Entry::Directory(_) => info.dir_count += 1,
+        Entry::File(file) => {
+          info.has_integrity |= file.integrity.is_some();
+          info.has_unpacked |= matches!(file.pos, FilePosition::Unpacked);
+        }
+      }
+    });
+    info
+  }
+}
+
+impl Display for ArchiveInfo {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let yes_no = |x| if x { "yes" } else { "no" };
+    writeln!(f, "entries:        {}", self.entry_count)?;
+    writeln!(f, "directories:    {}", self.dir_count)?;
+    writeln!(f, "files:          {}", self.entry_count - self.dir_count)?;
+    writeln!(f, "content size:   {}", self.content_size)?;
+    writeln!(f, "header size:    {}", self.header_size)?;
+    writeln!(f, "integrity:      {}", yes_no(self.has_integrity))?;
+    write!(f, "unpacked files: {}", yes_no(self.has_unpacked))
+  }
+}
+
+/// Shape of a directory tree, returned by
+/// [`Directory::metrics`](crate::header::Directory::metrics).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DirectoryMetrics {
+  /// Number of files, unpacked ones included.
+  pub file_count: u64,
+
+  /// Number of files stored outside the archive.
+  pub unpacked_count: u64,
+
+  /// Number of directories, excluding the directory itself.
+  pub dir_count: u64,
+
+  /// Number of segments of the deepest entry's path, e.g. `1` if every entry
+  /// is directly in the directory, or `0` if it is empty.
+  pub max_depth: usize,
+
+  /// Average number of entries per directory, the directory itself
+  /// included.
+  pub average_fanout: f64,
+
+  /// Largest number of entries in a single directory.
+  pub max_fanout: usize,
+
+  /// Longest full path of any entry, in bytes. The first one in pre-order
+  /// wins if several are equally long.
+  pub longest_path: String,
+}
+
+impl DirectoryMetrics {
+  pub(crate) fn new(dir: &Directory) -> Self {
+    let mut metrics = Self {
+      max_fanout: dir.files.len(),
+      ..Default::default()
+    };
+    dir.visit(&mut |path, entry| {
+      let depth = path.split('/').count();
+      metrics.max_depth = metrics.max_depth.max(depth);
+      if path.len() > metrics.longest_path.len() {
+        metrics.longest_path = path.to_owned();
+      }
+      match entry {
+        Entry::Directory(dir) => {
+          metrics.dir_count += 1;
+          metrics.max_fanout = metrics.max_fanout.max(dir.files.len());
+        }
+        Entry::File(file) => {
+          metrics.file_count += 1;
+          metrics.unpacked_count += matches!(file.pos, FilePosition::Unpacked) as u64;
+        }
+      }
+    });
+    let entries = metrics.file_count + metrics.dir_count;
+    metrics.average_fanout = entries as f64 / (metrics.dir_count + 1) as f64;
+    metrics
+  }
+}
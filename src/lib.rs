@@ -8,23 +8,74 @@
 //! - Parse archive from file or async reader
 //! - Pack archive from multiple readers, or conveniently from a folder.
 //! - Write and check integrity
-//!
-//! Currently not supported:
-//! - Unpacked files
+//! - Resolve and write unpacked files, with configurable layout
 
+pub mod error;
+pub mod format;
 pub mod header;
+pub mod info;
+pub mod list;
+pub mod manifest;
+pub mod read_dir;
+pub mod walk;
+
+#[cfg(any(test, feature = "test-util"))]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod testing;
+
+#[cfg(feature = "watch")]
+#[cfg_attr(docsrs, doc(cfg(feature = "watch")))]
+pub mod watch;
+
+#[cfg(feature = "xattr")]
+#[cfg_attr(docsrs, doc(cfg(feature = "xattr")))]
+pub mod xattr;
 
 mod archive;
+mod archive_path;
+mod auto_traits;
+mod compare;
+mod edit;
+mod mount;
+mod tar;
+mod timeout;
+mod transform;
 mod writer;
 
-pub use archive::{check_asar_format, Archive, Duplicable, File, LocalDuplicable};
-pub use writer::Writer;
+cfg_stream! {
+  mod segmented;
+
+  pub use segmented::SegmentedReader;
+}
+
+pub use error::Error;
+
+pub use archive::{
+  check_asar_format, probe_asar_format, Archive, ArchiveOptions, CloneDuplicable, Duplicable, File,
+  LocalDuplicable, ScopedArchive, SharedArchive, MAX_PINNED_SIZE,
+};
+pub use archive_path::{ArchivePath, ArchivePathBuf, AsArchivePath};
+pub use compare::{compare, CompareMode};
+pub use edit::{EditableArchive, FileMut, HeaderChange};
+pub use mount::MountTable;
+pub use timeout::TimeoutReader;
+pub use transform::TransformReader;
+pub use writer::{ConcurrentWriter, PackOrder, WindowsNames, WritePlan, Writer};
 
 cfg_fs! {
+  pub mod embed;
   mod extract;
+  mod retry;
+  mod unpacked;
 
   pub use archive::DuplicableFile;
-  pub use writer::{pack_dir, pack_dir_into_writer};
+  pub use extract::{CaseCollision, ExtractOptions, PlannedEntry};
+  pub use retry::RetryPolicy;
+  pub use unpacked::UnpackedLayout;
+  pub use writer::{
+    pack_dir, pack_dir_into_writer, pack_dir_into_writer_with_options, pack_dir_with_options,
+    ErrorPolicy, ExecutableRule, PackCache, PackOptions, PackReport, PathOptions, SkipReason,
+  };
 
   cfg_stream! {
     pub use writer::pack_dir_into_stream;
@@ -32,7 +83,29 @@ cfg_fs! {
 }
 
 cfg_integrity! {
-  const BLOCK_SIZE: u32 = 4_194_304;
+  mod verify;
+
+  pub use archive::{VerifiedFile, Verification};
+  pub use verify::{verify_reader, VerifyReport};
+
+  cfg_fs! {
+    pub use verify::verify_file;
+    pub use writer::pack_dir_with_header_hash;
+  }
+
+  cfg_stream! {
+    pub use writer::DigestHandle;
+  }
+}
+
+/// Normalizes a path inside an archive, the same way every API of this crate
+/// does when reading or writing.
+///
+/// Segments are separated by `/`. Empty and `.` segments are removed, and `..`
+/// removes the previous segment, never going above the root. Backslashes are
+/// not separators.
+pub fn normalize_path(path: &str) -> String {
+  split_path(path).join("/")
 }
 
 fn split_path(path: &str) -> Vec<&str> {
@@ -49,6 +122,21 @@ fn split_path(path: &str) -> Vec<&str> {
     })
 }
 
+/// Loads an archive packed by [`embed::embed_dir`] into the binary.
+///
+/// Expands to an [`io::Result`](std::io::Result) of
+/// `Archive<Cursor<&'static [u8]>>`, parsed with [`Archive::parse_slice`].
+/// Files' content can then be borrowed for `'static` with
+/// [`Archive::get_slice`].
+#[cfg(feature = "fs")]
+#[cfg_attr(docsrs, doc(cfg(feature = "fs")))]
+#[macro_export]
+macro_rules! asar_embed {
+  ($name:expr) => {
+    $crate::Archive::parse_slice(include_bytes!(concat!(env!("OUT_DIR"), "/", $name)))
+  };
+}
+
 mod private {
   pub trait Sealed {}
   impl<T> Sealed for T {}
@@ -78,6 +166,18 @@ macro_rules! cfg_integrity {
   }
 }
 
+#[macro_export]
+#[doc(hidden)]
+macro_rules! cfg_compressed_header {
+  ($($item:item)*) => {
+    $(
+      #[cfg(feature = "compressed-header")]
+      #[cfg_attr(docsrs, doc(cfg(feature = "compressed-header")))]
+      $item
+    )*
+  }
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! cfg_stream {
@@ -0,0 +1,407 @@
+//! Listing entries of an archive.
+//!
+//! [`Listing`] collects every entry under a [`Directory`] in sorted order, and
+//! formats them according to [`ListFormat`] when displayed. [`render_index`]
+//! renders a single directory as an autoindex-style page for HTTP servers.
+
+use crate::header::{Directory, Entry, FilePosition};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// Output format of a [`Listing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+  /// One path per line.
+  Short,
+
+  /// `ls -l` style, with flags and sizes.
+  ///
+  /// Flags are, in order: `d` for directories, `x` for executables, `h` for
+  /// files with integrity information and `u` for unpacked files. Sizes are
+  /// shown like `ls -lh` if `human_readable` is set.
+  Long { human_readable: bool },
+
+  /// Machine-readable JSON array.
+  Json,
+}
+
+/// Kind of an entry in a [`Listing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+  File,
+  Directory,
+}
+
+/// A single row of a [`Listing`].
+#[derive(Debug, Clone, Serialize)]
+pub struct ListEntry {
+  /// Full path of the entry, separated by `/`.
+  pub path: String,
+  #[serde(rename = "type")]
+  pub kind: EntryKind,
+  /// Size of the file, or `0` for directories.
+  pub size: u64,
+  pub executable: bool,
+  pub integrity: bool,
+  pub unpacked: bool,
+}
+
+/// Sorted list of entries in a directory, recursively.
+#[derive(Debug, Clone)]
+pub struct Listing {
+  entries: Vec<ListEntry>,
+  format: ListFormat,
+}
+
+impl Listing {
+  /// Lists every entry under `dir`.
+  pub fn new(dir: &Directory, format: ListFormat) -> Self {
+    let mut entries = Vec::new();
+    collect(dir, "", &mut entries);
+    Self { entries, format }
+  }
+
+  /// Returns the listed entries.
+  pub fn entries(&self) -> &[ListEntry] {
+    &self.entries
+  }
+
+  /// Changes the output format.
+  pub fn with_format(mut self, format: ListFormat) -> Self {
+    self.format = format;
+    self
+  }
+
+  /// Sorts entries by name within each directory according to `order`.
+  ///
+  /// Directories stay before their content. Names equal under `order` are
+  /// ordered bytewise, so the result never depends on the header's order.
+  pub fn sorted(mut self, order: SortOrder) -> Self {
+    self.entries.sort_by(|a, b| {
+      let (mut a, mut b) = (a.path.split('/'), b.path.split('/'));
+      loop {
+        match (a.next(), b.next()) {
+          (Some(x), Some(y)) => match order.compare(x, y) {
+            Ordering::Equal => continue,
+            result => return result,
+          },
+          (x, y) => return x.is_some().cmp(&y.is_some()),
+        }
+      }
+    });
+    self
+  }
+
+  /// Keeps files matching `filter`, and directories containing any of them.
+  pub fn filtered(mut self, filter: &ListFilter) -> Self {
+    self
+      .entries
+      .retain(|x| x.kind == EntryKind::Directory || filter.matches(x));
+    let mut keep = vec![false; self.entries.len()];
+    let mut parents: Vec<usize> = Vec::new();
+    for (i, entry) in self.entries.iter().enumerate() {
+      let depth = entry.path.matches('/').count();
+      parents.truncate(depth);
+      if entry.kind == EntryKind::File {
+        keep[i] = true;
+        parents.iter().for_each(|&j| keep[j] = true);
+      } else {
+        parents.push(i);
+      }
+    }
+    let mut keep = keep.into_iter();
+    self.entries.retain(|_| keep.next().unwrap());
+    self
+  }
+}
+
+/// Order of names in [`Listing::sorted`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+  /// Compare names byte by byte, like the default listing.
+  #[default]
+  Bytewise,
+
+  /// Compare runs of ASCII digits by their numeric value, so that `file2`
+  /// comes before `file10`.
+  Natural,
+
+  /// Compare lowercased names.
+  CaseInsensitive,
+}
+
+impl SortOrder {
+  /// Compares two names, falling back to bytewise order for ties.
+  pub fn compare(self, a: &str, b: &str) -> Ordering {
+    let result = match self {
+      Self::Bytewise => Ordering::Equal,
+      Self::Natural => compare_natural(a, b),
+      Self::CaseInsensitive => a.to_lowercase().cmp(&b.to_lowercase()),
+    };
+    result.then_with(|| a.cmp(b))
+  }
+}
+
+fn compare_natural(a: &str, b: &str) -> Ordering {
+  let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+  loop {
+    match (a.first(), b.first()) {
+      (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+        let (x, rest_a) = split_digits(a);
+        let (y, rest_b) = split_digits(b);
+        let (x, y) = (trim_zeros(x), trim_zeros(y));
+        match x.len().cmp(&y.len()).then_with(|| x.cmp(y)) {
+          Ordering::Equal => (a, b) = (rest_a, rest_b),
+          result => return result,
+        }
+      }
+      (Some(x), Some(y)) => match x.cmp(y) {
+        Ordering::Equal => (a, b) = (&a[1..], &b[1..]),
+        result => return result,
+      },
+      (x, y) => return x.is_some().cmp(&y.is_some()),
+    }
+  }
+}
+
+fn split_digits(s: &[u8]) -> (&[u8], &[u8]) {
+  let len = s.iter().take_while(|x| x.is_ascii_digit()).count();
+  s.split_at(len)
+}
+
+fn trim_zeros(s: &[u8]) -> &[u8] {
+  let len = s.iter().take_while(|x| **x == b'0').count();
+  &s[len..]
+}
+
+/// Filter of files in [`Listing::filtered`].
+///
+/// Every condition must hold for a file to be kept.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+  /// File extensions to keep, without the leading `.`, compared
+  /// case-insensitively. Empty keeps every extension.
+  pub extensions: Vec<String>,
+
+  /// Minimum size of files, inclusive.
+  pub min_size: Option<u64>,
+
+  /// Maximum size of files, inclusive.
+  pub max_size: Option<u64>,
+}
+
+impl ListFilter {
+  fn matches(&self, entry: &ListEntry) -> bool {
+    let name = entry.path.rsplit('/').next().unwrap();
+    let extension = match name.rfind('.') {
+      Some(i) if i > 0 => &name[i + 1..],
+      _ => "",
+    };
+    (self.extensions.is_empty()
+      || (self.extensions.iter()).any(|x| x.eq_ignore_ascii_case(extension)))
+      && self.min_size.is_none_or(|min| entry.size >= min)
+      && self.max_size.is_none_or(|max| entry.size <= max)
+  }
+}
+
+fn collect(dir: &Directory, prefix: &str, entries: &mut Vec<ListEntry>) {
+  let mut names: Vec<_> = dir.files.iter().collect();
+  names.sort_unstable_by_key(|(name, _)| *name);
+  for (name, entry) in names {
+    let path = if prefix.is_empty() {
+      name.to_string()
+    } else {
+      format!("{prefix}/{name}")
+    };
+    match entry {
+      Entry::File(file) => entries.push(ListEntry {
+        path,
+        kind: EntryKind::File,
+        size: file.size,
+        executable: file.executable,
+        integrity: file.integrity.is_some(),
+        unpacked: matches!(file.pos, FilePosition::Unpacked),
+      }),
+      Entry::Directory(dir) => {
+        entries.push(ListEntry {
+          path: path.clone(),
+          kind: EntryKind::Directory,
+          size: 0,
+          executable: false,
+          integrity: false,
+          unpacked: false,
+        });
+        collect(dir, &path, entries);
+      }
+    }
+  }
+}
+
+fn flag(set: bool, c: char) -> char {
+  if set {
+    c
+  } else {
+    '-'
+  }
+}
+
+/// Formats `size` like `ls -lh` does, e.g. `512`, `1.5K`, `23M`.
+pub fn human_size(size: u64) -> String {
+  const UNITS: [char; 6] = ['K', 'M', 'G', 'T', 'P', 'E'];
+  if size < 1024 {
+    return size.to_string();
+  }
+  let mut value = size as f64;
+  let mut unit = 0;
+  loop {
+    value /= 1024.0;
+    if value < 1024.0 || unit == UNITS.len() - 1 {
+      break;
+    }
+    unit += 1;
+  }
+  if value < 10.0 {
+    format!("{value:.1}{}", UNITS[unit])
+  } else {
+    format!("{value:.0}{}", UNITS[unit])
+  }
+}
+
+impl Display for Listing {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    match self.format {
+      ListFormat::Short => {
+        for entry in &self.entries {
+          writeln!(f, "{}", entry.path)?;
+        }
+      }
+      ListFormat::Long { human_readable } => {
+        for entry in &self.entries {
+          let size = if human_readable {
+            human_size(entry.size)
+          } else {
+            entry.size.to_string()
+          };
+          writeln!(
+            f,
+            "{}{}{}{} {size:>12} {}",
+            flag(entry.kind == EntryKind::Directory, 'd'),
+            flag(entry.executable, 'x'),
+            flag(entry.integrity, 'h'),
+            flag(entry.unpacked, 'u'),
+            entry.path,
+          )?;
+        }
+      }
+      ListFormat::Json => {
+        let json = serde_json::to_string(&self.entries).map_err(|_| fmt::Error)?;
+        writeln!(f, "{json}")?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Output format of [`render_index`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+  /// HTML page like nginx's `autoindex`.
+  Html,
+
+  /// JSON array like nginx's `autoindex_format json`, with `name`, `type` and
+  /// `size` of each entry.
+  Json,
+}
+
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+  name: &'a str,
+  #[serde(rename = "type")]
+  kind: EntryKind,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  size: Option<u64>,
+}
+
+/// Renders direct children of `dir`, located at `path` in the archive, as a
+/// directory index.
+///
+/// Directories are listed before files, each sorted by name. Links in HTML
+/// are relative, so the page must be served at the directory's URL with a
+/// trailing slash.
+pub fn render_index(dir: &Directory, path: &str, format: IndexFormat) -> String {
+  let mut entries: Vec<_> = (dir.files.iter())
+    .map(|(name, entry)| match entry {
+      Entry::File(file) => IndexEntry {
+        name,
+        kind: EntryKind::File,
+        size: Some(file.size),
+      },
+      Entry::Directory(_) => IndexEntry {
+        name,
+        kind: EntryKind::Directory,
+        size: None,
+      },
+    })
+    .collect();
+  entries.sort_unstable_by_key(|x| (x.kind != EntryKind::Directory, x.name));
+
+  match format {
+    IndexFormat::Json => serde_json::to_string(&entries).unwrap(),
+    IndexFormat::Html => {
+      let title = if path.is_empty() {
+        "Index of /".to_string()
+      } else {
+        format!("Index of /{}/", escape_html(path))
+      };
+      let mut result =
+        format!("<html>\n<head><title>{title}</title></head>\n<body>\n<h1>{title}</h1><hr><pre>");
+      if !path.is_empty() {
+        result += "<a href=\"../\">../</a>\n";
+      }
+      for entry in entries {
+        let (suffix, size) = match entry.size {
+          Some(size) => ("", size.to_string()),
+          None => ("/", "-".to_string()),
+        };
+        let name = format!("{}{suffix}", entry.name);
+        let padding = " ".repeat(50usize.saturating_sub(name.chars().count()) + 1);
+        result += &format!(
+          "<a href=\"{}{suffix}\">{}</a>{padding}{size:>20}\n",
+          encode_uri_component(entry.name),
+          escape_html(&name),
+        );
+      }
+      result += "</pre><hr></body>\n</html>\n";
+      result
+    }
+  }
+}
+
+fn escape_html(s: &str) -> String {
+  let mut result = String::with_capacity(s.len());
+  for c in s.chars() {
+    match c {
+      '&' => result += "&amp;",
+      '<' => result += "&lt;",
+      '>' => result += "&gt;",
+      '"' => result += "&quot;",
+      '\'' => result += "&#39;",
+      _ => result.push(c),
+    }
+  }
+  result
+}
+
+/// Percent-encodes `s` for use as a single segment of a relative URL.
+fn encode_uri_component(s: &str) -> String {
+  let mut result = String::with_capacity(s.len());
+  for b in s.bytes() {
+    match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => result.push(b as char),
+      _ => result += &format!("%{b:02X}"),
+    }
+  }
+  result
+}
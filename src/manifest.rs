@@ -0,0 +1,155 @@
+//! Manifests of archive content, for comparing archives against a known
+//! state.
+//!
+//! A [`Manifest`] records paths, sizes and hashes of every file in an archive.
+//! It can be exported from an [`Archive`](crate::Archive), saved (e.g. as a
+//! JSON lockfile), and later used to check whether another archive matches.
+//!
+//! An [`EmbeddedManifest`] is a manifest stored inside the archive itself at
+//! [`MANIFEST_PATH`], together with when and by what it was packed, for
+//! tracking provenance of deployed bundles.
+
+use crate::header::{Directory, Entry, Hash};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Path of the manifest embedded by
+/// [`Writer::set_embed_manifest`](crate::Writer::set_embed_manifest).
+pub const MANIFEST_PATH: &str = ".asar-manifest.json";
+
+/// Paths, sizes and hashes of files in an archive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+  /// Files, keyed by their full path separated by `/`.
+  pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// A file recorded in a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+  pub size: u64,
+
+  /// Hash of the entire file from its integrity information, if any.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hash: Option<Hash>,
+}
+
+/// Manifest embedded in an archive at [`MANIFEST_PATH`], read back with
+/// [`Archive::manifest`](crate::Archive::manifest).
+///
+/// Serialized as a JSON object with `packed_at`, `tool` and the manifest's
+/// `files`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddedManifest {
+  /// When the archive was packed, in seconds since the Unix epoch.
+  pub packed_at: u64,
+
+  /// Name and version of the tool that packed the archive, e.g.
+  /// `hive-asar 0.1.0`.
+  pub tool: String,
+
+  /// Every file in the archive, except the manifest itself.
+  #[serde(flatten)]
+  pub manifest: Manifest,
+}
+
+impl EmbeddedManifest {
+  /// Creates a manifest packed now by this crate.
+  pub fn new(manifest: Manifest) -> Self {
+    let packed_at = (SystemTime::now().duration_since(UNIX_EPOCH)).map_or(0, |x| x.as_secs());
+    Self {
+      packed_at,
+      tool: concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")).into(),
+      manifest,
+    }
+  }
+}
+
+/// A difference between an archive and a [`Manifest`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestMismatch {
+  /// The file is in the manifest but not in the archive.
+  Missing { path: String },
+
+  /// The file is in the archive but not in the manifest.
+  Unexpected { path: String },
+
+  /// The file's size differs.
+  Size {
+    path: String,
+    expected: u64,
+    actual: u64,
+  },
+
+  /// The file's hash differs, or is present on only one side.
+  Hash {
+    path: String,
+    expected: Option<Hash>,
+    actual: Option<Hash>,
+  },
+}
+
+impl Manifest {
+  /// Collects every file under `dir` into a manifest.
+  pub fn from_header(dir: &Directory) -> Self {
+    let mut manifest = Self::default();
+    dir.visit(&mut |path, entry| {
+      if let Entry::File(file) = entry {
+        let hash = file.integrity.as_ref().map(|x| x.hash.clone());
+        let entry = ManifestEntry {
+          size: file.size,
+          hash,
+        };
+        manifest.files.insert(path.into(), entry);
+      }
+    });
+    manifest
+  }
+
+  /// Compares `actual` against this manifest.
+  ///
+  /// Mismatches are ordered by path. An empty list means they are identical.
+  pub fn compare(&self, actual: &Self) -> Vec<ManifestMismatch> {
+    let mut mismatches = Vec::new();
+    for (path, expected) in &self.files {
+      let Some(actual) = actual.files.get(path) else {
+        mismatches.push(ManifestMismatch::Missing { path: path.clone() });
+        continue;
+      };
+      if expected.size != actual.size {
+        mismatches.push(ManifestMismatch::Size {
+          path: path.clone(),
+          expected: expected.size,
+          actual: actual.size,
+        });
+      }
+      if expected.hash != actual.hash {
+        mismatches.push(ManifestMismatch::Hash {
+          path: path.clone(),
+          expected: expected.hash.clone(),
+          actual: actual.hash.clone(),
+        });
+      }
+    }
+    for path in actual.files.keys() {
+      if !self.files.contains_key(path) {
+        mismatches.push(ManifestMismatch::Unexpected { path: path.clone() });
+      }
+    }
+    mismatches.sort_by(|a, b| a.path().cmp(b.path()));
+    mismatches
+  }
+}
+
+impl ManifestMismatch {
+  /// Path of the mismatched file.
+  pub fn path(&self) -> &str {
+    match self {
+      Self::Missing { path }
+      | Self::Unexpected { path }
+      | Self::Size { path, .. }
+      | Self::Hash { path, .. } => path,
+    }
+  }
+}
@@ -0,0 +1,144 @@
+//! Serving several archives under path prefixes.
+
+use crate::header::Entry;
+use crate::{normalize_path, Duplicable, File, LocalDuplicable, SharedArchive};
+use std::fmt::{self, Debug, Formatter};
+use tokio::io::{self, AsyncRead, AsyncSeek};
+
+/// Table mapping path prefixes to archives, e.g. `app` to `app.asar` and
+/// `vendor` to `vendor.asar`, resolving paths against the archive mounted
+/// at the longest matching prefix.
+///
+/// Prefixes are normalized with [`normalize_path`], and only match whole
+/// segments, so `app` matches `app/index.html` but not `apple/index.html`.
+/// An archive mounted at the empty prefix catches every path no other
+/// prefix matches.
+///
+/// ```
+/// # async fn f() -> std::io::Result<()> {
+/// use hive_asar::{MountTable, SharedArchive};
+///
+/// let mut table = MountTable::new();
+/// table.mount("/app", SharedArchive::new_from_file("app.asar").await?);
+/// table.mount("/vendor", SharedArchive::new_from_file("vendor.asar").await?);
+/// let file = table.get_owned("/vendor/lib.js").await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MountTable<R: AsyncRead + AsyncSeek + Unpin> {
+  /// Sorted by number of segments, longest first.
+  mounts: Vec<(String, SharedArchive<R>)>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> MountTable<R> {
+  /// Creates an empty table.
+  pub fn new() -> Self {
+    Self { mounts: Vec::new() }
+  }
+
+  /// Mounts `archive` at `prefix`, returning the archive mounted there
+  /// before, if any.
+  pub fn mount(
+    &mut self,
+    prefix: &str,
+    archive: impl Into<SharedArchive<R>>,
+  ) -> Option<SharedArchive<R>> {
+    let prefix = normalize_path(prefix);
+    let archive = archive.into();
+    if let Some((_, mounted)) = self.mounts.iter_mut().find(|(x, _)| *x == prefix) {
+      return Some(std::mem::replace(mounted, archive));
+    }
+    let depth = segment_count(&prefix);
+    let index = (self.mounts).partition_point(|(x, _)| segment_count(x) >= depth);
+    self.mounts.insert(index, (prefix, archive));
+    None
+  }
+
+  /// Unmounts the archive at `prefix`, returning it.
+  pub fn unmount(&mut self, prefix: &str) -> Option<SharedArchive<R>> {
+    let prefix = normalize_path(prefix);
+    let index = self.mounts.iter().position(|(x, _)| *x == prefix)?;
+    Some(self.mounts.remove(index).1)
+  }
+
+  /// Iterates over mounted prefixes and archives, longest prefix first.
+  pub fn mounts(&self) -> impl Iterator<Item = (&str, &SharedArchive<R>)> {
+    self
+      .mounts
+      .iter()
+      .map(|(prefix, archive)| (&**prefix, archive))
+  }
+
+  /// Finds the archive `path` belongs to, returning it together with the
+  /// normalized path inside it.
+  pub fn resolve(&self, path: &str) -> Option<(&SharedArchive<R>, String)> {
+    let path = normalize_path(path);
+    self.mounts.iter().find_map(|(prefix, archive)| {
+      let rest = match path.strip_prefix(&**prefix)? {
+        _ if prefix.is_empty() => &path,
+        "" => "",
+        rest => rest.strip_prefix('/')?,
+      };
+      Some((archive, rest.to_owned()))
+    })
+  }
+
+  /// Returns the entry at `path`.
+  ///
+  /// Mount points are the root directories of their archives.
+  pub fn get_entry(&self, path: &str) -> Option<&Entry> {
+    let (archive, path) = self.resolve(path)?;
+    archive.get_entry(&path)
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Duplicable + Unpin> MountTable<R> {
+  /// Opens the file at `path` from the archive it is mounted in.
+  ///
+  /// Fails with [`NotFound`](io::ErrorKind::NotFound) if no prefix matches.
+  /// See [`Archive::get_owned`](crate::Archive::get_owned).
+  pub async fn get_owned(&self, path: &str) -> io::Result<File<R>> {
+    let (archive, path) = self.resolve(path).ok_or(io::ErrorKind::NotFound)?;
+    archive.get_owned(&path).await
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + LocalDuplicable + Unpin> MountTable<R> {
+  /// Opens the file at `path` from the archive it is mounted in, without
+  /// `Sync`.
+  ///
+  /// See [`MountTable::get_owned`].
+  pub async fn get_owned_local(&self, path: &str) -> io::Result<File<R>> {
+    let (archive, path) = self.resolve(path).ok_or(io::ErrorKind::NotFound)?;
+    archive.get_owned_local(&path).await
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Default for MountTable<R> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Clone for MountTable<R> {
+  fn clone(&self) -> Self {
+    Self {
+      mounts: self.mounts.clone(),
+    }
+  }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Debug for MountTable<R> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_list()
+      .entries(self.mounts.iter().map(|(prefix, _)| prefix))
+      .finish()
+  }
+}
+
+fn segment_count(prefix: &str) -> usize {
+  match prefix {
+    "" => 0,
+    _ => prefix.split('/').count(),
+  }
+}
@@ -0,0 +1,96 @@
+//! Listing a single directory of an archive, mirroring [`tokio::fs::ReadDir`].
+//!
+//! Code written against `tokio::fs::read_dir` can usually be ported by
+//! replacing the call with [`Archive::read_dir`](crate::Archive::read_dir).
+
+use crate::header::{Directory, Entry, FileMap};
+use tokio::io;
+
+/// Entries of a directory in an archive, returned by
+/// [`Archive::read_dir`](crate::Archive::read_dir).
+///
+/// Like [`tokio::fs::ReadDir`], entries are yielded in no particular order.
+#[derive(Debug)]
+pub struct AsarReadDir<'a> {
+  parent: String,
+  entries: <&'a FileMap as IntoIterator>::IntoIter,
+}
+
+impl<'a> AsarReadDir<'a> {
+  pub(crate) fn new(parent: String, dir: &'a Directory) -> Self {
+    Self {
+      parent,
+      entries: dir.files.iter(),
+    }
+  }
+
+  /// Returns the next entry in the directory.
+  ///
+  /// Never fails; the [`io::Result`] is kept for parity with
+  /// [`tokio::fs::ReadDir::next_entry`].
+  pub async fn next_entry(&mut self) -> io::Result<Option<AsarDirEntry<'a>>> {
+    Ok(self.next())
+  }
+}
+
+impl<'a> Iterator for AsarReadDir<'a> {
+  type Item = AsarDirEntry<'a>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let (name, entry) = self.entries.next()?;
+    let path = if self.parent.is_empty() {
+      name.to_string()
+    } else {
+      format!("{}/{name}", self.parent)
+    };
+    Some(AsarDirEntry { path, entry })
+  }
+}
+
+/// An entry in an [`AsarReadDir`], mirroring [`tokio::fs::DirEntry`].
+#[derive(Debug, Clone)]
+pub struct AsarDirEntry<'a> {
+  path: String,
+  entry: &'a Entry,
+}
+
+impl<'a> AsarDirEntry<'a> {
+  /// Returns the full path of the entry in the archive, separated by `/`.
+  pub fn path(&self) -> &str {
+    &self.path
+  }
+
+  /// Returns the name of the entry.
+  pub fn file_name(&self) -> &str {
+    self.path.rsplit('/').next().unwrap_or_default()
+  }
+
+  /// Returns the type of the entry.
+  pub async fn file_type(&self) -> io::Result<AsarFileType> {
+    Ok(AsarFileType(matches!(self.entry, Entry::Directory(_))))
+  }
+
+  /// Returns the header entry, which serves as the entry's metadata.
+  pub async fn metadata(&self) -> io::Result<&'a Entry> {
+    Ok(self.entry)
+  }
+}
+
+/// Type of an [`AsarDirEntry`], mirroring [`std::fs::FileType`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsarFileType(bool);
+
+impl AsarFileType {
+  pub fn is_dir(&self) -> bool {
+    self.0
+  }
+
+  pub fn is_file(&self) -> bool {
+    !self.0
+  }
+
+  /// Always `false`, since archives do not store symbolic links.
+  pub fn is_symlink(&self) -> bool {
+    false
+  }
+}
@@ -0,0 +1,163 @@
+//! Retrying of transient I/O errors when packing and extracting.
+
+use pin_project::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{sleep, Sleep};
+
+/// How to retry transient I/O errors, set in
+/// [`PackOptions::retry`](crate::PackOptions::retry) and
+/// [`ExtractOptions::retry`](crate::ExtractOptions::retry).
+///
+/// Long-running jobs on network file systems or busy CI runners may hit
+/// errors that go away by themselves. Operations failing with one of them,
+/// see [`RetryPolicy::is_transient`], are retried after a delay doubling on
+/// each attempt, up to `max_backoff`. Successful operations reset the count,
+/// so `max_retries` bounds consecutive failures only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+  /// Maximum number of consecutive retries of an operation.
+  pub max_retries: u32,
+
+  /// Delay before the first retry.
+  pub initial_backoff: Duration,
+
+  /// Maximum delay between retries.
+  pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      max_retries: 5,
+      initial_backoff: Duration::from_millis(100),
+      max_backoff: Duration::from_secs(5),
+    }
+  }
+}
+
+impl RetryPolicy {
+  /// Whether `error` is considered transient, i.e. `EINTR`, `EAGAIN` or
+  /// `ETIMEDOUT`.
+  pub fn is_transient(error: &io::Error) -> bool {
+    matches!(
+      error.kind(),
+      io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+  }
+
+  fn backoff(&self, attempt: u32) -> Duration {
+    let factor = 1u32.checked_shl(attempt).unwrap_or(u32::MAX);
+    (self.initial_backoff.saturating_mul(factor)).min(self.max_backoff)
+  }
+
+  fn should_retry(&self, attempt: u32, error: &io::Error) -> bool {
+    attempt < self.max_retries && Self::is_transient(error)
+  }
+}
+
+/// Runs `f` until it succeeds or fails with a non-transient error, or
+/// retries run out.
+pub(crate) async fn retry<T, Fut>(
+  policy: Option<RetryPolicy>,
+  mut f: impl FnMut() -> Fut,
+) -> io::Result<T>
+where
+  Fut: Future<Output = io::Result<T>>,
+{
+  let mut attempt = 0;
+  loop {
+    match f().await {
+      Err(error) if policy.is_some_and(|x| x.should_retry(attempt, &error)) => {
+        sleep(policy.unwrap().backoff(attempt)).await;
+        attempt += 1;
+      }
+      result => return result,
+    }
+  }
+}
+
+/// Reader and writer retrying operations failed with transient errors.
+///
+/// A failed read or write has not consumed anything, so it is simply polled
+/// again after the delay. Without a policy, it is a plain wrapper.
+#[pin_project]
+#[derive(Debug)]
+pub(crate) struct Retry<T> {
+  #[pin]
+  inner: T,
+  policy: Option<RetryPolicy>,
+  attempt: u32,
+  delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<T> Retry<T> {
+  pub fn new(inner: T, policy: Option<RetryPolicy>) -> Self {
+    Self {
+      inner,
+      policy,
+      attempt: 0,
+      delay: None,
+    }
+  }
+
+  pub fn get_ref(&self) -> &T {
+    &self.inner
+  }
+
+  fn poll_with<U>(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    mut f: impl FnMut(Pin<&mut T>, &mut Context<'_>) -> Poll<io::Result<U>>,
+  ) -> Poll<io::Result<U>> {
+    let mut this = self.project();
+    loop {
+      if let Some(delay) = this.delay {
+        ready!(delay.as_mut().poll(cx));
+        *this.delay = None;
+      }
+      match ready!(f(this.inner.as_mut(), cx)) {
+        Err(error)
+          if this
+            .policy
+            .is_some_and(|x| x.should_retry(*this.attempt, &error)) =>
+        {
+          let backoff = this.policy.unwrap().backoff(*this.attempt);
+          *this.delay = Some(Box::pin(sleep(backoff)));
+          *this.attempt += 1;
+        }
+        result => {
+          *this.attempt = 0;
+          return Poll::Ready(result);
+        }
+      }
+    }
+  }
+}
+
+impl<T: AsyncRead> AsyncRead for Retry<T> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    self.poll_with(cx, |inner, cx| inner.poll_read(cx, buf))
+  }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Retry<T> {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    self.poll_with(cx, |inner, cx| inner.poll_write(cx, buf))
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    self.poll_with(cx, |inner, cx| inner.poll_flush(cx))
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    self.poll_with(cx, |inner, cx| inner.poll_shutdown(cx))
+  }
+}
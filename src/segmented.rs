@@ -0,0 +1,144 @@
+//! Reader over a chain of in-memory segments.
+
+use crate::{Duplicable, LocalDuplicable};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{self, AsyncRead, AsyncSeek, ReadBuf};
+
+/// Reader over a sequence of [`Bytes`] segments, as if they were
+/// concatenated.
+///
+/// Useful for archives assembled from frames received over the network,
+/// which can then be parsed without copying them into one contiguous
+/// buffer:
+///
+/// ```
+/// # async fn f(frames: Vec<bytes::Bytes>) -> std::io::Result<()> {
+/// use hive_asar::{Archive, SegmentedReader};
+///
+/// let archive = Archive::new(SegmentedReader::new(frames)).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Cloning is cheap and gives an independent position, so it implements
+/// [`Duplicable`] and [`LocalDuplicable`] by cloning.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentedReader {
+  segments: Arc<[Bytes]>,
+  /// Offset of each segment's start in the whole content.
+  starts: Arc<[u64]>,
+  len: u64,
+  pos: u64,
+}
+
+impl SegmentedReader {
+  /// Creates a reader over `segments`, positioned at the start.
+  pub fn new(segments: impl IntoIterator<Item = Bytes>) -> Self {
+    let segments: Arc<[Bytes]> = segments.into_iter().filter(|x| !x.is_empty()).collect();
+    let mut len = 0;
+    let starts = (segments.iter())
+      .map(|x| {
+        let start = len;
+        len += x.len() as u64;
+        start
+      })
+      .collect();
+    Self {
+      segments,
+      starts,
+      len,
+      pos: 0,
+    }
+  }
+
+  /// Returns the total length of all segments.
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  /// Whether there is no content at all.
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Returns the current position.
+  pub fn position(&self) -> u64 {
+    self.pos
+  }
+
+  /// Returns the segments, with empty ones left out.
+  pub fn segments(&self) -> &[Bytes] {
+    &self.segments
+  }
+}
+
+impl From<Vec<Bytes>> for SegmentedReader {
+  fn from(segments: Vec<Bytes>) -> Self {
+    Self::new(segments)
+  }
+}
+
+impl FromIterator<Bytes> for SegmentedReader {
+  fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Self {
+    Self::new(iter)
+  }
+}
+
+impl AsyncRead for SegmentedReader {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    _cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.get_mut();
+    if this.pos >= this.len {
+      return Poll::Ready(Ok(()));
+    }
+    let mut index = this.starts.partition_point(|&x| x <= this.pos) - 1;
+    while buf.remaining() > 0 && index < this.segments.len() {
+      let from = (this.pos - this.starts[index]) as usize;
+      let segment = &this.segments[index][from..];
+      let len = segment.len().min(buf.remaining());
+      buf.put_slice(&segment[..len]);
+      this.pos += len as u64;
+      index += 1;
+    }
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl AsyncSeek for SegmentedReader {
+  fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+    let this = self.get_mut();
+    let pos = match position {
+      SeekFrom::Start(pos) => Some(pos),
+      SeekFrom::Current(pos) => this.pos.checked_add_signed(pos),
+      SeekFrom::End(pos) => this.len.checked_add_signed(pos),
+    };
+    this.pos = pos.ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+    Ok(())
+  }
+
+  fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+    Poll::Ready(Ok(self.pos))
+  }
+}
+
+#[async_trait]
+impl Duplicable for SegmentedReader {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+}
+
+#[async_trait(?Send)]
+impl LocalDuplicable for SegmentedReader {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(self.clone())
+  }
+}
@@ -0,0 +1,386 @@
+//! Conversion between asar archives and tar streams.
+//!
+//! Only the subset of tar needed for plain directory trees is supported:
+//! regular files and directories, with ustar prefixes, GNU long names and
+//! pax `path`/`size` records. Other entry types are skipped.
+
+use crate::{cfg_stream, Writer};
+use std::collections::HashMap;
+use std::io::Cursor;
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+cfg_stream! {
+  use crate::header::{Entry, FileMetadata, FilePosition};
+  use crate::walk::WalkOptions;
+  use crate::Archive;
+  use bytes::{Bytes, BytesMut};
+  use futures_core::Stream;
+  use futures_util::stream::unfold;
+  use std::io::SeekFrom;
+  use tokio::io::{AsyncSeek, AsyncSeekExt};
+}
+
+const BLOCK: usize = 512;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Parses a numeric field, either in octal or in GNU base-256 encoding.
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+  if field.first().is_some_and(|x| x & 0x80 != 0) {
+    let mut result = (field[0] & 0x7f) as u64;
+    for byte in &field[1..] {
+      result =
+        (result.checked_shl(8)).ok_or_else(|| invalid("tar number overflow"))? | *byte as u64;
+    }
+    return Ok(result);
+  }
+  let s = std::str::from_utf8(field).map_err(|_| invalid("invalid tar number"))?;
+  let s = s.trim_matches(|c: char| c == '\0' || c == ' ');
+  if s.is_empty() {
+    return Ok(0);
+  }
+  u64::from_str_radix(s, 8).map_err(|_| invalid(format!("invalid tar number '{s}'")))
+}
+
+/// Length of zeros padding `size` bytes to a whole block.
+fn padding(size: u64) -> usize {
+  (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
+
+fn parse_str(field: &[u8]) -> io::Result<&str> {
+  let len = field.iter().position(|x| *x == 0).unwrap_or(field.len());
+  std::str::from_utf8(&field[..len]).map_err(|_| invalid("tar path is not valid UTF-8"))
+}
+
+/// Parses pax extended header records, returning `path` and `size` if
+/// present.
+fn parse_pax(data: &[u8]) -> io::Result<(Option<String>, Option<u64>)> {
+  let (mut path, mut size) = (None, None);
+  let mut rest = data;
+  while !rest.is_empty() {
+    let space = (rest.iter())
+      .position(|x| *x == b' ')
+      .ok_or_else(|| invalid("invalid pax record"))?;
+    let len: usize = (std::str::from_utf8(&rest[..space]).ok())
+      .and_then(|x| x.parse().ok())
+      .filter(|x| *x >= space + 2 && *x <= rest.len() && rest[*x - 1] == b'\n')
+      .ok_or_else(|| invalid("invalid pax record length"))?;
+    let record = std::str::from_utf8(&rest[space + 1..len - 1])
+      .map_err(|_| invalid("pax record is not valid UTF-8"))?;
+    if let Some((key, value)) = record.split_once('=') {
+      match key {
+        "path" => path = Some(value.to_owned()),
+        "size" => size = Some(value.parse().map_err(|_| invalid("invalid pax size"))?),
+        _ => {}
+      }
+    }
+    rest = &rest[len..];
+  }
+  Ok((path, size))
+}
+
+async fn read_padded(reader: &mut (impl AsyncRead + Unpin), size: u64) -> io::Result<Vec<u8>> {
+  let mut data = Vec::new();
+  (&mut *reader).take(size).read_to_end(&mut data).await?;
+  if (data.len() as u64) < size {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  }
+  skip_padding(reader, size).await?;
+  Ok(data)
+}
+
+async fn skip_padding(reader: &mut (impl AsyncRead + Unpin), size: u64) -> io::Result<()> {
+  reader.read_exact(&mut [0; BLOCK][..padding(size)]).await?;
+  Ok(())
+}
+
+impl Writer<Cursor<Vec<u8>>> {
+  /// Reads a tar stream entry by entry into a new writer.
+  ///
+  /// Files' content is kept in memory, so nothing is written on disk. Files
+  /// whose owner can execute them are marked executable. If a path appears
+  /// more than once, the last entry wins, like `tar -x`. Symbolic links, hard
+  /// links and other special entries are skipped.
+  pub async fn from_tar(mut reader: impl AsyncRead + Unpin) -> io::Result<Self> {
+    let mut files = Vec::<(String, Vec<u8>, bool)>::new();
+    let mut indices = HashMap::<String, usize>::new();
+    let mut dirs = Vec::new();
+    let (mut long_name, mut pax_path, mut pax_size) = (None, None, None);
+
+    let mut header = [0; BLOCK];
+    loop {
+      match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+        Err(error) => return Err(error),
+      }
+      if header.iter().all(|x| *x == 0) {
+        break;
+      }
+
+      let expected = parse_number(&header[148..156])?;
+      let checksum: u64 = (header.iter().enumerate())
+        .map(|(i, x)| if (148..156).contains(&i) { b' ' } else { *x } as u64)
+        .sum();
+      if checksum != expected {
+        return Err(invalid("tar header checksum mismatch"));
+      }
+
+      let size = pax_size.take().unwrap_or(parse_number(&header[124..136])?);
+      let path = match (long_name.take(), pax_path.take()) {
+        (_, Some(path)) | (Some(path), None) => path,
+        (None, None) => {
+          let name = parse_str(&header[..100])?;
+          let prefix = parse_str(&header[345..500])?;
+          if &header[257..262] == b"ustar" && !prefix.is_empty() {
+            format!("{prefix}/{name}")
+          } else {
+            name.to_owned()
+          }
+        }
+      };
+
+      match header[156] {
+        b'0' | 0 | b'7' => {
+          let data = read_padded(&mut reader, size).await?;
+          let executable = parse_number(&header[100..108])? & 0o100 != 0;
+          if path.ends_with('/') {
+            dirs.push(path);
+          } else if let Some(index) = indices.get(&path) {
+            files[*index] = (path, data, executable);
+          } else {
+            indices.insert(path.clone(), files.len());
+            files.push((path, data, executable));
+          }
+        }
+        b'5' => dirs.push(path),
+        b'L' => {
+          let data = read_padded(&mut reader, size).await?;
+          long_name = Some(parse_str(&data)?.to_owned());
+        }
+        b'x' => {
+          let data = read_padded(&mut reader, size).await?;
+          (pax_path, pax_size) = parse_pax(&data)?;
+        }
+        _ => {
+          read_padded(&mut reader, size).await?;
+        }
+      }
+    }
+
+    let mut writer = Self::new();
+    for dir in dirs {
+      if writer.conflicts(&dir, true) {
+        return Err(invalid(format!("'{dir}' conflicts with another entry")));
+      }
+      writer.add_empty_folder(&dir);
+    }
+    for (path, data, executable) in files {
+      if writer.conflicts(&path, false) {
+        return Err(invalid(format!("'{path}' conflicts with another entry")));
+      }
+      writer.add_tar_file(&path, data, executable).await?;
+    }
+    Ok(writer)
+  }
+
+  #[cfg(feature = "integrity")]
+  async fn add_tar_file(&mut self, path: &str, data: Vec<u8>, executable: bool) -> io::Result<()> {
+    let (size, integrity) = crate::writer::compute_integrity(&mut &data[..]).await?;
+    self.add_with_options(path, Cursor::new(data), size, executable, Some(integrity));
+    Ok(())
+  }
+
+  #[cfg(not(feature = "integrity"))]
+  async fn add_tar_file(&mut self, path: &str, data: Vec<u8>, executable: bool) -> io::Result<()> {
+    let size = data.len() as u64;
+    self.add_with_options(path, Cursor::new(data), size, executable, None);
+    Ok(())
+  }
+}
+
+cfg_stream! {
+  impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
+    /// Converts the archive into a tar byte stream.
+    ///
+    /// Entries are emitted in pre-order, sorted by name. Files are written
+    /// with mode `0755` if executable and `0644` otherwise, and a
+    /// modification time of zero. Long paths use GNU long name entries.
+    /// Unpacked files are skipped, since their content is not stored in the
+    /// archive.
+    pub fn to_tar_stream(&mut self) -> impl Stream<Item = io::Result<Bytes>> + '_ {
+      let options = WalkOptions {
+        sort: true,
+        ..Default::default()
+      };
+      let entries: Vec<_> = (self.walk_with(options))
+        .filter_map(|item| match item.entry {
+          Entry::Directory(_) => Some(TarEntry::Dir(item.path)),
+          Entry::File(FileMetadata {
+            pos: FilePosition::Offset(offset),
+            size,
+            executable,
+            ..
+          }) => Some(TarEntry::File {
+            path: item.path,
+            offset: *offset,
+            size: *size,
+            executable: *executable,
+          }),
+          Entry::File(_) => None,
+        })
+        .collect();
+      let state = TarState {
+        archive: self,
+        entries: entries.into_iter(),
+        remaining: None,
+        finished: false,
+      };
+      unfold(state, |mut state| async move {
+        let result = state.next_chunk().await.transpose()?;
+        if result.is_err() {
+          state.finished = true;
+        }
+        Some((result, state))
+      })
+    }
+  }
+
+  enum TarEntry {
+    Dir(String),
+    File {
+      path: String,
+      offset: u64,
+      size: u64,
+      executable: bool,
+    },
+  }
+
+  struct TarState<'a, R: AsyncRead + AsyncSeek + Unpin> {
+    archive: &'a mut Archive<R>,
+    entries: std::vec::IntoIter<TarEntry>,
+    /// Remaining bytes of the current file, and its size.
+    remaining: Option<(u64, u64)>,
+    finished: bool,
+  }
+
+  impl<R: AsyncRead + AsyncSeek + Unpin> TarState<'_, R> {
+    async fn next_chunk(&mut self) -> io::Result<Option<Bytes>> {
+      if self.finished {
+        return Ok(None);
+      }
+      if let Some((remaining, size)) = &mut self.remaining {
+        if *remaining > 0 {
+          let len = (*remaining).min(TAR_CHUNK_SIZE as u64) as usize;
+          let mut buf = BytesMut::zeroed(len);
+          self.archive.reader.read_exact(&mut buf).await?;
+          *remaining -= len as u64;
+          return Ok(Some(buf.freeze()));
+        }
+        let padding = padding(*size);
+        self.remaining = None;
+        if padding > 0 {
+          return Ok(Some(Bytes::from(vec![0; padding])));
+        }
+      }
+      match self.entries.next() {
+        Some(TarEntry::Dir(path)) => {
+          let header = encode_header(&format!("{path}/"), 0, 0o755, b'5');
+          Ok(Some(header.into()))
+        }
+        Some(TarEntry::File {
+          path,
+          offset,
+          size,
+          executable,
+        }) => {
+          let mode = if executable { 0o755 } else { 0o644 };
+          if size > 0 {
+            let start = self.archive.offset + offset;
+            self.archive.reader.seek(SeekFrom::Start(start)).await?;
+            self.remaining = Some((size, size));
+          }
+          Ok(Some(encode_header(&path, size, mode, b'0').into()))
+        }
+        None => {
+          self.finished = true;
+          Ok(Some(Bytes::from(vec![0; BLOCK * 2])))
+        }
+      }
+    }
+  }
+
+  /// Size of chunks of files' content in tar streams.
+  const TAR_CHUNK_SIZE: usize = 65536;
+
+  /// Encodes a ustar header, preceded by a GNU long name entry if `path` does
+  /// not fit.
+  fn encode_header(path: &str, size: u64, mode: u32, kind: u8) -> Vec<u8> {
+    let mut result = Vec::with_capacity(BLOCK);
+    let (prefix, name) = match split_ustar_path(path) {
+      Some(split) => split,
+      None => {
+        let mut long_name = path.as_bytes().to_vec();
+        long_name.push(0);
+        let len = long_name.len() as u64;
+        result.extend(header_block("", "././@LongLink", len, 0o644, b'L'));
+        long_name.resize(long_name.len() + padding(len), 0);
+        result.extend(long_name);
+        ("", &path[..floor_char_boundary(path, 100)])
+      }
+    };
+    result.extend(header_block(prefix, name, size, mode, kind));
+    result
+  }
+
+  fn floor_char_boundary(s: &str, index: usize) -> usize {
+    (0..=index.min(s.len())).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0)
+  }
+
+  /// Splits `path` into ustar's 155-byte prefix and 100-byte name.
+  fn split_ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+      return Some(("", path));
+    }
+    // Trailing slash of directories belongs to the name.
+    let search = path.strip_suffix('/').unwrap_or(path);
+    (search.match_indices('/'))
+      .map(|(i, _)| (&path[..i], &path[i + 1..]))
+      .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+  }
+
+  fn header_block(prefix: &str, name: &str, size: u64, mode: u32, kind: u8) -> [u8; BLOCK] {
+    let mut block = [0; BLOCK];
+    block[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut block[100..108], mode as u64);
+    write_octal(&mut block[108..116], 0);
+    write_octal(&mut block[116..124], 0);
+    if size < 0o77777777777 {
+      write_octal(&mut block[124..136], size);
+    } else {
+      // GNU base-256 encoding for sizes not fitting in 11 octal digits.
+      block[124] = 0x80;
+      block[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    write_octal(&mut block[136..148], 0);
+    block[156] = kind;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    block[148..156].fill(b' ');
+    let checksum: u64 = block.iter().map(|x| *x as u64).sum();
+    write_octal(&mut block[148..155], checksum);
+    block
+  }
+
+  /// Writes `value` as zero-padded octal digits followed by a NUL.
+  fn write_octal(field: &mut [u8], value: u64) {
+    let (digits, nul) = field.split_at_mut(field.len() - 1);
+    let width = digits.len();
+    digits.copy_from_slice(format!("{value:0width$o}").as_bytes());
+    nul[0] = 0;
+  }
+}
@@ -0,0 +1,542 @@
+//! Utilities for downstream crates' integration tests.
+//!
+//! Includes fixture builders generating random archives, helpers corrupting
+//! them, assertions over their content, and a seeded property check of
+//! round-trips through [`Writer`] and [`Archive`].
+//!
+//! The property check is not a `proptest` strategy: this crate does not
+//! depend on `proptest`, so trees come from a small seeded generator instead.
+//! Failures are not shrunk, but every failing seed reproduces exactly, and
+//! [`arbitrary_tree`] can be called from a `proptest` strategy over seeds.
+
+use crate::format::{content_offset, decode_prefix, PREFIX_LEN};
+use crate::header::{Directory, Entry, FilePosition};
+use crate::walk::{WalkEntry, WalkOptions};
+use crate::{Archive, Duplicable, SharedArchive, Writer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek};
+use tokio::task::JoinSet;
+
+/// Reads every packed file of `archive` from `tasks` tasks at once, and checks
+/// that each read returns the same content as a sequential one.
+///
+/// Every task walks the files in a different order and reads them in small
+/// chunks, yielding in between, so that reads over duplicated readers
+/// interleave as much as possible. [`Archive::verify_isolation`] is checked
+/// beforehand.
+///
+/// Returns [`InvalidData`](io::ErrorKind::InvalidData) on the first
+/// inconsistent read.
+///
+/// [`Archive::verify_isolation`]: crate::Archive::verify_isolation
+pub async fn concurrent_read_storm<R>(archive: &SharedArchive<R>, tasks: usize) -> io::Result<()>
+where
+  R: AsyncRead + AsyncSeek + Duplicable + Send + Sync + Unpin + 'static,
+{
+  archive.verify_isolation().await?;
+
+  let paths: Vec<String> = (archive.walk_with(WalkOptions::default()))
+    .filter(|x| matches!(x.entry, Entry::File(f) if matches!(f.pos, FilePosition::Offset(_))))
+    .map(|x| x.path)
+    .collect();
+  let mut expected = Vec::with_capacity(paths.len());
+  for path in &paths {
+    let mut content = Vec::new();
+    archive
+      .get_owned(path)
+      .await?
+      .read_to_end(&mut content)
+      .await?;
+    expected.push(content);
+  }
+
+  let expected = Arc::new(expected);
+  let paths = Arc::new(paths);
+  let mut set = JoinSet::new();
+  for task in 0..tasks {
+    let archive = archive.clone();
+    let expected = expected.clone();
+    let paths = paths.clone();
+    set.spawn(async move {
+      let len = paths.len();
+      let chunk_size = task % 7 + 1;
+      for i in 0..len {
+        let index = (i + task) % len;
+        let mut file = archive.get_owned(&paths[index]).await?;
+        let mut content = Vec::new();
+        let mut chunk = vec![0; chunk_size];
+        loop {
+          let n = file.read(&mut chunk).await?;
+          if n == 0 {
+            break;
+          }
+          content.extend_from_slice(&chunk[..n]);
+          tokio::task::yield_now().await;
+        }
+        if content != expected[index] {
+          return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+              "concurrent read of '{}' returned different content",
+              paths[index]
+            ),
+          ));
+        }
+      }
+      Ok(())
+    });
+  }
+  while let Some(result) = set.join_next().await {
+    result.map_err(io::Error::other)??;
+  }
+  Ok(())
+}
+
+/// Distribution of file sizes generated by [`random_archive`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeDistribution {
+  /// Every file has the same size.
+  Fixed(u64),
+
+  /// Sizes are uniformly distributed between `min` and `max`, inclusive.
+  Uniform { min: u64, max: u64 },
+
+  /// Magnitudes of sizes are uniformly distributed up to `max`, so most files
+  /// are small and a few are large, like in real-world archives.
+  LogUniform { max: u64 },
+}
+
+impl SizeDistribution {
+  fn sample(self, rng: &mut Rng) -> u64 {
+    match self {
+      Self::Fixed(size) => size,
+      Self::Uniform { min, max } => min + rng.below(max.saturating_sub(min).saturating_add(1)),
+      Self::LogUniform { max } => {
+        let bits = rng.below(u64::from(64 - max.leading_zeros()) + 1);
+        rng
+          .below(1u64.checked_shl(bits as _).unwrap_or(u64::MAX))
+          .min(max)
+      }
+    }
+  }
+}
+
+/// Options for [`random_archive`].
+#[derive(Debug, Clone)]
+pub struct FixtureOptions {
+  /// Number of files in the archive.
+  pub files: usize,
+
+  /// Distribution of files' sizes.
+  pub sizes: SizeDistribution,
+
+  /// Maximum number of directories a file is nested in.
+  pub max_depth: usize,
+
+  /// Whether to write integrity information of the files.
+  ///
+  /// Only takes effect with the `integrity` feature.
+  pub integrity: bool,
+
+  /// Seed of the generated content. The same options always produce the same
+  /// archive.
+  pub seed: u64,
+}
+
+impl Default for FixtureOptions {
+  fn default() -> Self {
+    Self {
+      files: 16,
+      sizes: SizeDistribution::LogUniform { max: 65536 },
+      max_depth: 2,
+      integrity: false,
+      seed: 0,
+    }
+  }
+}
+
+/// An archive generated by [`random_archive`], along with its files' content.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+  /// Serialized archive.
+  pub archive: Vec<u8>,
+
+  /// Content of every file, keyed by path.
+  pub files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Fixture {
+  /// Parses the archive.
+  pub fn parse(&self) -> io::Result<Archive<Cursor<&[u8]>>> {
+    Archive::parse_slice(&self.archive)
+  }
+
+  /// Flips every bit of the `index`-th byte of a file's content in the
+  /// archive.
+  ///
+  /// [`Fixture::files`] is left untouched, so it can be used to detect the
+  /// corruption.
+  pub fn corrupt_file(&mut self, path: &str, index: u64) -> io::Result<()> {
+    let archive = self.parse()?;
+    let metadata = match archive.get_entry(path) {
+      Some(Entry::File(metadata)) => metadata,
+      Some(_) => return Err(io::Error::from_raw_os_error(libc::EISDIR)),
+      None => return Err(io::Error::from_raw_os_error(libc::ENOENT)),
+    };
+    if index >= metadata.size {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "index out of file's bounds",
+      ));
+    }
+    let prefix = self.archive[..PREFIX_LEN].try_into().unwrap();
+    let header_len = decode_prefix(prefix).unwrap();
+    let offset = content_offset(header_len) + metadata.offset()? + index;
+    corrupt(&mut self.archive, offset as _);
+    Ok(())
+  }
+}
+
+/// Generates an archive of random files.
+pub async fn random_archive(options: &FixtureOptions) -> io::Result<Fixture> {
+  let mut rng = Rng(options.seed);
+  let mut files = BTreeMap::new();
+  let mut writer = Writer::new();
+
+  for i in 0..options.files {
+    let depth = rng.below(options.max_depth as u64 + 1);
+    let mut path = String::new();
+    for _ in 0..depth {
+      path += &format!("dir{}/", rng.below(4));
+    }
+    path += &format!("file{i}.bin");
+
+    let size = options.sizes.sample(&mut rng);
+    let content: Vec<u8> = (0..size).map(|_| rng.next() as u8).collect();
+
+    #[cfg(feature = "integrity")]
+    if options.integrity {
+      (writer)
+        .add_sized_with_integrity(&path, Cursor::new(content.clone()))
+        .await?;
+      files.insert(path, content);
+      continue;
+    }
+    writer.add(&path, Cursor::new(content.clone()), size);
+    files.insert(path, content);
+  }
+
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await?;
+  Ok(Fixture { archive, files })
+}
+
+/// Flips every bit of `data[offset]`.
+pub fn corrupt(data: &mut [u8], offset: usize) {
+  data[offset] = !data[offset];
+}
+
+/// Asserts that `archive` contains exactly the files in `expected`, with the
+/// same content.
+///
+/// Files' integrity is checked as well with the `integrity` feature.
+///
+/// # Panics
+///
+/// Panics on the first mismatch, or any I/O error.
+pub async fn assert_round_trip<R: AsyncRead + AsyncSeek + Unpin>(
+  archive: &mut Archive<R>,
+  expected: &BTreeMap<String, Vec<u8>>,
+) {
+  let paths: Vec<String> = (archive.walk_with(WalkOptions::default()))
+    .filter(WalkEntry::is_file)
+    .map(|x| x.path)
+    .collect();
+  let expected_paths: Vec<_> = expected.keys().cloned().collect();
+  let mut sorted_paths = paths.clone();
+  sorted_paths.sort_unstable();
+  assert_eq!(sorted_paths, expected_paths, "archive's files differ");
+
+  for path in paths {
+    let mut file =
+      (archive.get(&path).await).unwrap_or_else(|error| panic!("failed to open '{path}': {error}"));
+    let mut content = Vec::new();
+    (file.read_to_end(&mut content).await)
+      .unwrap_or_else(|error| panic!("failed to read '{path}': {error}"));
+    assert!(content == expected[&path], "content of '{path}' differs");
+
+    #[cfg(feature = "integrity")]
+    {
+      let valid = (file.check_integrity().await)
+        .unwrap_or_else(|error| panic!("failed to check integrity of '{path}': {error}"));
+      assert!(valid, "integrity of '{path}' does not match");
+    }
+  }
+}
+
+/// Directory tree generated by [`arbitrary_tree`], for checking round-trips
+/// with [`check_round_trip`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TreeCase {
+  /// Content of every file, keyed by path.
+  pub files: BTreeMap<String, Vec<u8>>,
+
+  /// Paths of executable files.
+  pub executable: BTreeSet<String>,
+
+  /// Paths of empty directories.
+  pub empty_dirs: BTreeSet<String>,
+}
+
+/// Generates an arbitrary directory tree from `seed`.
+///
+/// Trees are small but cover edge cases: empty files and directories,
+/// deep nesting, and names with spaces, dots, backslashes and non-ASCII
+/// characters. The same seed always produces the same tree.
+pub fn arbitrary_tree(seed: u64) -> TreeCase {
+  const PARTS: &[&str] = &[
+    "a", "B", "file", ".hidden", "x.js", " ", "日本", "é", "\\", "-", "_",
+  ];
+
+  fn generate(rng: &mut Rng, case: &mut TreeCase, prefix: &str, depth: u64) {
+    let children = rng.below(if depth == 0 { 6 } else { 4 });
+    if children == 0 && !prefix.is_empty() {
+      case.empty_dirs.insert(prefix.into());
+      return;
+    }
+    let mut names = BTreeSet::new();
+    for _ in 0..children {
+      let mut name: String = (0..=rng.below(3))
+        .map(|_| PARTS[rng.below(PARTS.len() as _) as usize])
+        .collect();
+      if name == "." || name == ".." {
+        name += "_";
+      }
+      if !names.insert(name.clone()) {
+        continue;
+      }
+      let path = match prefix {
+        "" => name,
+        _ => format!("{prefix}/{name}"),
+      };
+      if depth < 4 && rng.below(3) == 0 {
+        generate(rng, case, &path, depth + 1);
+      } else {
+        let size = SizeDistribution::LogUniform { max: 4096 }.sample(rng);
+        let content = (0..size).map(|_| rng.next() as u8).collect();
+        if rng.below(4) == 0 {
+          case.executable.insert(path.clone());
+        }
+        case.files.insert(path, content);
+      }
+    }
+  }
+
+  let mut case = TreeCase::default();
+  generate(&mut Rng(seed), &mut case, "", 0);
+  case
+}
+
+/// Packs `case` with a [`Writer`], parses the result, and checks that every
+/// file, executable flag and empty directory survives unchanged.
+///
+/// Returns [`InvalidData`](io::ErrorKind::InvalidData) describing the first
+/// difference.
+pub async fn check_round_trip(case: &TreeCase) -> io::Result<()> {
+  let mut writer = Writer::new();
+  for (path, content) in &case.files {
+    let size = content.len() as u64;
+    let executable = case.executable.contains(path);
+    writer.add_with_options(path, Cursor::new(content.clone()), size, executable, None);
+  }
+  for path in &case.empty_dirs {
+    writer.add_empty_folder(path);
+  }
+  let mut data = Vec::new();
+  writer.write(&mut data).await?;
+  let mut archive = Archive::new(Cursor::new(data)).await?;
+
+  let mut actual = TreeCase::default();
+  archive.header().visit(&mut |path, entry| match entry {
+    Entry::Directory(Directory { files }) if files.is_empty() => {
+      actual.empty_dirs.insert(path.into());
+    }
+    Entry::Directory(_) => {}
+    Entry::File(file) => {
+      if file.executable {
+        actual.executable.insert(path.into());
+      }
+      actual.files.insert(path.into(), Vec::new());
+    }
+  });
+  for (path, content) in &mut actual.files {
+    archive.get(path).await?.read_to_end(content).await?;
+  }
+
+  let invalid = |what| Err(io::Error::new(io::ErrorKind::InvalidData, what));
+  if actual.empty_dirs != case.empty_dirs {
+    return invalid(format!("empty directories differ: {:?}", actual.empty_dirs));
+  }
+  if actual.executable != case.executable {
+    return invalid(format!("executable files differ: {:?}", actual.executable));
+  }
+  if let Some(path) = (case.files.iter())
+    .chain(&actual.files)
+    .find(|(path, content)| {
+      case.files.get(*path) != Some(content) || actual.files.get(*path) != Some(content)
+    })
+    .map(|(path, _)| path)
+  {
+    return invalid(format!("content of '{path}' differs"));
+  }
+  Ok(())
+}
+
+/// Checks round-trips of `cases` trees generated by [`arbitrary_tree`] from
+/// seeds starting at `seed`.
+///
+/// A failure can be reproduced with [`arbitrary_tree`] and
+/// [`check_round_trip`] on the seed in the panic message, e.g. in a
+/// regression test.
+///
+/// # Panics
+///
+/// Panics on the first tree that does not round-trip.
+pub async fn round_trip_property(cases: u64, seed: u64) {
+  for seed in seed..seed.saturating_add(cases) {
+    let case = arbitrary_tree(seed);
+    if let Err(error) = check_round_trip(&case).await {
+      panic!("round-trip failed for seed {seed}: {error}\n{case:#?}");
+    }
+  }
+}
+
+/// SplitMix64 generator; fixtures only need to be reproducible, not
+/// statistically strong.
+struct Rng(u64);
+
+impl Rng {
+  fn next(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  fn below(&mut self, bound: u64) -> u64 {
+    if bound == 0 {
+      0
+    } else {
+      self.next() % bound
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::header::{HeaderChunks, SerializeOptions};
+
+  fn storm_fixture_options() -> FixtureOptions {
+    FixtureOptions {
+      sizes: SizeDistribution::LogUniform { max: 4096 },
+      ..Default::default()
+    }
+  }
+
+  /// Checks that every file of `archive` reads back as in `expected`.
+  async fn assert_content<R>(archive: &SharedArchive<R>, expected: &BTreeMap<String, Vec<u8>>)
+  where
+    R: AsyncRead + AsyncSeek + Duplicable + Unpin,
+  {
+    for (path, expected) in expected {
+      let mut content = Vec::new();
+      let mut file = archive.get_owned(path).await.unwrap();
+      file.read_to_end(&mut content).await.unwrap();
+      assert_eq!(&content, expected, "content of '{path}' differs");
+    }
+  }
+
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn read_storm_in_memory() {
+    let fixture = random_archive(&storm_fixture_options()).await.unwrap();
+    let archive = SharedArchive::new(Cursor::new(fixture.archive))
+      .await
+      .unwrap();
+    concurrent_read_storm(&archive, 16).await.unwrap();
+    assert_content(&archive, &fixture.files).await;
+  }
+
+  #[tokio::test]
+  async fn round_trip() {
+    round_trip_property(64, 0).await;
+  }
+
+  #[cfg(feature = "fs")]
+  #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+  async fn read_storm_file() {
+    use crate::DuplicableFile;
+
+    let fixture = random_archive(&storm_fixture_options()).await.unwrap();
+    let path = std::env::temp_dir().join(format!("hive-asar-{}-storm.asar", std::process::id()));
+    tokio::fs::write(&path, &fixture.archive).await.unwrap();
+    let archive = SharedArchive::<DuplicableFile>::new_from_file(&path)
+      .await
+      .unwrap();
+    let result = concurrent_read_storm(&archive, 16).await;
+    assert_content(&archive, &fixture.files).await;
+    tokio::fs::remove_file(&path).await.unwrap();
+    result.unwrap();
+  }
+
+  #[test]
+  fn header_chunks_match_serialize_with() {
+    let hash = "abababababababababababababababababababababababababababababababab";
+    let integrity = format!(
+      r#"{{"algorithm":"SHA256","hash":"{hash}","blockSize":4194304,"blocks":["{hash}"]}}"#
+    );
+    let json = format!(
+      r#"{{"files":{{
+        "a":{{"offset":"0","size":3,"integrity":{integrity}}},
+        "bin":{{"files":{{"run":{{"offset":"3","size":2,"executable":true}}}}}},
+        "dir":{{"files":{{
+          "empty":{{"files":{{}}}},
+          "nested":{{"files":{{"lib.node":{{"unpacked":true,"size":4,"integrity":{integrity}}}}}}},
+          "z":{{"offset":"5","size":1,"x-mode":420}}
+        }}}}
+      }}}}"#
+    );
+    let header = Directory::from_json(json.as_bytes(), false).unwrap();
+    let all_options = [
+      SerializeOptions::default(),
+      SerializeOptions {
+        skip_none_integrity: true,
+        always_emit_executable: true,
+      },
+    ];
+    for options in all_options {
+      let expected = serde_json::to_vec(&header.serialize_with(options)).unwrap();
+      for min_len in [1, 7, 64, 1 << 16] {
+        let mut chunks = HeaderChunks::new(&header, options);
+        let mut output = Vec::new();
+        let mut buf = Vec::new();
+        loop {
+          chunks.fill(&mut buf, min_len).unwrap();
+          if buf.is_empty() {
+            break;
+          }
+          output.extend_from_slice(&buf);
+          buf.clear();
+        }
+        assert_eq!(
+          String::from_utf8(output).unwrap(),
+          String::from_utf8(expected.clone()).unwrap(),
+          "{options:?}, chunks of {min_len} bytes"
+        );
+      }
+    }
+  }
+}
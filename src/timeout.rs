@@ -0,0 +1,121 @@
+//! Reader wrapper failing reads and seeks that take too long.
+
+use crate::{Duplicable, LocalDuplicable};
+use async_trait::async_trait;
+use pin_project::pin_project;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::io::{self, AsyncRead, AsyncSeek, ReadBuf};
+use tokio::time::{sleep, Sleep};
+
+/// Reader that fails with [`io::ErrorKind::TimedOut`] if a single read or
+/// seek does not complete in time.
+///
+/// Created by [`Archive::with_io_timeout`](crate::Archive::with_io_timeout).
+/// Requires Tokio's timer to be enabled in the runtime.
+///
+/// A timed-out operation leaves the inner reader's position unspecified, but
+/// [`File`](crate::File)s always seek before reading, so later reads from the
+/// archive remain correct.
+#[pin_project]
+#[derive(Debug)]
+pub struct TimeoutReader<R> {
+  #[pin]
+  inner: R,
+  timeout: Duration,
+  deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> TimeoutReader<R> {
+  /// Wraps `inner`, failing reads and seeks that take longer than `timeout`.
+  pub fn new(inner: R, timeout: Duration) -> Self {
+    Self {
+      inner,
+      timeout,
+      deadline: None,
+    }
+  }
+
+  /// Returns the timeout applied to each operation.
+  pub fn timeout(&self) -> Duration {
+    self.timeout
+  }
+
+  pub fn get_ref(&self) -> &R {
+    &self.inner
+  }
+
+  pub fn get_mut(&mut self) -> &mut R {
+    &mut self.inner
+  }
+
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+}
+
+/// Polls the deadline of a pending operation, starting it if necessary.
+fn poll_deadline(
+  deadline: &mut Option<Pin<Box<Sleep>>>,
+  timeout: Duration,
+  cx: &mut Context<'_>,
+) -> Poll<io::Error> {
+  let sleep = deadline.get_or_insert_with(|| Box::pin(sleep(timeout)));
+  ready!(sleep.as_mut().poll(cx));
+  *deadline = None;
+  Poll::Ready(io::Error::new(
+    io::ErrorKind::TimedOut,
+    "I/O operation timed out",
+  ))
+}
+
+impl<R: AsyncRead> AsyncRead for TimeoutReader<R> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    let this = self.project();
+    match this.inner.poll_read(cx, buf) {
+      Poll::Ready(result) => {
+        *this.deadline = None;
+        Poll::Ready(result)
+      }
+      Poll::Pending => poll_deadline(this.deadline, *this.timeout, cx).map(Err),
+    }
+  }
+}
+
+impl<R: AsyncSeek> AsyncSeek for TimeoutReader<R> {
+  fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+    self.project().inner.start_seek(position)
+  }
+
+  fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+    let this = self.project();
+    match this.inner.poll_complete(cx) {
+      Poll::Ready(result) => {
+        *this.deadline = None;
+        Poll::Ready(result)
+      }
+      Poll::Pending => poll_deadline(this.deadline, *this.timeout, cx).map(Err),
+    }
+  }
+}
+
+#[async_trait]
+impl<R: Duplicable + Sync> Duplicable for TimeoutReader<R> {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(Self::new(self.inner.duplicate().await?, self.timeout))
+  }
+}
+
+#[async_trait(?Send)]
+impl<R: LocalDuplicable> LocalDuplicable for TimeoutReader<R> {
+  async fn duplicate(&self) -> io::Result<Self> {
+    Ok(Self::new(self.inner.duplicate().await?, self.timeout))
+  }
+}
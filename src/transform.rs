@@ -0,0 +1,56 @@
+//! Read transforms of files, selected by their extension fields.
+
+use crate::header::FileMetadata;
+use serde_json::Value;
+use std::fmt::{self, Debug, Formatter};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::io::{self, AsyncRead};
+
+/// Boxed reader passed to and returned from transforms registered with
+/// [`Archive::register_transform`](crate::Archive::register_transform).
+pub type TransformReader<'a> = Pin<Box<dyn AsyncRead + Send + 'a>>;
+
+type TransformFn =
+  dyn for<'a> Fn(TransformReader<'a>) -> io::Result<TransformReader<'a>> + Send + Sync;
+
+/// Transforms registered on an archive, in order of registration.
+#[derive(Clone, Default)]
+pub(crate) struct Transforms(Vec<(Box<str>, Value, Arc<TransformFn>)>);
+
+impl Transforms {
+  pub fn register(
+    &mut self,
+    key: &str,
+    value: Value,
+    transform: impl for<'a> Fn(TransformReader<'a>) -> io::Result<TransformReader<'a>>
+      + Send
+      + Sync
+      + 'static,
+  ) {
+    self.0.retain(|(k, v, _)| !(**k == *key && *v == value));
+    self.0.push((key.into(), value, Arc::new(transform)));
+  }
+
+  /// Wraps `reader` in every transform matching `file`'s extension fields.
+  pub fn apply<'a>(
+    &self,
+    file: &FileMetadata,
+    mut reader: TransformReader<'a>,
+  ) -> io::Result<TransformReader<'a>> {
+    for (key, value, transform) in &self.0 {
+      if file.attributes.get(&**key) == Some(value) {
+        reader = transform(reader)?;
+      }
+    }
+    Ok(reader)
+  }
+}
+
+impl Debug for Transforms {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_list()
+      .entries(self.0.iter().map(|(key, value, _)| (key, value)))
+      .finish()
+  }
+}
@@ -0,0 +1,52 @@
+//! Layout of unpacked files, which are stored outside of the archive.
+
+use crate::split_path;
+use std::path::{Path, PathBuf};
+
+/// Describes where unpacked files of an archive are located.
+///
+/// By default it follows Electron's convention: unpacked files are stored in
+/// a directory next to the archive, named after the archive with `.unpacked`
+/// appended, mirroring their full paths inside the archive. For example,
+/// `foo/bar.node` in `app.asar` is stored at `app.asar.unpacked/foo/bar.node`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnpackedLayout {
+  /// Suffix appended to the archive's path to get the unpacked directory.
+  pub suffix: String,
+
+  /// Whether files mirror their full paths inside the unpacked directory.
+  ///
+  /// If not, files are stored directly in the unpacked directory by their
+  /// file names.
+  pub mirror_paths: bool,
+}
+
+impl UnpackedLayout {
+  /// Returns the unpacked directory of the archive at `archive_path`.
+  pub fn dir(&self, archive_path: &Path) -> PathBuf {
+    let mut dir = archive_path.as_os_str().to_owned();
+    dir.push(&self.suffix);
+    dir.into()
+  }
+
+  /// Returns the location of the unpacked file `path` of the archive at
+  /// `archive_path`.
+  pub fn file_path(&self, archive_path: &Path, path: &str) -> PathBuf {
+    let dir = self.dir(archive_path);
+    let segments = split_path(path);
+    if self.mirror_paths {
+      segments.into_iter().fold(dir, |dir, seg| dir.join(seg))
+    } else {
+      dir.join(segments.last().copied().unwrap_or_default())
+    }
+  }
+}
+
+impl Default for UnpackedLayout {
+  fn default() -> Self {
+    Self {
+      suffix: ".unpacked".into(),
+      mirror_paths: true,
+    }
+  }
+}
@@ -0,0 +1,180 @@
+//! Single-pass verification of whole archives.
+
+use crate::archive::check_asar_format;
+use crate::error::ResultExt;
+use crate::format::{content_offset, padding};
+use crate::header::{Directory, Entry, FileMetadata, FilePosition, Hash, Integrity};
+use crate::{cfg_fs, Error};
+use sha2::digest::Digest;
+use sha2::Sha256;
+use tokio::io::{self, AsyncRead, AsyncReadExt};
+
+cfg_fs! {
+  use std::path::Path;
+  use tokio::fs::File as TokioFile;
+  use tokio::io::BufReader;
+}
+
+/// Summary of [`verify_reader`] and [`verify_file`].
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+  /// Number of files whose content is in the archive.
+  pub files: u64,
+
+  /// Number of files whose content matches their integrity information.
+  pub verified: u64,
+
+  /// Files without integrity information, whose content is not checked.
+  pub no_integrity: Vec<String>,
+
+  /// Files whose content does not match their integrity information, with
+  /// the index of the first mismatched block, or `None` if every block
+  /// matches but the whole-file hash does not.
+  pub mismatched: Vec<(String, Option<usize>)>,
+
+  /// Total length of the archive read.
+  pub bytes: u64,
+}
+
+impl VerifyReport {
+  /// Whether every file with integrity information matches it.
+  pub fn is_ok(&self) -> bool {
+    self.mismatched.is_empty()
+  }
+}
+
+/// Verifies an archive by reading it once from start to end.
+///
+/// The prefix and header are checked first, including that no files'
+/// content overlaps. Then files' content is read in the order of their
+/// offsets, each checked against its integrity information, so only one
+/// block is held in memory at a time, and the reader never needs to seek.
+///
+/// Fails if the archive is malformed, e.g. with [`Error::Overlap`], or with
+/// [`Error::Truncated`] if a file's content is missing. Content not matching
+/// integrity information is listed in [`VerifyReport::mismatched`] instead.
+pub async fn verify_reader(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<VerifyReport> {
+  let header_len = check_asar_format(reader)
+    .await?
+    .ok_or_else(|| io::Error::other("file format check failed"))?;
+  let mut header_bytes = vec![0; header_len as usize + padding(header_len) as usize];
+  reader.read_exact(&mut header_bytes).await?;
+  let header: Directory =
+    serde_json::from_slice(&header_bytes[..header_len as _]).map_err(io::Error::from)?;
+  if let Some((first, second)) = header.find_overlap() {
+    let (first, second) = (first.into(), second.into());
+    return Err(Error::Overlap { first, second }.into());
+  }
+
+  let mut files = Vec::new();
+  header.visit(&mut |path, entry| {
+    if let Entry::File(
+      file @ FileMetadata {
+        pos: FilePosition::Offset(offset),
+        ..
+      },
+    ) = entry
+    {
+      files.push((*offset, path.to_owned(), file.clone()));
+    }
+  });
+  files.sort_unstable_by_key(|(offset, ..)| *offset);
+
+  let mut report = VerifyReport {
+    files: files.len() as u64,
+    ..Default::default()
+  };
+  let mut pos = 0;
+  let mut block = Vec::new();
+  for (offset, path, file) in files {
+    let gap = offset.saturating_sub(pos);
+    let skipped = io::copy(&mut (&mut *reader).take(gap), &mut io::sink()).await?;
+    pos += skipped;
+    if skipped < gap {
+      return Err(truncated(path, file.size, 0));
+    }
+
+    let mut content = (&mut *reader).take(file.size);
+    match &file.integrity {
+      Some(integrity) => {
+        integrity.validate_structure().with_entry(&path)?;
+        if let Err(block) = verify_content(&mut content, integrity, &mut block).await? {
+          report.mismatched.push((path.clone(), block));
+        } else {
+          report.verified += 1;
+        }
+      }
+      None => {
+        io::copy(&mut content, &mut io::sink()).await?;
+        report.no_integrity.push(path.clone());
+      }
+    }
+    let read = file.size - content.limit();
+    pos = pos.max(offset + read);
+    if read < file.size {
+      return Err(truncated(path, file.size, read));
+    }
+  }
+  report.bytes = content_offset(header_len) + pos;
+  Ok(report)
+}
+
+cfg_fs! {
+  /// Verifies the archive at `path` by reading it once from start to end.
+  ///
+  /// See [`verify_reader`].
+  pub async fn verify_file(path: impl AsRef<Path>) -> io::Result<VerifyReport> {
+    let path = path.as_ref();
+    let file = TokioFile::open(path).await.with_path(path)?;
+    let mut reader = BufReader::with_capacity(1 << 16, file);
+    verify_reader(&mut reader).await.with_path(path)
+  }
+}
+
+fn truncated(path: String, expected: u64, available: u64) -> io::Error {
+  Error::Truncated {
+    path: path.into(),
+    expected,
+    available,
+  }
+  .into()
+}
+
+/// Reads all of `content`, checking it against `integrity`.
+///
+/// Returns the first mismatched block, or `Err(None)` if only the whole-file
+/// hash or the number of blocks does not match.
+async fn verify_content(
+  content: &mut (impl AsyncRead + Unpin),
+  integrity: &Integrity,
+  block: &mut Vec<u8>,
+) -> io::Result<Result<(), Option<usize>>> {
+  let mut digest = Sha256::new();
+  let mut mismatch = None;
+  let mut index = 0;
+  loop {
+    block.clear();
+    let len = (&mut *content)
+      .take(integrity.block_size.into())
+      .read_to_end(block)
+      .await?;
+    if len == 0 {
+      break;
+    }
+    let matches = (integrity.blocks.get(index))
+      .is_some_and(|x| Hash::Sha256(Sha256::digest(&block).into()) == *x);
+    if !matches && mismatch.is_none() {
+      mismatch = Some(Some(index));
+    }
+    digest.update(&block);
+    index += 1;
+  }
+  if let Some(mismatch) = mismatch {
+    return Ok(Err(mismatch));
+  }
+  let actual = Hash::Sha256(digest.finalize().into());
+  if index != integrity.blocks.len() || actual != integrity.hash {
+    return Ok(Err(None));
+  }
+  Ok(Ok(()))
+}
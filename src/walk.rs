@@ -0,0 +1,160 @@
+//! Recursive traversal of archive entries, mirroring [`walkdir`]'s
+//! ergonomics.
+//!
+//! [`walkdir`]: https://docs.rs/walkdir
+
+use crate::header::{Directory, Entry};
+use std::fmt::{self, Debug, Formatter};
+use std::vec;
+
+type FilterFn = dyn FnMut(&WalkEntry<'_>) -> bool;
+
+/// Options for [`Walk`].
+pub struct WalkOptions {
+  /// Minimum depth of yielded entries. Entries directly in the root directory
+  /// have depth `1`.
+  pub min_depth: usize,
+
+  /// Maximum depth to descend into.
+  pub max_depth: usize,
+
+  /// Whether to yield the entries of each directory sorted by name.
+  pub sort: bool,
+
+  /// Predicate deciding whether an entry is yielded.
+  ///
+  /// If it returns `false` for a directory, the directory's contents are
+  /// skipped as well, like `walkdir`'s `filter_entry`.
+  pub filter_entry: Option<Box<FilterFn>>,
+}
+
+impl Default for WalkOptions {
+  fn default() -> Self {
+    Self {
+      min_depth: 0,
+      max_depth: usize::MAX,
+      sort: false,
+      filter_entry: None,
+    }
+  }
+}
+
+impl Debug for WalkOptions {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("WalkOptions")
+      .field("min_depth", &self.min_depth)
+      .field("max_depth", &self.max_depth)
+      .field("sort", &self.sort)
+      .field("filter_entry", &self.filter_entry.as_ref().map(|_| ".."))
+      .finish()
+  }
+}
+
+/// An entry yielded by [`Walk`].
+#[derive(Debug, Clone)]
+pub struct WalkEntry<'a> {
+  /// Full path of the entry, separated by `/`.
+  pub path: String,
+
+  /// Depth of the entry. Entries directly in the root directory have depth
+  /// `1`.
+  pub depth: usize,
+
+  pub entry: &'a Entry,
+}
+
+impl WalkEntry<'_> {
+  /// Returns the last segment of the entry's path.
+  pub fn file_name(&self) -> &str {
+    self.path.rsplit('/').next().unwrap_or_default()
+  }
+
+  pub fn is_dir(&self) -> bool {
+    matches!(self.entry, Entry::Directory(_))
+  }
+
+  pub fn is_file(&self) -> bool {
+    matches!(self.entry, Entry::File(_))
+  }
+}
+
+struct Frame<'a> {
+  depth: usize,
+  prefix: String,
+  entries: vec::IntoIter<(&'a str, &'a Entry)>,
+}
+
+/// Pre-order iterator over entries under a [`Directory`].
+pub struct Walk<'a> {
+  options: WalkOptions,
+  stack: Vec<Frame<'a>>,
+}
+
+impl<'a> Walk<'a> {
+  /// Walks entries under `dir`.
+  pub fn new(dir: &'a Directory, options: WalkOptions) -> Self {
+    let mut walk = Self {
+      options,
+      stack: Vec::new(),
+    };
+    if walk.options.max_depth > 0 {
+      walk.push(dir, 1, String::new());
+    }
+    walk
+  }
+
+  fn push(&mut self, dir: &'a Directory, depth: usize, prefix: String) {
+    let mut entries: Vec<_> = dir.files.iter().map(|(k, v)| (&**k, v)).collect();
+    if self.options.sort {
+      entries.sort_unstable_by_key(|(name, _)| *name);
+    }
+    self.stack.push(Frame {
+      depth,
+      prefix,
+      entries: entries.into_iter(),
+    });
+  }
+}
+
+impl<'a> Iterator for Walk<'a> {
+  type Item = WalkEntry<'a>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let frame = self.stack.last_mut()?;
+      let Some((name, entry)) = frame.entries.next() else {
+        self.stack.pop();
+        continue;
+      };
+      let depth = frame.depth;
+      let path = if frame.prefix.is_empty() {
+        name.to_string()
+      } else {
+        format!("{}/{name}", frame.prefix)
+      };
+      let item = WalkEntry { path, depth, entry };
+
+      if let Some(filter) = &mut self.options.filter_entry {
+        if !filter(&item) {
+          continue;
+        }
+      }
+      if let Entry::Directory(dir) = entry {
+        if depth < self.options.max_depth {
+          self.push(dir, depth + 1, item.path.clone());
+        }
+      }
+      if depth >= self.options.min_depth {
+        return Some(item);
+      }
+    }
+  }
+}
+
+impl Debug for Walk<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Walk")
+      .field("options", &self.options)
+      .finish_non_exhaustive()
+  }
+}
@@ -0,0 +1,194 @@
+//! Keeping an archive in sync with a directory, for development tooling.
+//!
+//! [`pack_on_change`] packs a directory into an archive, and packs it again
+//! whenever something in the directory changes:
+//!
+//! ```no_run
+//! # async fn f() -> std::io::Result<()> {
+//! use hive_asar::watch::{pack_on_change, WatchOptions};
+//!
+//! pack_on_change("app", "app.asar", WatchOptions::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Changes are detected by polling sizes and modification times, so no
+//! platform-specific notification API is needed.
+
+use crate::error::ResultExt;
+use crate::{pack_dir_with_options, PackCache, PackOptions, PackReport};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs::{canonicalize, read_dir, rename, symlink_metadata, File as TokioFile};
+use tokio::io::{self, AsyncWriteExt};
+use tokio::time::sleep;
+
+type PackFn = dyn Fn(&io::Result<PackReport>) + Send + Sync;
+
+/// Options for [`pack_on_change`].
+#[derive(Clone)]
+pub struct WatchOptions {
+  /// Options used for every pack.
+  pub pack: PackOptions,
+
+  /// Interval between checks for changes.
+  pub poll_interval: Duration,
+
+  /// How long the directory must stay unchanged before packing, so that a
+  /// burst of changes, e.g. from a build tool, results in a single pack.
+  pub debounce: Duration,
+
+  /// Called after every pack with its result.
+  ///
+  /// Failed packs, e.g. because a file was removed while packing, do not
+  /// stop watching; the archive is left as it was and packed again on the
+  /// next change.
+  pub on_pack: Option<Arc<PackFn>>,
+}
+
+impl Default for WatchOptions {
+  fn default() -> Self {
+    Self {
+      pack: PackOptions::default(),
+      poll_interval: Duration::from_millis(500),
+      debounce: Duration::from_millis(300),
+      on_pack: None,
+    }
+  }
+}
+
+impl Debug for WatchOptions {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.debug_struct("WatchOptions")
+      .field("pack", &self.pack)
+      .field("poll_interval", &self.poll_interval)
+      .field("debounce", &self.debounce)
+      .field("on_pack", &self.on_pack.as_ref().map(|_| ".."))
+      .finish()
+  }
+}
+
+/// Packs `dir` into `dest`, and packs it again whenever anything in `dir`
+/// changes, until the returned future is dropped.
+///
+/// `dest` is written through a temporary file next to it and renamed into
+/// place, so readers never see a partially written archive. Unless
+/// [`PackOptions::cache`] is set, the previous archive at `dest` is used as
+/// the cache of each pack.
+///
+/// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) right away if
+/// `dest` is inside `dir`, since every pack would then change `dir` and
+/// trigger another one. Otherwise, only returns when the state of `dir`
+/// itself cannot be read.
+pub async fn pack_on_change(
+  dir: impl AsRef<Path>,
+  dest: impl AsRef<Path>,
+  options: WatchOptions,
+) -> io::Result<Infallible> {
+  let (dir, dest) = (dir.as_ref(), dest.as_ref());
+  check_dest(dir, dest).await?;
+  let mut last = snapshot(dir).await?;
+  pack(dir, dest, &options).await;
+  loop {
+    sleep(options.poll_interval).await;
+    let mut current = snapshot(dir).await?;
+    if current == last {
+      continue;
+    }
+    loop {
+      sleep(options.debounce).await;
+      let next = snapshot(dir).await?;
+      if next == current {
+        break;
+      }
+      current = next;
+    }
+    last = current;
+    pack(dir, dest, &options).await;
+  }
+}
+
+/// Checks that `dest` is not inside `dir`, comparing canonical paths of
+/// `dir` and `dest`'s parent, since `dest` may not exist yet.
+async fn check_dest(dir: &Path, dest: &Path) -> io::Result<()> {
+  let dir = canonicalize(dir).await.with_path(dir)?;
+  let parent = match dest.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => parent,
+    _ => Path::new("."),
+  };
+  let parent = canonicalize(parent).await.with_path(parent)?;
+  if parent.starts_with(&dir) {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      format!(
+        "'{}' is inside the watched directory '{}'",
+        dest.display(),
+        dir.display()
+      ),
+    ));
+  }
+  Ok(())
+}
+
+async fn pack(dir: &Path, dest: &Path, options: &WatchOptions) {
+  let result = pack_into_file(dir, dest, &options.pack).await;
+  if let Some(on_pack) = &options.on_pack {
+    on_pack(&result);
+  }
+}
+
+async fn pack_into_file(dir: &Path, dest: &Path, options: &PackOptions) -> io::Result<PackReport> {
+  let mut temp = dest.as_os_str().to_owned();
+  temp.push(".tmp");
+  let temp = PathBuf::from(temp);
+  let mut options = options.clone();
+  if options.cache.is_none() {
+    options.cache = PackCache::open(dest).await.ok();
+  }
+  let mut file = TokioFile::create(&temp).await.with_path(&temp)?;
+  let report = pack_dir_with_options(dir, &mut file, &options).await?;
+  file.flush().await.with_path(&temp)?;
+  drop(file);
+  rename(&temp, dest).await.with_path(dest)?;
+  Ok(report)
+}
+
+type Snapshot = BTreeMap<PathBuf, (u64, Option<SystemTime>)>;
+
+/// Records size and modification time of every entry under `dir`.
+///
+/// Entries that cannot be read below `dir`, e.g. removed while walking, are
+/// left out rather than failing; packing reports them instead.
+async fn snapshot(dir: &Path) -> io::Result<Snapshot> {
+  fn walk<'a>(
+    path: &'a Path,
+    result: &'a mut Snapshot,
+  ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+    Box::pin(async move {
+      let Ok(mut rd) = read_dir(path).await else {
+        return;
+      };
+      while let Ok(Some(entry)) = rd.next_entry().await {
+        let path = entry.path();
+        let Ok(metadata) = symlink_metadata(&path).await else {
+          continue;
+        };
+        result.insert(path.clone(), (metadata.len(), metadata.modified().ok()));
+        if metadata.is_dir() {
+          walk(&path, result).await;
+        }
+      }
+    })
+  }
+
+  symlink_metadata(dir).await.with_path(dir)?;
+  let mut result = Snapshot::new();
+  walk(dir, &mut result).await;
+  Ok(result)
+}
@@ -1,39 +1,471 @@
-use crate::header::{Directory, Entry, FileMetadata, FilePosition, Integrity};
-use crate::{cfg_fs, cfg_integrity, cfg_stream, split_path};
+use crate::archive_path::is_normalized;
+use crate::error::{Error, Limit, ResultExt};
+use crate::format::{content_offset, encode_prefix, padding};
+use crate::header::{
+  Directory, Entry, FileMetadata, FilePosition, HeaderChunks, Integrity, SerializeOptions,
+};
+use crate::manifest::{EmbeddedManifest, Manifest, MANIFEST_PATH};
+use crate::{
+  cfg_compressed_header, cfg_fs, cfg_integrity, cfg_stream, normalize_path, split_path,
+  AsArchivePath,
+};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Debug, Formatter};
 use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 use tokio::io::{
-  self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, Take,
+  self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt, ReadBuf, Take,
 };
 
+cfg_compressed_header! {
+  use crate::format::{encode_compressed_prefix, HeaderCodec};
+}
+
 cfg_fs! {
   use std::future::Future;
-  use std::path::Path;
-  use std::pin::Pin;
-  use tokio::fs::{read_dir, symlink_metadata, File as TokioFile};
+  use std::path::{Path, PathBuf};
+  use crate::retry::{retry, Retry};
+  #[cfg(feature = "xattr")]
+  use crate::xattr::{XattrPolicy, XATTRS_KEY};
+  use crate::{DuplicableFile, File, RetryPolicy, SharedArchive, UnpackedLayout};
+  use std::time::SystemTime;
+  use tokio::fs::{
+    create_dir_all, metadata, read_dir, remove_file, rename, symlink_metadata, File as TokioFile,
+  };
+
+  /// Maximum number of files opened at once when resolving path entries.
+  const PATH_CONCURRENCY: usize = 64;
+
+  /// Temporary file holding content spilled by [`Writer::add_bytes`], removed
+  /// when dropped.
+  #[derive(Debug)]
+  struct SpillFile {
+    path: PathBuf,
+    size: u64,
+  }
+
+  impl SpillFile {
+    async fn create(content: &[u8]) -> io::Result<Self> {
+      use std::sync::atomic::{AtomicU64, Ordering};
+      static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+      let name = format!(
+        "hive-asar-spill-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+      );
+      let path = std::env::temp_dir().join(name);
+      let mut file = (tokio::fs::OpenOptions::new().write(true).create_new(true))
+        .open(&path)
+        .await
+        .with_path(&path)?;
+      let spill = Self { path, size: content.len() as u64 };
+      file.write_all(content).await.with_path(&spill.path)?;
+      file.flush().await.with_path(&spill.path)?;
+      Ok(spill)
+    }
+  }
+
+  impl Drop for SpillFile {
+    fn drop(&mut self) {
+      let _ = std::fs::remove_file(&self.path);
+    }
+  }
 }
 
 cfg_integrity! {
-  use crate::BLOCK_SIZE;
-  use crate::header::{Algorithm, Hash};
+  use crate::format::DEFAULT_BLOCK_SIZE;
+  use crate::header::Hash;
+  use pin_project::pin_project;
   use sha2::digest::Digest;
+  use std::task::ready;
   use sha2::Sha256;
 }
 
 cfg_stream! {
-  use bytes::Bytes;
+  use crate::format::PREFIX_LEN;
+  use std::pin::pin;
+  use bytes::{BufMut, Bytes, BytesMut};
   use futures_core::Stream;
-  use futures_util::future::ok;
-  use futures_util::stream::{iter, once};
-  use futures_util::StreamExt;
-  use tokio_util::io::ReaderStream;
+  use futures_util::future::{ok, ready, Either};
+  use futures_util::stream::{iter, once, unfold};
+  use futures_util::{Sink, SinkExt, StreamExt};
+}
+
+/// Size of the buffer used to copy files' content.
+const COPY_BUFFER_SIZE: usize = 65536;
+
+/// Size of chunks the header is serialized into while writing.
+const HEADER_CHUNK_SIZE: usize = 65536;
+
+/// Checks that a header of `len` bytes fits in the prefix.
+fn header_len(len: u64) -> io::Result<u32> {
+  u32::try_from(len).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "header too large"))
 }
 
 /// Asar archive writer.
 #[derive(Debug)]
 pub struct Writer<F: AsyncRead + Unpin> {
   header: Directory,
-  file_offset: u64,
-  files: Vec<Take<F>>,
+  files: Vec<(Box<str>, Content<F>)>,
+  trailer: Vec<u8>,
+  serialize_options: SerializeOptions,
+  order: PackOrder,
+  rate_limit: Option<u64>,
+  progress: Option<ProgressHook>,
+  strict_paths: bool,
+  rejected_path: Option<Box<str>>,
+  windows_names: WindowsNames,
+  embed_manifest: bool,
+  max_component_len: Option<u64>,
+  max_path_len: Option<u64>,
+  /// First path exceeding `max_component_len` or `max_path_len`.
+  long_path: Option<(Box<str>, Limit, u64, u64)>,
+  /// First path rejected by `windows_names`, with the offending segment.
+  reserved_name: Option<(Box<str>, Box<str>)>,
+  max_archive_size: Option<u64>,
+  added_size: u64,
+  over_budget: Option<(Box<str>, u64)>,
+  empty_files: usize,
+  #[cfg(feature = "fs")]
+  spill_threshold: Option<u64>,
+  #[cfg(feature = "fs")]
+  unpacked: Vec<(Box<str>, Take<F>)>,
+  #[cfg(feature = "fs")]
+  unpacked_layout: UnpackedLayout,
+}
+
+type ProgressFn = dyn FnMut(&str, u64, u64) + Send;
+
+/// Callback reporting progress of each file being written.
+///
+/// The mutex is only ever accessed through `&mut`, and only makes the hook
+/// `Sync` without requiring `Sync` of the callback.
+struct ProgressHook(Mutex<Box<ProgressFn>>);
+
+impl Debug for ProgressHook {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    f.write_str("ProgressHook")
+  }
+}
+
+/// Reports progress and throttles output while writing files' content.
+struct Pacer {
+  rate_limit: Option<u64>,
+  progress: Option<ProgressHook>,
+  start: Instant,
+  written: u64,
+}
+
+impl Pacer {
+  fn new(rate_limit: Option<u64>, progress: Option<ProgressHook>) -> Self {
+    Self {
+      rate_limit,
+      progress,
+      start: Instant::now(),
+      written: 0,
+    }
+  }
+
+  /// Maximum bytes to write at once, so that throttling stays smooth.
+  fn chunk_size(&self) -> usize {
+    match self.rate_limit {
+      Some(rate) => (rate as usize / 8).clamp(1, COPY_BUFFER_SIZE),
+      None => COPY_BUFFER_SIZE,
+    }
+  }
+
+  /// Records `len` bytes of file `path` have been written, `copied` out of
+  /// `size` in total, and waits if writing is faster than the rate limit.
+  async fn advance(&mut self, path: &str, len: usize, copied: u64, size: u64) {
+    if let Some(progress) = &mut self.progress {
+      let progress = progress.0.get_mut().unwrap_or_else(PoisonError::into_inner);
+      progress(path, copied, size);
+    }
+    self.written += len as u64;
+    if let Some(rate) = self.rate_limit {
+      let expected = Duration::from_secs_f64(self.written as f64 / rate as f64);
+      let elapsed = self.start.elapsed();
+      if expected > elapsed {
+        tokio::time::sleep(expected - elapsed).await;
+      }
+    }
+  }
+}
+
+/// Physical order of files' content in an archive.
+///
+/// The order does not affect the header, only where files' content is
+/// placed. Reading related files from adjacent locations improves locality,
+/// especially over slow media.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PackOrder {
+  /// Files are placed in the order they are added.
+  #[default]
+  Insertion,
+
+  /// Smaller files are placed first, so that many small, startup-critical
+  /// files end up close to the header.
+  SmallFirst,
+
+  /// Files are grouped by extension, with files without extension first.
+  ByExtension,
+
+  /// Files listed (e.g. from an access log) are placed first, in the given
+  /// order. Other files follow in the order they are added.
+  Profile(Vec<String>),
+}
+
+/// What a [`Writer`] does with names that are invalid on Windows, set with
+/// [`Writer::set_windows_names`].
+///
+/// Such archives extract fine on Linux and macOS, but fail or silently lose
+/// files on Windows. A segment is invalid if its part before the first `.`,
+/// ignoring trailing spaces and case, is a reserved device name (`CON`,
+/// `PRN`, `AUX`, `NUL`, `COM1` to `COM9` or `LPT1` to `LPT9`, e.g. `aux.js`),
+/// or if it ends with a dot or a space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowsNames {
+  /// Names are added as they are.
+  #[default]
+  Allow,
+
+  /// Entries with invalid names are not added, and writing the archive fails
+  /// with [`Error::ReservedName`].
+  Reject,
+
+  /// Invalid segments are renamed: `_` is appended to reserved device names
+  /// before the extension, e.g. `aux.js` becomes `aux_.js`, and trailing
+  /// dots and spaces are replaced with `_`.
+  Rename,
+}
+
+impl WindowsNames {
+  /// Applies the policy to `path`, returning the path to add, or the
+  /// offending segment if it should be rejected.
+  fn apply<'a>(self, path: &'a str) -> Result<Cow<'a, str>, &'a str> {
+    if self == Self::Allow {
+      return Ok(path.into());
+    }
+    let segments = split_path(path);
+    let Some(invalid) = segments.iter().find(|x| !is_windows_safe(x)) else {
+      return Ok(path.into());
+    };
+    if self == Self::Reject {
+      return Err(invalid);
+    }
+    let renamed: Vec<_> = segments.into_iter().map(windows_safe_name).collect();
+    Ok(renamed.join("/").into())
+  }
+}
+
+fn is_reserved_stem(name: &str) -> bool {
+  let stem = name
+    .split('.')
+    .next()
+    .unwrap_or_default()
+    .trim_end_matches(' ');
+  let upper = stem.to_ascii_uppercase();
+  matches!(
+    upper.as_bytes(),
+    b"CON"
+      | b"PRN"
+      | b"AUX"
+      | b"NUL"
+      | [b'C', b'O', b'M', b'1'..=b'9']
+      | [b'L', b'P', b'T', b'1'..=b'9']
+  )
+}
+
+fn is_windows_safe(name: &str) -> bool {
+  !is_reserved_stem(name) && !name.ends_with(['.', ' '])
+}
+
+fn windows_safe_name(name: &str) -> Cow<'_, str> {
+  if is_windows_safe(name) {
+    return name.into();
+  }
+  let mut name = name.to_owned();
+  if is_reserved_stem(&name) {
+    let stem_len = name.find('.').unwrap_or(name.len());
+    name.insert(stem_len, '_');
+  }
+  let kept = name.trim_end_matches(['.', ' ']).len();
+  let trailing = name.len() - kept;
+  name.truncate(kept);
+  name.extend(std::iter::repeat_n('_', trailing));
+  name.into()
+}
+
+impl PackOrder {
+  fn apply<F: AsyncRead + Unpin>(&self, files: &mut [(Box<str>, Content<F>)]) {
+    match self {
+      Self::Insertion => {}
+      Self::SmallFirst => files.sort_by_key(|(_, content)| content.size()),
+      Self::ByExtension => files.sort_by(|(a, _), (b, _)| extension(a).cmp(extension(b))),
+      Self::Profile(paths) => {
+        let ranks: HashMap<_, _> = (paths.iter().enumerate())
+          .map(|(i, path)| (normalize_path(path), i))
+          .collect();
+        files.sort_by_key(|(path, _)| ranks.get(&**path).copied().unwrap_or(usize::MAX));
+      }
+    }
+  }
+}
+
+fn extension(path: &str) -> &str {
+  let name = path.rsplit('/').next().unwrap_or_default();
+  match name.rfind('.') {
+    Some(i) if i > 0 => &name[i + 1..],
+    _ => "",
+  }
+}
+
+/// Layout of an archive computed by [`Writer::plan`].
+#[derive(Debug, Clone)]
+pub struct WritePlan {
+  /// The final header, with offsets of every file assigned.
+  pub header: Directory,
+
+  /// Length of the serialized header, excluding padding.
+  pub header_len: u32,
+
+  /// Total size of files' content stored in the archive.
+  pub content_len: u64,
+
+  /// Size of the whole archive, including the trailer.
+  pub total_len: u64,
+
+  /// Number of files stored in the archive, excluding unpacked files.
+  pub file_count: usize,
+
+  /// Total size of unpacked files, which are written outside the archive.
+  pub unpacked_len: u64,
+}
+
+impl WritePlan {
+  /// Exports paths, sizes and hashes of every file into a [`Manifest`].
+  pub fn manifest(&self) -> Manifest {
+    Manifest::from_header(&self.header)
+  }
+}
+
+/// Content of a file in the order they are written.
+#[derive(Debug)]
+enum Content<F: AsyncRead + Unpin> {
+  Reader(Take<F>),
+  Memory(Vec<u8>),
+  #[cfg(feature = "fs")]
+  Spilled(SpillFile),
+  #[cfg(feature = "fs")]
+  Cached {
+    archive: SharedArchive<DuplicableFile>,
+    path: String,
+    size: u64,
+  },
+  #[cfg(feature = "fs")]
+  Path {
+    path: PathBuf,
+    size: u64,
+    #[cfg(feature = "integrity")]
+    integrity: bool,
+    resolved: bool,
+    retry: Option<RetryPolicy>,
+  },
+}
+
+impl<F: AsyncRead + Unpin> Content<F> {
+  fn size(&self) -> u64 {
+    match self {
+      Self::Reader(reader) => reader.limit(),
+      Self::Memory(data) => data.len() as u64,
+      #[cfg(feature = "fs")]
+      Self::Spilled(file) => file.size,
+      #[cfg(feature = "fs")]
+      Self::Cached { size, .. } => *size,
+      #[cfg(feature = "fs")]
+      Self::Path { size, .. } => *size,
+    }
+  }
+
+  /// Whether the content is known to be empty, so that it needs not be kept
+  /// until writing.
+  fn is_empty(&self) -> bool {
+    match self {
+      #[cfg(feature = "fs")]
+      Self::Path {
+        resolved: false, ..
+      } => false,
+      _ => self.size() == 0,
+    }
+  }
+
+  async fn open(self) -> io::Result<Source<F>> {
+    match self {
+      Self::Reader(reader) => Ok(Source::Reader(reader)),
+      Self::Memory(data) => Ok(Source::Memory(std::io::Cursor::new(data))),
+      #[cfg(feature = "fs")]
+      Self::Spilled(spill) => {
+        let file = TokioFile::open(&spill.path).await.with_path(&spill.path)?;
+        let file = file.take(spill.size);
+        Ok(Source::Spilled {
+          file,
+          _spill: spill,
+        })
+      }
+      #[cfg(feature = "fs")]
+      Self::Cached { archive, path, .. } => {
+        Ok(Source::Cached(Box::new(archive.get_owned(&path).await?)))
+      }
+      #[cfg(feature = "fs")]
+      Self::Path {
+        path, size, retry, ..
+      } => {
+        let file = (self::retry(retry, || TokioFile::open(&path)).await).with_path(&path)?;
+        Ok(Source::File(Retry::new(file.take(size), retry)))
+      }
+    }
+  }
+}
+
+/// Opened [`Content`].
+enum Source<F: AsyncRead + Unpin> {
+  Reader(Take<F>),
+  Memory(std::io::Cursor<Vec<u8>>),
+  /// The spill file is removed once dropped, after the open file.
+  #[cfg(feature = "fs")]
+  Spilled {
+    file: Take<TokioFile>,
+    _spill: SpillFile,
+  },
+  #[cfg(feature = "fs")]
+  Cached(Box<File<DuplicableFile>>),
+  #[cfg(feature = "fs")]
+  File(Retry<Take<TokioFile>>),
+}
+
+impl<F: AsyncRead + Unpin> AsyncRead for Source<F> {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: &mut ReadBuf<'_>,
+  ) -> Poll<io::Result<()>> {
+    match self.get_mut() {
+      Self::Reader(reader) => Pin::new(reader).poll_read(cx, buf),
+      Self::Memory(data) => Pin::new(data).poll_read(cx, buf),
+      #[cfg(feature = "fs")]
+      Self::Spilled { file, .. } => Pin::new(file).poll_read(cx, buf),
+      #[cfg(feature = "fs")]
+      Self::Cached(file) => Pin::new(file).poll_read(cx, buf),
+      #[cfg(feature = "fs")]
+      Self::File(file) => Pin::new(file).poll_read(cx, buf),
+    }
+  }
 }
 
 impl<F: AsyncRead + Unpin> Writer<F> {
@@ -69,12 +501,44 @@ impl<F: AsyncRead + Unpin> Writer<F> {
   /// # Panic
   ///
   /// The method panics if normalised `path` contains no filename, or if the
-  /// path is already occupied by a previously inserted file.
-  pub fn add(&mut self, path: &str, content: F, size: u64) {
-    self.add_with_options(path, content, size, false, None)
+  /// path is already occupied by a previously inserted file. See
+  /// [`normalize_path`] for how `path` is normalised; an
+  /// [`ArchivePath`](crate::ArchivePath) is used as is.
+  pub fn add(&mut self, path: impl AsArchivePath, content: F, size: u64) {
+    self.add_with_options(path.as_path_str(), content, size, false, None)
+  }
+
+  /// Add an entry with in-memory content to the archive.
+  ///
+  /// Unlike [`Writer::add`], this does not need a reader type. With the `fs`
+  /// feature, content larger than [`Writer::set_spill_threshold`] is written
+  /// to a temporary file right away instead of being held in memory until
+  /// the archive is written.
+  ///
+  /// # Panic
+  ///
+  /// See [`Writer::add`].
+  pub async fn add_bytes(&mut self, path: &str, content: impl Into<Vec<u8>>) -> io::Result<()> {
+    let content = content.into();
+    let file_entry = FileMetadata {
+      pos: FilePosition::Offset(0),
+      size: content.len() as u64,
+      executable: false,
+      integrity: None,
+      mode: None,
+      attributes: Default::default(),
+    };
+    #[cfg(feature = "fs")]
+    if (self.spill_threshold).is_some_and(|x| content.len() as u64 > x) {
+      let spill = SpillFile::create(&content).await?;
+      self.add_content(path, Content::Spilled(spill), file_entry);
+      return Ok(());
+    }
+    self.add_content(path, Content::Memory(content), file_entry);
+    Ok(())
   }
 
-  fn add_with_options(
+  pub(crate) fn add_with_options(
     &mut self,
     path: &str,
     content: F,
@@ -82,196 +546,1677 @@ impl<F: AsyncRead + Unpin> Writer<F> {
     executable: bool,
     integrity: Option<Integrity>,
   ) {
-    let mut segments = split_path(path);
-    let filename = segments
-      .pop()
-      .expect("normalised path contains no filename");
+    let content = Content::Reader(content.take(size));
     let file_entry = FileMetadata {
-      pos: FilePosition::Offset(self.file_offset),
+      pos: FilePosition::Offset(0),
       size,
       executable,
       integrity,
+      mode: None,
+      attributes: Default::default(),
+    };
+    self.add_content(path, content, file_entry)
+  }
+
+  /// Add an entry with integrity information computed elsewhere, e.g. with
+  /// [`Integrity::compute_async`] or [`Integrity::from_precomputed`], to the
+  /// archive.
+  ///
+  /// `integrity` is written into the header as is, without reading
+  /// `content`. Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) if
+  /// it is malformed or its number of blocks does not match `size`.
+  ///
+  /// # Panic
+  ///
+  /// See [`Writer::add`].
+  pub fn add_with_integrity(
+    &mut self,
+    path: &str,
+    content: F,
+    size: u64,
+    integrity: Integrity,
+  ) -> io::Result<()> {
+    check_integrity(size, &integrity)?;
+    self.add_with_options(path, content, size, false, Some(integrity));
+    Ok(())
+  }
+
+  /// Add an empty file to the archive, without a reader.
+  ///
+  /// Empty files added by any method are only recorded in the header, and
+  /// need no content when writing.
+  ///
+  /// # Panic
+  ///
+  /// See [`Writer::add`].
+  pub fn add_empty_file(&mut self, path: &str) {
+    self.add_content(
+      path,
+      Content::Memory(Vec::new()),
+      FileMetadata {
+        pos: FilePosition::Offset(0),
+        size: 0,
+        executable: false,
+        integrity: None,
+        mode: None,
+        attributes: Default::default(),
+      },
+    );
+  }
+
+  fn add_content(&mut self, path: &str, content: Content<F>, file_entry: FileMetadata) {
+    // Offsets are assigned in `Writer::prepare`.
+    if let Some(normalized_path) = self.insert_file(path, file_entry) {
+      if content.is_empty() {
+        self.empty_files += 1;
+        return;
+      }
+      self.added_size = self.added_size.saturating_add(content.size());
+      if self
+        .max_archive_size
+        .is_some_and(|max| self.added_size > max)
+      {
+        (self.over_budget).get_or_insert_with(|| (normalized_path.clone(), self.added_size));
+      }
+      self.files.push((normalized_path, content))
+    }
+  }
+
+  /// Checks `path` against strict paths and the policy on Windows names,
+  /// recording the first rejected one, and returns the path to add.
+  fn accept_path<'a>(&mut self, path: &'a str) -> Option<Cow<'a, str>> {
+    if self.strict_paths && !is_normalized(path) {
+      self.rejected_path.get_or_insert_with(|| path.into());
+      return None;
+    }
+    let path = match self.windows_names.apply(path) {
+      Ok(path) => path,
+      Err(segment) => {
+        (self.reserved_name).get_or_insert_with(|| (path.into(), segment.into()));
+        return None;
+      }
     };
+    let normalized = path.to_archive_path();
+    let component_len = (normalized.segments())
+      .map(|x| x.len() as u64)
+      .max()
+      .unwrap_or(0);
+    let too_long = [
+      (Limit::ComponentLen, component_len, self.max_component_len),
+      (Limit::PathLen, normalized.len() as u64, self.max_path_len),
+    ]
+    .into_iter()
+    .find_map(|(limit, value, max)| Some((limit, value, max.filter(|&x| value > x)?)));
+    if let Some((limit, value, max)) = too_long {
+      (self.long_path).get_or_insert_with(|| (normalized.as_str().into(), limit, value, max));
+      return None;
+    }
+    Some(path)
+  }
+
+  /// Inserts a file into the header, returning its normalised path, or `None`
+  /// if the path is rejected.
+  fn insert_file(&mut self, path: &str, file_entry: FileMetadata) -> Option<Box<str>> {
+    let path = self.accept_path(path)?;
+    let mut segments = split_path(&path);
+    let normalized_path = segments.join("/").into();
+    let filename = segments
+      .pop()
+      .expect("normalised path contains no filename");
     let result = self
       .add_folder_recursively(segments)
       .files
       .insert(filename.into(), Entry::File(file_entry));
     assert!(result.is_none());
-    self.file_offset += size;
-    self.files.push(content.take(size))
+    Some(normalized_path)
   }
 
-  /// Adds an empty folder recursively to the archive.
-  pub fn add_empty_folder(&mut self, path: &str) {
-    self.add_folder_recursively(split_path(path));
+  /// Resolves pending entries and assigns offsets of all files.
+  async fn prepare(&mut self) -> io::Result<()> {
+    if let Some(path) = &self.rejected_path {
+      let normalized = normalize_path(path).into();
+      let path = path.clone();
+      return Err(Error::InvalidPath { path, normalized }.into());
+    }
+    if let Some((path, segment)) = &self.reserved_name {
+      let (path, segment) = (path.clone(), segment.clone());
+      return Err(Error::ReservedName { path, segment }.into());
+    }
+    if let Some((path, limit, value, max)) = &self.long_path {
+      let (limit, value, max) = (*limit, *value, *max);
+      let source = Error::LimitExceeded { limit, value, max }.into();
+      return Err(
+        Error::Entry {
+          path: path.clone(),
+          source,
+        }
+        .into(),
+      );
+    }
+    if let (Some((path, size)), Some(max)) = (&self.over_budget, self.max_archive_size) {
+      let (entry, size) = (Some(path.clone()), *size);
+      return Err(Error::ArchiveTooLarge { entry, size, max }.into());
+    }
+
+    #[cfg(feature = "fs")]
+    self.resolve_paths().await?;
+    if self.embed_manifest {
+      self.add_manifest()?;
+    }
+
+    self.order.apply(&mut self.files);
+    let mut offset = 0;
+    for (path, content) in &self.files {
+      let metadata = (self.header)
+        .search_segments_mut(&split_path(path))
+        .expect("file added to writer should exist in header");
+      metadata.pos = FilePosition::Offset(offset);
+      offset += content.size();
+    }
+    self.check_budget()
   }
 
-  /// Finishes the archive and writes the content into `dest`.
-  pub async fn write(self, dest: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
-    let header_bytes = serde_json::to_vec(&self.header)?;
-    let header_len = header_bytes.len() as u32;
-    let padding = match header_len % 4 {
-      0 => 0,
-      r => 4 - r,
+  /// Adds an [`EmbeddedManifest`] of every other file at [`MANIFEST_PATH`],
+  /// replacing one added by a previous call.
+  fn add_manifest(&mut self) -> io::Result<()> {
+    if let Some(index) = self.files.iter().position(|(x, _)| **x == *MANIFEST_PATH) {
+      let (_, content) = self.files.remove(index);
+      self.added_size -= content.size();
+    }
+    self.header.files.remove(MANIFEST_PATH);
+    let manifest = EmbeddedManifest::new(Manifest::from_header(&self.header));
+    let content = serde_json::to_vec_pretty(&manifest)?;
+    let file_entry = FileMetadata {
+      pos: FilePosition::Offset(0),
+      size: content.len() as u64,
+      executable: false,
+      integrity: None,
+      mode: None,
+      attributes: Default::default(),
     };
+    self.add_content(MANIFEST_PATH, Content::Memory(content), file_entry);
+    Ok(())
+  }
 
-    dest.write_u32_le(4).await?;
-    dest.write_u32_le(header_len + padding + 8).await?;
-    dest.write_u32_le(header_len + padding + 4).await?;
-    dest.write_u32_le(header_len).await?;
+  /// Checks the final layout against the maximum archive size.
+  fn check_budget(&self) -> io::Result<()> {
+    let Some(max) = self.max_archive_size else {
+      return Ok(());
+    };
+    let header_len = self.header.serialized_len(self.serialize_options)?;
+    let mut size = content_offset(u32::try_from(header_len).unwrap_or(u32::MAX));
+    let mut entry = None;
+    for (path, content) in &self.files {
+      if size > max {
+        break;
+      }
+      size = size.saturating_add(content.size());
+      entry = Some(path);
+    }
+    if size <= max {
+      size = size.saturating_add(self.trailer.len() as u64);
+      entry = None;
+    }
+    if size > max {
+      let entry = entry.cloned();
+      return Err(Error::ArchiveTooLarge { entry, size, max }.into());
+    }
+    Ok(())
+  }
 
-    dest.write_all(&header_bytes).await?;
-    dest.write_all(&vec![0; padding as _]).await?;
+  /// Computes the archive's final layout without writing anything.
+  ///
+  /// Content readers are not consumed, so the writer can still be written
+  /// afterwards. Entries added by [`Writer::add_path`] are resolved (and
+  /// hashed, if enabled) here, then marked unresolved again, so that they are
+  /// resolved anew when the archive is written, in case the files changed in
+  /// between. The returned plan reflects the files as they were when planning.
+  pub async fn plan(&mut self) -> io::Result<WritePlan> {
+    self.prepare().await?;
+    let header_len = self.header.serialized_len(self.serialize_options)? as u32;
+    let content_len = self
+      .files
+      .iter()
+      .map(|(_, content)| content.size())
+      .sum::<u64>();
+    let offset = content_offset(header_len);
+    #[cfg(feature = "fs")]
+    let unpacked_len = self
+      .unpacked
+      .iter()
+      .map(|(_, content)| content.limit())
+      .sum();
+    #[cfg(not(feature = "fs"))]
+    let unpacked_len = 0;
+    let plan = WritePlan {
+      header: self.header.clone(),
+      header_len,
+      content_len,
+      total_len: offset + content_len + self.trailer.len() as u64,
+      file_count: self.files.len() + self.empty_files,
+      unpacked_len,
+    };
+    #[cfg(feature = "fs")]
+    self.unresolve_paths();
+    Ok(plan)
+  }
 
-    for mut file in self.files {
-      io::copy(&mut file, dest).await?;
-    }
+  /// Sets data to be appended after the archive's content.
+  ///
+  /// The trailer is not described by the header, and can be read back with
+  /// [`Archive::trailing_reader`](crate::Archive::trailing_reader).
+  pub fn set_trailer(&mut self, trailer: impl Into<Vec<u8>>) {
+    self.trailer = trailer.into();
+  }
 
-    Ok(())
+  /// Sets options controlling how the header is serialized.
+  ///
+  /// See [`SerializeOptions`] for more information.
+  pub fn set_serialize_options(&mut self, options: SerializeOptions) {
+    self.serialize_options = options;
   }
 
-  cfg_stream! {
-    pub fn into_stream(self) -> io::Result<impl Stream<Item = io::Result<Bytes>>> {
-      let mut header_bytes = serde_json::to_vec(&self.header)?;
-      let header_len = header_bytes.len() as u32;
-      let padding = match header_len % 4 {
-        0 => 0,
-        r => 4 - r,
-      };
-      for _ in 0..padding {
-        header_bytes.extend(Some(0))
-      }
+  /// Sets the physical order of files' content in the archive.
+  ///
+  /// See [`PackOrder`] for more information.
+  pub fn set_order(&mut self, order: PackOrder) {
+    self.order = order;
+  }
 
-      let mut header_meta = Vec::with_capacity((16 + header_len + padding) as _);
-      for i in [
-        4u32,
-        header_len + padding + 8,
-        header_len + padding + 4,
-        header_len,
-      ] {
-        header_meta.extend(i.to_le_bytes());
-      }
+  /// Limits the speed of writing files' content, in bytes per second.
+  ///
+  /// Useful for background packing on end-user machines, so that disks are
+  /// not saturated. `None` removes the limit.
+  pub fn set_rate_limit(&mut self, bytes_per_sec: Option<u64>) {
+    self.rate_limit = bytes_per_sec.filter(|x| *x > 0);
+  }
 
-      let stream = once(ok(header_meta.into()))
-        .chain(once(ok(header_bytes.into())))
-        .chain(iter(self.files.into_iter().map(ReaderStream::new)).flatten());
-      Ok(stream)
+  /// Sets a callback reporting progress of each file being written.
+  ///
+  /// The callback receives the file's path, bytes written so far and the
+  /// file's total size. It is called after every chunk of content is written,
+  /// by both [`Writer::write`] and `Writer::into_stream`.
+  pub fn on_progress(&mut self, progress: impl FnMut(&str, u64, u64) + Send + 'static) {
+    self.progress = Some(ProgressHook(Mutex::new(Box::new(progress))));
+  }
+
+  /// Returns extension fields of a previously added file, to be written into
+  /// the header.
+  ///
+  /// See [`FileMetadata::attributes`] for which keys are allowed. Returns
+  /// `None` if there is no file at `path`.
+  pub fn attributes_mut(&mut self, path: &str) -> Option<&mut BTreeMap<String, Value>> {
+    let metadata = self.header.search_segments_mut(&split_path(path))?;
+    Some(&mut metadata.attributes)
+  }
+
+  /// Adds an empty folder recursively to the archive.
+  pub fn add_empty_folder(&mut self, path: &str) {
+    if let Some(path) = self.accept_path(path) {
+      self.add_folder_recursively(split_path(&path));
     }
   }
-}
 
-impl<F: AsyncRead + AsyncSeek + Unpin> Writer<F> {
-  /// Add an entry to the archive.
+  /// Sets the maximum size of the whole archive, e.g. a platform's store
+  /// limit.
   ///
-  /// Similar to [`Writer::add`], but it uses [`AsyncSeekExt::seek`] to
-  /// determine the size of the content.
+  /// Once content of files added so far exceeds the budget, the file crossing
+  /// it is recorded right away; writing the archive then fails with
+  /// [`Error::ArchiveTooLarge`] naming the file that crossed the limit, before
+  /// anything is written. The final size, including the header and the
+  /// trailer, is checked again once entries added by path are resolved.
+  /// Unpacked files do not count. `None` (unlimited) by default.
+  pub fn set_max_archive_size(&mut self, max_archive_size: Option<u64>) {
+    self.max_archive_size = max_archive_size;
+    self.over_budget = None;
+  }
+
+  /// Rejects paths that are not already normalized, instead of silently
+  /// normalizing them.
   ///
-  /// For more information see [`Writer::add`].
-  pub async fn add_sized(&mut self, path: &str, mut content: F) -> io::Result<()> {
-    let size = content.seek(SeekFrom::End(0)).await? - content.stream_position().await?;
-    self.add(path, content, size);
-    Ok(())
+  /// Paths like `./a//b/../c` usually indicate a bug in the caller. With
+  /// strict paths, every path passed to the writer must equal its
+  /// [`normalize_path`] form; otherwise the entry is not added, and writing
+  /// the archive fails with [`Error::InvalidPath`]. Disabled by default.
+  pub fn set_strict_paths(&mut self, strict: bool) {
+    self.strict_paths = strict;
   }
 
-  cfg_integrity! {
-    pub async fn add_sized_with_integrity(&mut self, path: &str, mut content: F) -> io::Result<()> {
-      let mut global_state = Sha256::new();
-      let mut block = Vec::with_capacity(BLOCK_SIZE as _);
-      let mut blocks = Vec::new();
-      let mut size = 0;
-      loop {
-        let read_size = (&mut content)
-          .take(BLOCK_SIZE as _)
-          .read_to_end(&mut block)
-          .await?;
-        if read_size == 0 {
-          break;
-        }
-        size += read_size;
-        blocks.push(Hash(Sha256::digest(&block).to_vec()));
-        global_state.update(&block);
-        block.clear();
-      }
-      let integrity = Integrity {
-        algorithm: Algorithm::SHA256,
-        hash: Hash(global_state.finalize().to_vec()),
-        block_size: BLOCK_SIZE,
-        blocks,
-      };
-      content.rewind().await?;
-      self.add_with_options(path, content, size as _, false, Some(integrity));
-      Ok(())
-    }
+  /// Sets what to do with names that are invalid on Windows, e.g. `CON` or
+  /// `aux.js`, in paths added afterwards. See [`WindowsNames`].
+  ///
+  /// Paths are checked after normalization. [`WindowsNames::Allow`] by
+  /// default.
+  pub fn set_windows_names(&mut self, policy: WindowsNames) {
+    self.windows_names = policy;
   }
-}
 
-impl<F: AsyncRead + Unpin> Default for Writer<F> {
-  fn default() -> Self {
-    Self {
-      header: Default::default(),
-      file_offset: 0,
-      files: Vec::new(),
-    }
+  /// Sets the maximum length in bytes of a single component of paths added
+  /// afterwards, e.g. [`MAX_COMPONENT_LEN`](crate::format::MAX_COMPONENT_LEN).
+  ///
+  /// Electron fails to load archives with components longer than file
+  /// systems support, with obscure errors. Entries exceeding it are not
+  /// added, and writing the archive fails with [`Error::LimitExceeded`]
+  /// wrapped in [`Error::Entry`] naming the first one. `None` (unlimited) by
+  /// default.
+  pub fn set_max_component_len(&mut self, max: Option<u64>) {
+    self.max_component_len = max;
   }
-}
 
-cfg_fs! {
-  /// Pack a directory to asar archive.
-  pub async fn pack_dir(
-    path: impl AsRef<Path>,
-    dest: &mut (impl AsyncWrite + Unpin),
-  ) -> io::Result<()> {
-    pack_dir_into_writer(path)
-      .await?
-      .write(dest)
-      .await
+  /// Sets the maximum length in bytes of full paths added afterwards.
+  ///
+  /// Enforced like [`Writer::set_max_component_len`]. `None` (unlimited) by
+  /// default.
+  pub fn set_max_path_len(&mut self, max: Option<u64>) {
+    self.max_path_len = max;
   }
 
-  cfg_stream! {
-    pub async fn pack_dir_into_stream(
-      path: impl AsRef<Path>,
-    ) -> io::Result<impl Stream<Item = io::Result<Bytes>>> {
-      pack_dir_into_writer(path)
-        .await?
-        .into_stream()
-    }
+  /// Embeds an [`EmbeddedManifest`] of every file, with the time of packing
+  /// and this crate's version, at [`MANIFEST_PATH`] when the archive is
+  /// written. Disabled by default.
+  ///
+  /// The manifest is generated once files added by path are resolved, so it
+  /// records their hashes if integrity is enabled. A file previously added
+  /// at [`MANIFEST_PATH`] is replaced.
+  pub fn set_embed_manifest(&mut self, embed: bool) {
+    self.embed_manifest = embed;
   }
 
-  pub async fn pack_dir_into_writer(
-    path: impl AsRef<Path>,
-  ) -> io::Result<Writer<TokioFile>> {
-    let path = path.as_ref().canonicalize()?;
-    let mut writer = Writer::<TokioFile>::new();
-    add_dir_files(&mut writer, &path, &path).await?;
-    Ok(writer)
+  /// Checks whether adding a file (or a directory if `dir` is `true`) at
+  /// `path` would conflict with existing entries.
+  pub(crate) fn conflicts(&self, path: &str, dir: bool) -> bool {
+    let segments = split_path(path);
+    let mut current = &self.header;
+    for (i, segment) in segments.iter().enumerate() {
+      match current.files.get(*segment) {
+        None => return false,
+        Some(Entry::Directory(next)) if dir || i + 1 < segments.len() => current = next,
+        Some(_) => return true,
+      }
+    }
+    segments.is_empty() && !dir
+  }
+
+  /// Finishes the archive and writes the content into `dest`.
+  ///
+  /// Writing an empty writer is supported and produces a valid archive with
+  /// header `{"files":{}}`.
+  ///
+  /// Content of unpacked files is not written; use [`Writer::write_file`]
+  /// instead if there are any.
+  pub async fn write(self, dest: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+    self.write_inner(dest).await.map(drop)
+  }
+
+  cfg_compressed_header! {
+    /// Finishes the archive and writes it into `dest`, with the header
+    /// compressed by `codec`.
+    ///
+    /// This is a non-standard extension that Electron cannot read; see
+    /// [`HeaderCodec`]. If the compressed header is not smaller, the standard
+    /// layout is written instead, so the result is only non-standard when it
+    /// pays off.
+    pub async fn write_with_header_codec(
+      mut self,
+      dest: &mut (impl AsyncWrite + Unpin),
+      codec: &dyn HeaderCodec,
+    ) -> io::Result<()> {
+      self.prepare().await?;
+      let header = serde_json::to_vec(&self.header.serialize_with(self.serialize_options))?;
+      let compressed = codec.compress(&header)?;
+      let (prefix, header) = if compressed.len() < header.len() {
+        let header_len = header_len(compressed.len() as u64)?;
+        (encode_compressed_prefix(header_len), compressed)
+      } else {
+        (encode_prefix(header_len(header.len() as u64)?), header)
+      };
+      dest.write_all(&prefix).await?;
+      dest.write_all(&header).await?;
+      dest.write_all(&vec![0; padding(header.len() as u32) as _]).await?;
+      self.write_content(dest).await.map(drop)
+    }
+  }
+
+  /// Writes the archive, returning the number of content bytes written.
+  async fn write_inner(mut self, dest: &mut (impl AsyncWrite + Unpin)) -> io::Result<u64> {
+    self.prepare().await?;
+    // The header is serialized twice, first only to know its length, so that
+    // it never needs to be held in memory as a whole.
+    let header_len = header_len(self.header.serialized_len(self.serialize_options)?)?;
+    dest.write_all(&encode_prefix(header_len)).await?;
+    let mut chunks = HeaderChunks::new(&self.header, self.serialize_options);
+    let mut buf = Vec::with_capacity(HEADER_CHUNK_SIZE);
+    loop {
+      chunks.fill(&mut buf, HEADER_CHUNK_SIZE)?;
+      if buf.is_empty() {
+        break;
+      }
+      dest.write_all(&buf).await?;
+      buf.clear();
+    }
+    dest.write_all(&vec![0; padding(header_len) as _]).await?;
+    self.write_content(dest).await
+  }
+
+  /// Writes files' content and the trailer after the header, returning the
+  /// number of content bytes written.
+  async fn write_content(self, dest: &mut (impl AsyncWrite + Unpin)) -> io::Result<u64> {
+    let mut pacer = Pacer::new(self.rate_limit, self.progress);
+    let mut buf = vec![0; pacer.chunk_size()];
+    let mut written = 0;
+    for (path, content) in self.files {
+      let size = content.size();
+      if size == 0 {
+        continue;
+      }
+      let mut reader = content.open().await?;
+      let mut copied = 0;
+      loop {
+        let len = reader.read(&mut buf).await.with_entry(&path)?;
+        if len == 0 {
+          break;
+        }
+        dest.write_all(&buf[..len]).await?;
+        copied += len as u64;
+        pacer.advance(&path, len, copied, size).await;
+      }
+      if copied < size {
+        return Err(io::Error::new(
+          io::ErrorKind::UnexpectedEof,
+          format!("'{path}' is shorter than its size {size}"),
+        ));
+      }
+      written += copied;
+    }
+    dest.write_all(&self.trailer).await?;
+
+    Ok(written)
+  }
+
+  cfg_integrity! {
+    /// Finishes the archive, writes it into `dest`, and returns the digest of
+    /// all bytes written, computed with `D` (e.g. [`sha2::Sha256`]).
+    ///
+    /// This lets uploaders attach a checksum of the archive without reading
+    /// it again.
+    pub async fn write_with_digest<D: Digest + Send>(
+      self,
+      dest: &mut (impl AsyncWrite + Unpin),
+    ) -> io::Result<Hash> {
+      let mut dest = DigestWriter {
+        inner: dest,
+        digest: D::new(),
+      };
+      self.write(&mut dest).await?;
+      Ok(Hash::from(dest.digest.finalize().to_vec()))
+    }
+
+    /// Computes the SHA256 hash of the header, as checked by Electron's ASAR
+    /// integrity fuse.
+    ///
+    /// The header is finalized first, resolving entries added by
+    /// [`Writer::add_path`], so the hash matches the archive written
+    /// afterwards as long as no more entries are added. Its [`Display`]
+    /// output is the hex string expected in `Info.plist`'s
+    /// `ElectronAsarIntegrity`.
+    ///
+    /// [`Display`]: std::fmt::Display
+    pub async fn header_hash(&mut self) -> io::Result<Hash> {
+      self.prepare().await?;
+      let mut chunks = HeaderChunks::new(&self.header, self.serialize_options);
+      let mut digest = Sha256::new();
+      let mut buf = Vec::with_capacity(HEADER_CHUNK_SIZE);
+      loop {
+        chunks.fill(&mut buf, HEADER_CHUNK_SIZE)?;
+        if buf.is_empty() {
+          break;
+        }
+        digest.update(&buf);
+        buf.clear();
+      }
+      Ok(Hash::Sha256(digest.finalize().into()))
+    }
+
+    cfg_stream! {
+      /// Turns the archive into a stream of bytes, while computing the digest
+      /// of all bytes emitted with `D`.
+      ///
+      /// The digest is available from the returned [`DigestHandle`] once the
+      /// stream finishes successfully.
+      pub fn into_stream_with_digest<D: Digest + Send + 'static>(
+        self,
+      ) -> io::Result<(impl Stream<Item = io::Result<Bytes>>, DigestHandle)> {
+        let handle = DigestHandle::default();
+        let stream = DigestStream {
+          inner: self.into_stream()?,
+          digest: Some(D::new()),
+          handle: handle.clone(),
+        };
+        Ok((stream, handle))
+      }
+    }
+  }
+
+  cfg_stream! {
+    /// Turns the archive into a stream of bytes.
+    ///
+    /// The 16-byte prefix and the header are emitted as a single chunk. Data
+    /// chunks of all files are read into one shared buffer, whose allocation is
+    /// reused once previously yielded chunks are dropped.
+    ///
+    /// Entries added by [`Writer::add_path`] are resolved when the stream is
+    /// first polled.
+    pub fn into_stream(mut self) -> io::Result<impl Stream<Item = io::Result<Bytes>>> {
+      let prepared = once(async move {
+        self.prepare().await?;
+        let header = self.encode_header()?;
+        let pacer = Pacer::new(self.rate_limit, self.progress);
+        io::Result::Ok((header, self.files, self.trailer, pacer))
+      });
+      let stream = prepared.flat_map(|result| match result {
+        Ok((header, files, trailer, pacer)) => {
+          let trailer = Some(trailer)
+            .filter(|x| !x.is_empty())
+            .map(|x| Ok(x.into()));
+          let stream = once(ok(header))
+            .chain(data_stream(files, pacer))
+            .chain(iter(trailer));
+          Either::Left(stream)
+        }
+        Err(error) => Either::Right(once(ready(Err(error)))),
+      });
+      Ok(stream)
+    }
+
+    /// Writes the archive into `sink`, chunk by chunk as emitted by
+    /// [`Writer::into_stream`], and flushes it.
+    ///
+    /// This plugs the archive into existing [`Sink`] pipelines, e.g. a
+    /// `FramedWrite` from `tokio-util` with `BytesCodec`. Chunks are fed
+    /// without flushing in between, so the sink may batch them.
+    pub async fn write_to_sink<S>(self, sink: &mut S) -> io::Result<()>
+    where
+      S: Sink<Bytes> + Unpin,
+      S::Error: Into<io::Error>,
+    {
+      let mut stream = pin!(self.into_stream()?);
+      while let Some(chunk) = stream.next().await {
+        sink.feed(chunk?).await.map_err(Into::into)?;
+      }
+      sink.flush().await.map_err(Into::into)
+    }
+
+    /// Finishes the archive and delivers it to `sink` in chunks of exactly
+    /// `chunk_size` bytes, except for the last one which may be shorter.
+    ///
+    /// This suits multipart uploads that require a minimum part size (e.g.
+    /// 5MiB for S3), without writing the archive to a temporary file first.
+    /// Each chunk is awaited before the next one is produced, so at most one
+    /// chunk is buffered at a time.
+    ///
+    /// # Panic
+    ///
+    /// The method panics if `chunk_size` is zero.
+    pub async fn write_chunked<Fut>(
+      self,
+      chunk_size: usize,
+      mut sink: impl FnMut(Bytes) -> Fut,
+    ) -> io::Result<()>
+    where
+      Fut: std::future::Future<Output = io::Result<()>>,
+    {
+      assert!(chunk_size > 0, "chunk size must not be zero");
+      let mut stream = pin!(self.into_stream()?);
+      let mut buf = BytesMut::with_capacity(chunk_size);
+      while let Some(data) = stream.next().await {
+        let mut data = data?;
+        while !data.is_empty() {
+          let len = data.len().min(chunk_size - buf.len());
+          buf.extend_from_slice(&data.split_to(len));
+          if buf.len() == chunk_size {
+            sink(buf.split().freeze()).await?;
+          }
+        }
+      }
+      if !buf.is_empty() {
+        sink(buf.freeze()).await?;
+      }
+      Ok(())
+    }
+
+    fn encode_header(&self) -> io::Result<Bytes> {
+      let mut header = BytesMut::new();
+      header.put_bytes(0, PREFIX_LEN);
+      let serialized = self.header.serialize_with(self.serialize_options);
+      serde_json::to_writer((&mut header).writer(), &serialized)?;
+      let header_len = (header.len() - PREFIX_LEN) as u32;
+      header.put_bytes(0, padding(header_len) as _);
+      header[..PREFIX_LEN].copy_from_slice(&encode_prefix(header_len));
+      Ok(header.freeze())
+    }
+  }
+}
+
+cfg_fs! {
+  /// Options for [`Writer::add_path`].
+  #[derive(Debug, Clone, Copy, Default)]
+  pub struct PathOptions {
+    /// Whether the file is an executable.
+    pub executable: bool,
+
+    /// Whether to compute integrity information of the file.
+    #[cfg(feature = "integrity")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "integrity")))]
+    pub integrity: bool,
+
+    /// Permission bits to record in the header's `x-mode` extension field.
+    pub mode: Option<u32>,
+
+    /// How to retry transient errors opening and reading the file.
+    pub retry: Option<RetryPolicy>,
+  }
+
+  type ErrorFn = dyn Fn(&Path, &io::Error) -> ErrorPolicy + Send + Sync;
+  type NameFn = dyn Fn(&str) -> String + Send + Sync;
+
+  /// Options for packing directories, used by [`pack_dir_with_options`] and
+  /// friends.
+  #[derive(Clone, Default)]
+  pub struct PackOptions {
+    /// How to decide whether a file is executable from its permission bits.
+    pub executable: ExecutableRule,
+
+    /// Whether to record full permission bits of files in the header's
+    /// `x-mode` extension field.
+    ///
+    /// Only takes effect on Unix.
+    pub record_mode: bool,
+
+    /// Physical order of files' content in the archive.
+    pub order: PackOrder,
+
+    /// Decides what to do with a file or directory that cannot be read, e.g.
+    /// because of permissions, given its path and the error.
+    ///
+    /// Without a callback, the first error aborts packing. With one set,
+    /// files are probed by opening them while walking the directory, and
+    /// skipped ones are listed in [`PackReport::skipped`]. Errors happening
+    /// later, e.g. if a file is removed before the archive is written,
+    /// still abort.
+    pub on_error: Option<Arc<ErrorFn>>,
+
+    /// Previous archive of the directory, whose content is reused for files
+    /// that have not changed since.
+    pub cache: Option<PackCache>,
+
+    /// How to retry transient errors reading the directory and its files.
+    ///
+    /// Errors still failing after retries are handled as set in
+    /// [`PackOptions::on_error`].
+    pub retry: Option<RetryPolicy>,
+
+    /// Maps each segment of every file's path before it is added to the
+    /// archive.
+    ///
+    /// Mainly for Unicode normalization: macOS file systems return names in
+    /// NFD, while lookups usually use NFC, so files packed on macOS may not
+    /// be found elsewhere. Set this to e.g. the `unicode-normalization`
+    /// crate's NFC composition to pack every name in the same form. Packing
+    /// fails with [`AlreadyExists`](io::ErrorKind::AlreadyExists) if two
+    /// files end up at the same path.
+    pub map_name: Option<Arc<NameFn>>,
+
+    /// Which extended attributes of files to record in the archive. See
+    /// [`xattr`](crate::xattr).
+    #[cfg(feature = "xattr")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "xattr")))]
+    pub xattrs: XattrPolicy,
+
+    /// What to do with names that are invalid on Windows. See
+    /// [`Writer::set_windows_names`].
+    ///
+    /// Renamed files colliding with existing ones fail packing with
+    /// [`AlreadyExists`](io::ErrorKind::AlreadyExists).
+    pub windows_names: WindowsNames,
+
+    /// Whether to embed a manifest of packed files. See
+    /// [`Writer::set_embed_manifest`].
+    pub embed_manifest: bool,
+  }
+
+  impl Debug for PackOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      let mut f = f.debug_struct("PackOptions");
+      f.field("executable", &self.executable)
+        .field("record_mode", &self.record_mode)
+        .field("order", &self.order)
+        .field("on_error", &self.on_error.as_ref().map(|_| ".."))
+        .field("cache", &self.cache)
+        .field("retry", &self.retry)
+        .field("map_name", &self.map_name.as_ref().map(|_| ".."))
+        .field("windows_names", &self.windows_names)
+        .field("embed_manifest", &self.embed_manifest);
+      #[cfg(feature = "xattr")]
+      f.field("xattrs", &self.xattrs);
+      f.finish()
+    }
+  }
+
+  /// Previous archive of a directory, set as [`PackOptions::cache`] to speed
+  /// up packing the directory again.
+  ///
+  /// A file is considered unchanged, and its content and integrity copied
+  /// from the previous archive instead of being read and hashed again, when
+  /// the previous archive has a packed file at the same path with the same
+  /// size, and the file was last modified before the previous archive was.
+  ///
+  /// The previous archive is read while the new one is written, so they must
+  /// not be the same file; write to a temporary file and rename it instead.
+  #[derive(Debug, Clone)]
+  pub struct PackCache {
+    archive: SharedArchive<DuplicableFile>,
+    modified: SystemTime,
+  }
+
+  impl PackCache {
+    /// Uses `archive`, last modified at `modified`, as the previous archive.
+    pub fn new(archive: SharedArchive<DuplicableFile>, modified: SystemTime) -> Self {
+      Self { archive, modified }
+    }
+
+    /// Opens the previous archive at `path`, using the file's modification
+    /// time.
+    pub async fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+      let path = path.into();
+      let modified = (metadata(&path).await)
+        .and_then(|x| x.modified())
+        .with_path(&path)?;
+      let archive = SharedArchive::new_from_file(path).await?;
+      Ok(Self { archive, modified })
+    }
+
+    /// Returns the previous archive.
+    pub fn archive(&self) -> &SharedArchive<DuplicableFile> {
+      &self.archive
+    }
+
+    /// Finds reusable metadata of the file at `path`.
+    fn lookup(&self, path: &str, metadata: &std::fs::Metadata) -> Option<&FileMetadata> {
+      let Some(Entry::File(file)) = self.archive.get_entry(path) else {
+        return None;
+      };
+      let unchanged = matches!(file.pos, FilePosition::Offset(_))
+        && file.size == metadata.len()
+        && metadata.modified().is_ok_and(|x| x < self.modified);
+      #[cfg(feature = "integrity")]
+      let unchanged = unchanged && file.integrity.is_some();
+      unchanged.then_some(file)
+    }
+  }
+
+  /// What to do with an unreadable entry when packing a directory, returned
+  /// by [`PackOptions::on_error`].
+  #[derive(Debug, Clone, PartialEq, Eq)]
+  pub enum ErrorPolicy {
+    /// Fail packing with the error.
+    Abort,
+
+    /// Leave the entry out of the archive.
+    Skip,
+
+    /// Pack the given content in place of the file.
+    ///
+    /// Directories cannot be substituted, and are skipped instead.
+    Substitute(Vec<u8>),
+  }
+
+  /// Summary of [`pack_dir_with_options`].
+  #[derive(Debug, Clone, Default)]
+  pub struct PackReport {
+    /// Number of files packed.
+    pub files: u64,
+
+    /// Number of files whose content was copied from [`PackOptions::cache`].
+    pub cached: u64,
+
+    /// Entries not packed, relative to the packed directory, with reasons.
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+
+    /// Total size of files' content written.
+    pub bytes: u64,
+
+    /// Time spent packing, from walking the directory to finishing writing.
+    pub elapsed: Duration,
+  }
+
+  /// Why an entry was skipped when packing a directory.
+  #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+  #[non_exhaustive]
+  pub enum SkipReason {
+    /// The entry is a symbolic link, which asar archives cannot express.
+    Symlink,
+
+    /// The entry's path is not valid UTF-8.
+    NonUtf8,
+
+    /// The entry could not be read, and [`PackOptions::on_error`] chose to
+    /// skip it.
+    Unreadable(io::ErrorKind),
+  }
+
+  /// Rule mapping a file's permission bits to the `executable` flag.
+  ///
+  /// Permission bits are only available on Unix; on other platforms, files
+  /// are never marked executable.
+  #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+  pub enum ExecutableRule {
+    /// Never mark files executable.
+    #[default]
+    Never,
+
+    /// Mark files executable if the owner can execute it (`0o100`), like
+    /// `@electron/asar` does.
+    OwnerExec,
+
+    /// Mark files executable if anyone can execute it (`0o111`).
+    AnyExec,
+  }
+
+  impl ExecutableRule {
+    /// Applies the rule to permission bits `mode`.
+    pub fn is_executable(self, mode: u32) -> bool {
+      match self {
+        Self::Never => false,
+        Self::OwnerExec => mode & 0o100 != 0,
+        Self::AnyExec => mode & 0o111 != 0,
+      }
+    }
+  }
+
+  impl<F: AsyncRead + Unpin> Writer<F> {
+    /// Spills content added by [`Writer::add_bytes`] larger than `threshold`
+    /// bytes to temporary files, keeping memory bounded when generating many
+    /// large entries.
+    ///
+    /// Files are created in [`std::env::temp_dir`], and removed once written
+    /// into the archive or when the writer is dropped. Content added before
+    /// is not affected. `None` (never spill) by default.
+    pub fn set_spill_threshold(&mut self, threshold: Option<u64>) {
+      self.spill_threshold = threshold;
+    }
+
+    /// Add a file on the file system to the archive.
+    ///
+    /// Unlike other `add_*` methods, the file is not opened here. Its size
+    /// and integrity information (if enabled) are resolved when the archive
+    /// is being written, in multiple spawned tasks. The file is then opened
+    /// again to copy its content.
+    ///
+    /// This makes adding a huge number of files instant, and keeps the
+    /// number of simultaneously opened files bounded.
+    ///
+    /// # Panic
+    ///
+    /// See [`Writer::add`].
+    pub fn add_path(&mut self, path: &str, fs_path: impl Into<PathBuf>, options: PathOptions) {
+      let content = Content::Path {
+        path: fs_path.into(),
+        size: 0,
+        #[cfg(feature = "integrity")]
+        integrity: options.integrity,
+        resolved: false,
+        retry: options.retry,
+      };
+      let file_entry = FileMetadata {
+        pos: FilePosition::Offset(0),
+        size: 0,
+        executable: options.executable,
+        integrity: None,
+        mode: options.mode,
+        attributes: Default::default(),
+      };
+      self.add_content(path, content, file_entry);
+    }
+
+    /// Marks entries added by path as unresolved, so that they are resolved
+    /// again by the next `prepare`.
+    fn unresolve_paths(&mut self) {
+      for (_, content) in &mut self.files {
+        if let Content::Path { resolved, .. } = content {
+          *resolved = false;
+        }
+      }
+    }
+
+    async fn resolve_paths(&mut self) -> io::Result<()> {
+      let mut pending: Vec<_> = (self.files.iter_mut())
+        .filter(|(_, content)| matches!(content, Content::Path { resolved: false, .. }))
+        .collect();
+      for chunk in pending.chunks_mut(PATH_CONCURRENCY) {
+        let handles: Vec<_> = (chunk.iter())
+          .map(|(_, content)| match content {
+            #[cfg(feature = "integrity")]
+            Content::Path {
+              path,
+              integrity,
+              retry,
+              ..
+            } => tokio::spawn(resolve_path(path.clone(), *integrity, *retry)),
+            #[cfg(not(feature = "integrity"))]
+            Content::Path { path, retry, .. } => {
+              tokio::spawn(resolve_path(path.clone(), false, *retry))
+            }
+            _ => unreachable!(),
+          })
+          .collect();
+        for ((archive_path, content), handle) in chunk.iter_mut().zip(handles) {
+          let (resolved_size, integrity) = handle.await.map_err(io::Error::other)??;
+          if let Content::Path { size, resolved, .. } = content {
+            (*size, *resolved) = (resolved_size, true);
+          }
+          let metadata = (self.header)
+            .search_segments_mut(&split_path(archive_path))
+            .expect("file added to writer should exist in header");
+          metadata.size = resolved_size;
+          metadata.integrity = integrity;
+        }
+      }
+      Ok(())
+    }
+
+    /// Add an unpacked file to the archive.
+    ///
+    /// The file is recorded in the header, but its content is stored outside
+    /// the archive, at a location described by the writer's
+    /// [`UnpackedLayout`]. Unpacked content is only written by
+    /// [`Writer::write_file`].
+    ///
+    /// See [`Writer::add`] for more information.
+    pub fn add_unpacked(&mut self, path: &str, content: F, size: u64) {
+      let file_entry = FileMetadata {
+        pos: FilePosition::Unpacked,
+        size,
+        executable: false,
+        integrity: None,
+        mode: None,
+        attributes: Default::default(),
+      };
+      if let Some(normalized_path) = self.insert_file(path, file_entry) {
+        self.unpacked.push((normalized_path, content.take(size)));
+      }
+    }
+
+    /// Sets the layout used to write unpacked files.
+    pub fn set_unpacked_layout(&mut self, layout: UnpackedLayout) {
+      self.unpacked_layout = layout;
+    }
+
+    /// Finishes the archive and writes it into a file at `path`.
+    ///
+    /// The archive is first written to `path` with `.tmp` appended, and then
+    /// renamed to `path` once it is fully written and synced, so an
+    /// interrupted write never leaves a half-written archive at `path`. The
+    /// temporary file is removed if writing fails.
+    ///
+    /// Unpacked files are written according to the writer's
+    /// [`UnpackedLayout`] before the archive is renamed.
+    pub async fn write_file(mut self, path: impl AsRef<Path>) -> io::Result<()> {
+      let path = path.as_ref();
+      let mut tmp_path = path.as_os_str().to_owned();
+      tmp_path.push(".tmp");
+      let unpacked = std::mem::take(&mut self.unpacked);
+      let layout = self.unpacked_layout.clone();
+
+      let result = async {
+        let dest = TokioFile::create(&tmp_path).await.with_path(&tmp_path)?;
+        let mut dest = io::BufWriter::new(dest);
+        self.write(&mut dest).await?;
+        dest.flush().await.with_path(&tmp_path)?;
+        dest.into_inner().sync_all().await.with_path(&tmp_path)?;
+
+        for (unpacked_path, mut content) in unpacked {
+          let file_path = layout.file_path(path, &unpacked_path);
+          if let Some(parent) = file_path.parent() {
+            create_dir_all(parent).await.with_path(parent)?;
+          }
+          let mut file = TokioFile::create(&file_path).await.with_path(&file_path)?;
+          io::copy(&mut content, &mut file).await.with_path(&file_path)?;
+        }
+        Ok(())
+      }
+      .await;
+
+      match result {
+        Ok(()) => rename(&tmp_path, path).await.with_path(path),
+        Err(error) => {
+          let _ = remove_file(&tmp_path).await;
+          Err(error)
+        }
+      }
+    }
+  }
+}
+
+impl<F: AsyncRead + AsyncSeek + Unpin> Writer<F> {
+  /// Add an entry to the archive.
+  ///
+  /// Similar to [`Writer::add`], but it uses [`AsyncSeekExt::seek`] to
+  /// determine the size of the content.
+  ///
+  /// For more information see [`Writer::add`].
+  pub async fn add_sized(&mut self, path: &str, mut content: F) -> io::Result<()> {
+    let size = content.seek(SeekFrom::End(0)).await? - content.stream_position().await?;
+    self.add(path, content, size);
+    Ok(())
+  }
+
+  cfg_integrity! {
+    pub async fn add_sized_with_integrity(&mut self, path: &str, mut content: F) -> io::Result<()> {
+      let (size, integrity) = compute_integrity(&mut content).await?;
+      content.rewind().await?;
+      self.add_with_options(path, content, size, false, Some(integrity));
+      Ok(())
+    }
+  }
+}
+
+/// Asar archive writer that can be shared between tasks.
+///
+/// Cloned handles add entries to the same archive. Expensive work like
+/// determining sizes and hashing content is done outside of the internal
+/// lock, so multiple tasks can prepare files in parallel and only lock
+/// briefly to insert them.
+///
+/// Since tasks may add files in any order, files are sorted by path in
+/// [`ConcurrentWriter::finish`] before offsets are assigned, making the
+/// output independent of scheduling.
+#[derive(Debug)]
+pub struct ConcurrentWriter<F: AsyncRead + Unpin>(Arc<Mutex<Writer<F>>>);
+
+impl<F: AsyncRead + Unpin> ConcurrentWriter<F> {
+  /// Creates a new, empty concurrent archive writer.
+  pub fn new() -> Self {
+    Self::from(Writer::new())
+  }
+
+  fn lock(&self) -> MutexGuard<'_, Writer<F>> {
+    self.0.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Add an entry to the archive.
+  ///
+  /// See [`Writer::add`] for more information.
+  pub fn add(&self, path: impl AsArchivePath, content: F, size: u64) {
+    self.lock().add(path, content, size)
+  }
+
+  /// Add an entry with integrity information computed elsewhere to the
+  /// archive.
+  ///
+  /// See [`Writer::add_with_integrity`] for more information.
+  pub fn add_with_integrity(
+    &self,
+    path: &str,
+    content: F,
+    size: u64,
+    integrity: Integrity,
+  ) -> io::Result<()> {
+    check_integrity(size, &integrity)?;
+    self
+      .lock()
+      .add_with_options(path, content, size, false, Some(integrity));
+    Ok(())
+  }
+
+  /// Adds an empty folder recursively to the archive.
+  pub fn add_empty_folder(&self, path: &str) {
+    self.lock().add_empty_folder(path)
+  }
+
+  /// Returns the inner [`Writer`] with files sorted by path.
+  ///
+  /// Fails and returns the handle back if other handles of the writer still
+  /// exist.
+  pub fn finish(self) -> Result<Writer<F>, Self> {
+    let mutex = Arc::try_unwrap(self.0).map_err(Self)?;
+    let mut writer = mutex.into_inner().unwrap_or_else(PoisonError::into_inner);
+    writer.files.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(writer)
+  }
+}
+
+impl<F: AsyncRead + AsyncSeek + Unpin> ConcurrentWriter<F> {
+  /// Add an entry to the archive, determining its size by seeking.
+  ///
+  /// See [`Writer::add_sized`] for more information.
+  pub async fn add_sized(&self, path: &str, mut content: F) -> io::Result<()> {
+    let size = content.seek(SeekFrom::End(0)).await? - content.stream_position().await?;
+    self.add(path, content, size);
+    Ok(())
+  }
+
+  cfg_integrity! {
+    /// Add an entry to the archive with integrity information.
+    ///
+    /// The content is hashed before the internal lock is taken.
+    pub async fn add_sized_with_integrity(&self, path: &str, mut content: F) -> io::Result<()> {
+      let (size, integrity) = compute_integrity(&mut content).await?;
+      content.rewind().await?;
+      (self.lock()).add_with_options(path, content, size, false, Some(integrity));
+      Ok(())
+    }
+  }
+}
+
+cfg_fs! {
+  impl<F: AsyncRead + Unpin> ConcurrentWriter<F> {
+    /// Add a file on the file system to the archive.
+    ///
+    /// See [`Writer::add_path`] for more information.
+    pub fn add_path(&self, path: &str, fs_path: impl Into<PathBuf>, options: PathOptions) {
+      self.lock().add_path(path, fs_path, options)
+    }
+  }
+}
+
+impl<F: AsyncRead + Unpin> Clone for ConcurrentWriter<F> {
+  fn clone(&self) -> Self {
+    Self(self.0.clone())
+  }
+}
+
+impl<F: AsyncRead + Unpin> Default for ConcurrentWriter<F> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<F: AsyncRead + Unpin> From<Writer<F>> for ConcurrentWriter<F> {
+  fn from(writer: Writer<F>) -> Self {
+    Self(Arc::new(Mutex::new(writer)))
+  }
+}
+
+cfg_integrity! {
+  /// Writer updating a digest with every byte written.
+  #[pin_project]
+  struct DigestWriter<W, D> {
+    #[pin]
+    inner: W,
+    digest: D,
+  }
+
+  impl<W: AsyncWrite, D: Digest> AsyncWrite for DigestWriter<W, D> {
+    fn poll_write(
+      self: Pin<&mut Self>,
+      cx: &mut Context<'_>,
+      buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+      let this = self.project();
+      let len = ready!(this.inner.poll_write(cx, buf))?;
+      this.digest.update(&buf[..len]);
+      Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+      self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+      self.project().inner.poll_shutdown(cx)
+    }
+  }
+}
+
+cfg_stream! {
+  cfg_integrity! {
+    /// Digest of an archive stream returned by
+    /// [`Writer::into_stream_with_digest`].
+    #[derive(Debug, Clone, Default)]
+    pub struct DigestHandle(Arc<Mutex<Option<Hash>>>);
+
+    impl DigestHandle {
+      /// Returns the digest, or `None` if the stream has not finished
+      /// successfully yet.
+      pub fn get(&self) -> Option<Hash> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).clone()
+      }
+    }
+
+    #[pin_project]
+    struct DigestStream<S, D> {
+      #[pin]
+      inner: S,
+      digest: Option<D>,
+      handle: DigestHandle,
+    }
+
+    impl<S: Stream<Item = io::Result<Bytes>>, D: Digest> Stream for DigestStream<S, D> {
+      type Item = io::Result<Bytes>;
+
+      fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let item = ready!(this.inner.poll_next(cx));
+        match (&item, this.digest.as_mut()) {
+          (Some(Ok(chunk)), Some(digest)) => digest.update(chunk),
+          (Some(Err(_)), _) => *this.digest = None,
+          (None, Some(_)) => {
+            let hash = Hash::from(this.digest.take().unwrap().finalize().to_vec());
+            *this.handle.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(hash);
+          }
+          _ => {}
+        }
+        Poll::Ready(item)
+      }
+    }
+  }
+}
+
+cfg_stream! {
+  struct DataState<F: AsyncRead + Unpin> {
+    files: std::vec::IntoIter<(Box<str>, Content<F>)>,
+    current: Option<(Box<str>, u64, u64, Source<F>)>,
+    buf: BytesMut,
+    pacer: Pacer,
+  }
+
+  fn data_stream<F: AsyncRead + Unpin>(
+    files: Vec<(Box<str>, Content<F>)>,
+    pacer: Pacer,
+  ) -> impl Stream<Item = io::Result<Bytes>> {
+    let state = DataState {
+      files: files.into_iter(),
+      current: None,
+      buf: BytesMut::new(),
+      pacer,
+    };
+    unfold(state, |mut state| async move {
+      let chunk_size = state.pacer.chunk_size();
+      loop {
+        let (path, size, copied, file) = match &mut state.current {
+          Some(current) => current,
+          None => {
+            let (path, content) = state.files.next()?;
+            let size = content.size();
+            if size == 0 {
+              continue;
+            }
+            match content.open().await {
+              Ok(file) => state.current.insert((path, size, 0, file)),
+              Err(error) => {
+                state.files = Vec::new().into_iter();
+                return Some((Err(error), state));
+              }
+            }
+          }
+        };
+        if state.buf.capacity() < chunk_size {
+          state.buf.reserve(chunk_size);
+        }
+        let result = file.take(chunk_size as _).read_buf(&mut state.buf).await;
+        match result.with_entry(path) {
+          Ok(0) => state.current = None,
+          Ok(len) => {
+            *copied += len as u64;
+            state.pacer.advance(path, len, *copied, *size).await;
+            return Some((Ok(state.buf.split().freeze()), state));
+          }
+          Err(error) => {
+            state.files = Vec::new().into_iter();
+            state.current = None;
+            return Some((Err(error), state));
+          }
+        }
+      }
+    })
+  }
+}
+
+fn check_integrity(size: u64, integrity: &Integrity) -> io::Result<()> {
+  let invalid = |error: io::Error| io::Error::new(io::ErrorKind::InvalidInput, error.to_string());
+  integrity.validate_structure().map_err(invalid)?;
+  if size.div_ceil(integrity.block_size.into()) != integrity.blocks.len() as u64 {
+    return Err(io::Error::new(
+      io::ErrorKind::InvalidInput,
+      "number of blocks does not match size",
+    ));
+  }
+  Ok(())
+}
+
+cfg_integrity! {
+  pub(crate) async fn compute_integrity(
+    content: &mut (impl AsyncRead + Unpin),
+  ) -> io::Result<(u64, Integrity)> {
+    Integrity::compute_async(content, DEFAULT_BLOCK_SIZE).await
+  }
+}
+
+impl<F: AsyncRead + Unpin> Default for Writer<F> {
+  fn default() -> Self {
+    Self {
+      header: Default::default(),
+      files: Vec::new(),
+      trailer: Vec::new(),
+      serialize_options: Default::default(),
+      order: Default::default(),
+      rate_limit: None,
+      progress: None,
+      strict_paths: false,
+      rejected_path: None,
+      windows_names: Default::default(),
+      embed_manifest: false,
+      max_component_len: None,
+      max_path_len: None,
+      long_path: None,
+      reserved_name: None,
+      max_archive_size: None,
+      added_size: 0,
+      over_budget: None,
+      empty_files: 0,
+      #[cfg(feature = "fs")]
+      spill_threshold: None,
+      #[cfg(feature = "fs")]
+      unpacked: Vec::new(),
+      #[cfg(feature = "fs")]
+      unpacked_layout: Default::default(),
+    }
+  }
+}
+
+cfg_fs! {
+  /// Pack a directory to asar archive.
+  ///
+  /// See [`pack_dir_with_options`] for the returned report.
+  pub async fn pack_dir(
+    path: impl AsRef<Path>,
+    dest: &mut (impl AsyncWrite + Unpin),
+  ) -> io::Result<PackReport> {
+    pack_dir_with_options(path, dest, &Default::default()).await
+  }
+
+  /// Pack a directory to asar archive, with options.
+  ///
+  /// Returns a [`PackReport`] summarizing what was packed and skipped, e.g. for
+  /// build logs.
+  pub async fn pack_dir_with_options(
+    path: impl AsRef<Path>,
+    dest: &mut (impl AsyncWrite + Unpin),
+    options: &PackOptions,
+  ) -> io::Result<PackReport> {
+    let start = Instant::now();
+    let mut report = PackReport::default();
+    let writer = pack_dir_into_writer_inner(path.as_ref(), options, &mut report).await?;
+    report.bytes = writer.write_inner(dest).await?;
+    report.elapsed = start.elapsed();
+    Ok(report)
+  }
+
+  cfg_stream! {
+    pub async fn pack_dir_into_stream(
+      path: impl AsRef<Path>,
+    ) -> io::Result<impl Stream<Item = io::Result<Bytes>>> {
+      pack_dir_into_writer(path)
+        .await?
+        .into_stream()
+    }
+  }
+
+  cfg_integrity! {
+    /// Pack a directory to asar archive, with options, also returning the
+    /// header's hash for Electron's ASAR integrity fuse.
+    ///
+    /// See [`Writer::header_hash`].
+    pub async fn pack_dir_with_header_hash(
+      path: impl AsRef<Path>,
+      dest: &mut (impl AsyncWrite + Unpin),
+      options: &PackOptions,
+    ) -> io::Result<(PackReport, Hash)> {
+      let start = Instant::now();
+      let mut report = PackReport::default();
+      let mut writer = pack_dir_into_writer_inner(path.as_ref(), options, &mut report).await?;
+      let hash = writer.header_hash().await?;
+      report.bytes = writer.write_inner(dest).await?;
+      report.elapsed = start.elapsed();
+      Ok((report, hash))
+    }
+  }
+
+  pub async fn pack_dir_into_writer(
+    path: impl AsRef<Path>,
+  ) -> io::Result<Writer<TokioFile>> {
+    pack_dir_into_writer_with_options(path, &Default::default()).await
+  }
+
+  /// Adds every file in a directory into a new [`Writer`], with options.
+  pub async fn pack_dir_into_writer_with_options(
+    path: impl AsRef<Path>,
+    options: &PackOptions,
+  ) -> io::Result<Writer<TokioFile>> {
+    pack_dir_into_writer_inner(path.as_ref(), options, &mut PackReport::default()).await
+  }
+
+  async fn pack_dir_into_writer_inner(
+    path: &Path,
+    options: &PackOptions,
+    report: &mut PackReport,
+  ) -> io::Result<Writer<TokioFile>> {
+    let path = path.canonicalize().with_path(path)?;
+    let mut writer = Writer::<TokioFile>::new();
+    writer.set_order(options.order.clone());
+    writer.set_windows_names(options.windows_names);
+    writer.set_embed_manifest(options.embed_manifest);
+    add_dir_files(&mut writer, &path, &path, options, report).await?;
+    Ok(writer)
+  }
+
+  /// Consults [`PackOptions::on_error`] about `error` on `path`, returning the
+  /// error if packing should abort.
+  fn on_error(path: &Path, error: io::Error, options: &PackOptions) -> io::Result<ErrorPolicy> {
+    match options.on_error.as_ref().map(|f| f(path, &error)) {
+      None | Some(ErrorPolicy::Abort) => Err(error).with_path(path),
+      Some(policy) => Ok(policy),
+    }
+  }
+
+  impl Writer<TokioFile> {
+    /// Adds in-memory content substituting an unreadable file.
+    async fn add_substitute(&mut self, path: &str, content: Vec<u8>) -> io::Result<()> {
+      #[cfg(feature = "integrity")]
+      let integrity = Some(compute_integrity(&mut &content[..]).await?.1);
+      #[cfg(not(feature = "integrity"))]
+      let integrity = None;
+      let file_entry = FileMetadata {
+        pos: FilePosition::Offset(0),
+        size: content.len() as u64,
+        executable: false,
+        integrity,
+        mode: None,
+        attributes: Default::default(),
+      };
+      self.add_content(path, Content::Memory(content), file_entry);
+      Ok(())
+    }
+
+    /// Adds content of an unchanged file from the previous archive.
+    fn add_cached(
+      &mut self,
+      path: &str,
+      cache: &PackCache,
+      file: &FileMetadata,
+      options: PathOptions,
+    ) {
+      let content = Content::Cached {
+        archive: cache.archive.clone(),
+        path: path.into(),
+        size: file.size,
+      };
+      let file_entry = FileMetadata {
+        pos: FilePosition::Offset(0),
+        size: file.size,
+        executable: options.executable,
+        integrity: file.integrity.clone(),
+        mode: options.mode,
+        attributes: Default::default(),
+      };
+      self.add_content(path, content, file_entry);
+    }
+  }
+
+  #[cfg(unix)]
+  fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+  }
+
+  #[cfg(not(unix))]
+  fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+  }
+
+  async fn resolve_path(
+    path: PathBuf,
+    integrity: bool,
+    policy: Option<RetryPolicy>,
+  ) -> io::Result<(u64, Option<Integrity>)> {
+    #[cfg(feature = "integrity")]
+    if integrity {
+      let file = (retry(policy, || TokioFile::open(&path)).await).with_path(&path)?;
+      let mut file = Retry::new(file, policy);
+      let (size, integrity) = compute_integrity(&mut file).await.with_path(&path)?;
+      return Ok((size, Some(integrity)));
+    }
+    let _ = integrity;
+    let metadata = retry(policy, || metadata(&path)).await.with_path(&path)?;
+    Ok((metadata.len(), None))
+  }
+
+  /// Computes the path of a file in the archive, applying
+  /// [`PackOptions::map_name`].
+  fn archive_path(
+    writer: &Writer<TokioFile>,
+    relative_path: &Path,
+    options: &PackOptions,
+  ) -> io::Result<String> {
+    let path = relative_path.to_str().unwrap();
+    if options.map_name.is_none() && options.windows_names != WindowsNames::Rename {
+      return Ok(path.into());
+    }
+    let segments: Vec<_> = (split_path(path).into_iter())
+      .map(|x| options.map_name.as_ref().map_or_else(|| x.to_owned(), |f| f(x)))
+      .collect();
+    let mapped = normalize_path(&segments.join("/"));
+    let mapped = match options.windows_names.apply(&mapped) {
+      Ok(renamed) => renamed.into_owned(),
+      Err(_) => mapped,
+    };
+    let segments = split_path(&mapped);
+    if segments.is_empty() {
+      return Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("'{path}' is mapped to an empty path"),
+      ));
+    }
+    let parent_is_file = (1..segments.len())
+      .any(|i| matches!(writer.header.search_segments(&segments[..i]), Some(Entry::File(_))));
+    if parent_is_file || writer.header.search_segments(&segments).is_some() {
+      return Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("'{path}' is mapped to '{mapped}', which already exists"),
+      ));
+    }
+    Ok(mapped)
   }
 
   fn add_dir_files<'a>(
     writer: &'a mut Writer<TokioFile>,
     path: &'a Path,
     original_path: &'a Path,
-  ) -> Pin<Box<dyn Future<Output = io::Result<()>> + 'a>> {
+    options: &'a PackOptions,
+    report: &'a mut PackReport,
+  ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>> {
     Box::pin(async move {
-      if symlink_metadata(path).await?.is_dir() {
-        let mut rd = read_dir(path).await?;
-        while let Some(entry) = rd.next_entry().await? {
-          let file_type = entry.file_type().await?;
-          if file_type.is_dir() {
-            add_dir_files(writer, &entry.path(), original_path).await?;
-          } else if file_type.is_symlink() {
-            // do nothing
+      let policy = options.retry;
+      if (retry(policy, || symlink_metadata(path)).await)
+        .with_path(path)?
+        .is_dir()
+      {
+        let mut rd = match retry(policy, || read_dir(path)).await {
+          Ok(rd) => rd,
+          Err(error) => {
+            let relative_path = path.strip_prefix(original_path).unwrap();
+            let reason = SkipReason::Unreadable(error.kind());
+            on_error(path, error, options)?;
+            report.skipped.push((relative_path.into(), reason));
+            return Ok(());
+          }
+        };
+        while let Some(entry) = rd.next_entry().await.with_path(path)? {
+          let file_type = (retry(policy, || entry.file_type()).await).with_path(entry.path())?;
+          let absolute_path = entry.path();
+          let relative_path = absolute_path.strip_prefix(original_path).unwrap();
+          let skip = if file_type.is_symlink() {
+            Some(SkipReason::Symlink)
+          } else if relative_path.to_str().is_none() {
+            Some(SkipReason::NonUtf8)
           } else {
-            let absolute_path = entry.path();
-            let file = TokioFile::open(&absolute_path).await?;
-            let relative_path = absolute_path
-              .strip_prefix(original_path)
-              .unwrap()
-              .to_str()
-              .unwrap();
-            #[cfg(not(feature = "integrity"))]
-            writer.add_sized(relative_path, file).await?;
-            #[cfg(feature = "integrity")]
-            writer.add_sized_with_integrity(relative_path, file).await?;
+            None
+          };
+          if let Some(reason) = skip {
+            report.skipped.push((relative_path.into(), reason));
+          } else if file_type.is_dir() {
+            add_dir_files(writer, &absolute_path, original_path, options, report).await?;
+          } else {
+            let archive_path = archive_path(writer, relative_path, options)?;
+            let relative_path = &*archive_path;
+            if options.on_error.is_some() {
+              if let Err(error) = retry(policy, || TokioFile::open(&absolute_path)).await {
+                let reason = SkipReason::Unreadable(error.kind());
+                match on_error(&absolute_path, error, options)? {
+                  ErrorPolicy::Substitute(content) => {
+                    writer.add_substitute(relative_path, content).await?;
+                    report.files += 1;
+                  }
+                  _ => report.skipped.push((relative_path.into(), reason)),
+                }
+                continue;
+              }
+            }
+            let needs_metadata = options.executable != ExecutableRule::Never
+              || options.record_mode
+              || options.cache.is_some();
+            let metadata = if needs_metadata {
+              Some((retry(policy, || entry.metadata()).await).with_path(entry.path())?)
+            } else {
+              None
+            };
+            let mode = metadata.as_ref().and_then(file_mode);
+            let path_options = PathOptions {
+              executable: mode.is_some_and(|x| options.executable.is_executable(x)),
+              #[cfg(feature = "integrity")]
+              integrity: true,
+              mode: mode.filter(|_| options.record_mode),
+              retry: policy,
+            };
+            let cached = (options.cache.as_ref())
+              .and_then(|cache| Some((cache, cache.lookup(relative_path, metadata.as_ref()?)?)));
+            if let Some((cache, file)) = cached {
+              writer.add_cached(relative_path, cache, file, path_options);
+              report.cached += 1;
+            } else {
+              writer.add_path(relative_path, &absolute_path, path_options);
+            }
+            #[cfg(feature = "xattr")]
+            {
+              let xattrs = (crate::xattr::capture(&absolute_path, &options.xattrs).await)
+                .with_path(&absolute_path)?;
+              if !xattrs.is_empty() {
+                if let Some(attributes) = writer.attributes_mut(relative_path) {
+                  attributes.insert(XATTRS_KEY.into(), crate::xattr::encode(&xattrs));
+                }
+              }
+            }
+            report.files += 1;
           }
         }
       }
@@ -0,0 +1,242 @@
+//! Capturing and restoring extended attributes of files, e.g. for bundling
+//! macOS apps.
+//!
+//! Attributes selected by [`PackOptions::xattrs`](crate::PackOptions::xattrs)
+//! are stored in the header's non-standard `x-xattrs` extension field, as an
+//! object mapping names to hex-encoded values, and restored as selected by
+//! [`ExtractOptions::xattrs`](crate::ExtractOptions::xattrs).
+//!
+//! Only Linux and macOS are supported. Elsewhere, capturing or restoring with
+//! a policy other than [`XattrPolicy::None`] fails with
+//! [`Unsupported`](io::ErrorKind::Unsupported).
+
+use crate::header::FileMetadata;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use tokio::io;
+use tokio::task::spawn_blocking;
+
+/// Key of the extension field extended attributes are stored in.
+pub const XATTRS_KEY: &str = "x-xattrs";
+
+/// Which extended attributes to capture when packing, or to restore when
+/// extracting.
+///
+/// For example, `Except(vec!["com.apple.quarantine".into()])` keeps every
+/// attribute except the quarantine flag macOS puts on downloaded files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum XattrPolicy {
+  /// No attributes at all.
+  #[default]
+  None,
+
+  /// Every attribute.
+  All,
+
+  /// Only attributes with these names.
+  Only(Vec<String>),
+
+  /// Every attribute except ones with these names.
+  Except(Vec<String>),
+}
+
+impl XattrPolicy {
+  /// Whether the attribute `name` is selected.
+  pub fn allows(&self, name: &str) -> bool {
+    match self {
+      Self::None => false,
+      Self::All => true,
+      Self::Only(names) => names.iter().any(|x| x == name),
+      Self::Except(names) => !names.iter().any(|x| x == name),
+    }
+  }
+}
+
+/// Decodes extended attributes recorded in `file`'s `x-xattrs` field.
+///
+/// Returns an empty map if there are none, and fails with
+/// [`InvalidData`](io::ErrorKind::InvalidData) if the field is malformed.
+pub fn decode(file: &FileMetadata) -> io::Result<BTreeMap<String, Vec<u8>>> {
+  let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid x-xattrs field");
+  let Some(value) = file.attributes.get(XATTRS_KEY) else {
+    return Ok(BTreeMap::new());
+  };
+  (value.as_object().ok_or_else(invalid)?.iter())
+    .map(|(name, value)| {
+      let value = hex::decode(value.as_str().ok_or_else(invalid)?).map_err(|_| invalid())?;
+      Ok((name.clone(), value))
+    })
+    .collect()
+}
+
+/// Encodes extended attributes into the value of the `x-xattrs` field.
+pub fn encode(xattrs: &BTreeMap<String, Vec<u8>>) -> Value {
+  let map: Map<_, _> = (xattrs.iter())
+    .map(|(name, value)| (name.clone(), hex::encode(value).into()))
+    .collect();
+  map.into()
+}
+
+/// Reads attributes of the file at `path` selected by `policy`.
+pub(crate) async fn capture(
+  path: &Path,
+  policy: &XattrPolicy,
+) -> io::Result<BTreeMap<String, Vec<u8>>> {
+  if *policy == XattrPolicy::None {
+    return Ok(BTreeMap::new());
+  }
+  let (path, policy) = (path.to_owned(), policy.clone());
+  blocking(move || {
+    let mut xattrs = BTreeMap::new();
+    for name in sys::list(&path)? {
+      if !policy.allows(&name) {
+        continue;
+      }
+      match sys::get(&path, &name) {
+        Ok(value) => {
+          xattrs.insert(name, value);
+        }
+        // Removed since listed.
+        Err(error) if error.raw_os_error() == Some(sys::ENOATTR) => {}
+        Err(error) => return Err(error),
+      }
+    }
+    Ok(xattrs)
+  })
+  .await
+}
+
+/// Sets attributes recorded in `file` selected by `policy` on the file at
+/// `path`.
+pub(crate) async fn restore(
+  path: &Path,
+  file: &FileMetadata,
+  policy: &XattrPolicy,
+) -> io::Result<()> {
+  if *policy == XattrPolicy::None {
+    return Ok(());
+  }
+  let mut xattrs = decode(file)?;
+  xattrs.retain(|name, _| policy.allows(name));
+  if xattrs.is_empty() {
+    return Ok(());
+  }
+  let path = path.to_owned();
+  blocking(move || (xattrs.iter()).try_for_each(|(name, value)| sys::set(&path, name, value))).await
+}
+
+async fn blocking<T: Send + 'static>(
+  f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+  spawn_blocking(f).await.map_err(io::Error::other)?
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+mod sys {
+  use std::ffi::CString;
+  use std::os::unix::ffi::OsStrExt;
+  use std::path::Path;
+  use std::ptr::null_mut;
+  use tokio::io;
+
+  #[cfg(target_os = "macos")]
+  pub const ENOATTR: i32 = libc::ENOATTR;
+  #[cfg(not(target_os = "macos"))]
+  pub const ENOATTR: i32 = libc::ENODATA;
+
+  fn c_string(bytes: &[u8]) -> io::Result<CString> {
+    CString::new(bytes).map_err(|_| io::ErrorKind::InvalidInput.into())
+  }
+
+  /// Calls `f` first to get the length of the value, then to read it,
+  /// retrying if it grew in between.
+  fn read_with(mut f: impl FnMut(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+      let len = f(null_mut(), 0);
+      if len < 0 {
+        return Err(io::Error::last_os_error());
+      }
+      let mut buf = vec![0u8; len as usize];
+      let len = f(buf.as_mut_ptr().cast(), buf.len());
+      if len < 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::ERANGE) {
+          continue;
+        }
+        return Err(error);
+      }
+      buf.truncate(len as usize);
+      return Ok(buf);
+    }
+  }
+
+  /// Lists attribute names, leaving out ones that are not valid UTF-8.
+  pub fn list(path: &Path) -> io::Result<Vec<String>> {
+    let path = c_string(path.as_os_str().as_bytes())?;
+    let names = read_with(|buf, len| {
+      // SAFETY: `path` is a valid C string, and `buf` is either null with
+      // `len` 0 or valid for `len` bytes.
+      #[cfg(target_os = "macos")]
+      return unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len, 0) };
+      #[cfg(not(target_os = "macos"))]
+      return unsafe { libc::listxattr(path.as_ptr(), buf.cast(), len) };
+    })?;
+    Ok(
+      (names.split(|&x| x == 0))
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| String::from_utf8(x.to_vec()).ok())
+        .collect(),
+    )
+  }
+
+  pub fn get(path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    let path = c_string(path.as_os_str().as_bytes())?;
+    let name = c_string(name.as_bytes())?;
+    read_with(|buf, len| {
+      // SAFETY: `path` and `name` are valid C strings, and `buf` is either
+      // null with `len` 0 or valid for `len` bytes.
+      #[cfg(target_os = "macos")]
+      return unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len, 0, 0) };
+      #[cfg(not(target_os = "macos"))]
+      return unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len) };
+    })
+  }
+
+  pub fn set(path: &Path, name: &str, value: &[u8]) -> io::Result<()> {
+    let path = c_string(path.as_os_str().as_bytes())?;
+    let name = c_string(name.as_bytes())?;
+    let value_ptr = value.as_ptr().cast();
+    // SAFETY: `path` and `name` are valid C strings, and `value_ptr` is valid
+    // for `value.len()` bytes.
+    #[cfg(target_os = "macos")]
+    let result =
+      unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value_ptr, value.len(), 0, 0) };
+    #[cfg(not(target_os = "macos"))]
+    let result = unsafe { libc::setxattr(path.as_ptr(), name.as_ptr(), value_ptr, value.len(), 0) };
+    match result {
+      0 => Ok(()),
+      _ => Err(io::Error::last_os_error()),
+    }
+  }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+mod sys {
+  use std::path::Path;
+  use tokio::io;
+
+  pub const ENOATTR: i32 = 0;
+
+  pub fn list(_path: &Path) -> io::Result<Vec<String>> {
+    Err(io::ErrorKind::Unsupported.into())
+  }
+
+  pub fn get(_path: &Path, _name: &str) -> io::Result<Vec<u8>> {
+    Err(io::ErrorKind::Unsupported.into())
+  }
+
+  pub fn set(_path: &Path, _name: &str, _value: &[u8]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+  }
+}
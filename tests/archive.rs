@@ -0,0 +1,220 @@
+#![cfg(feature = "integrity")]
+
+use hive_asar::header::{Entry, Hash, Integrity};
+use hive_asar::list::{ListFormat, Listing, SortOrder};
+use hive_asar::{format, Archive, ArchiveOptions, ArchivePath, ArchivePathBuf, Error, Writer};
+use std::io::Cursor;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+
+async fn duplicated_files() -> Vec<u8> {
+  let mut writer = Writer::new();
+  for path in ["a", "b", "dir/c"] {
+    let content = Cursor::new(b"same content".to_vec());
+    writer
+      .add_sized_with_integrity(path, content)
+      .await
+      .unwrap();
+  }
+  let content = Cursor::new(b"other content".to_vec());
+  writer.add_sized_with_integrity("d", content).await.unwrap();
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  archive
+}
+
+fn blocks<'a>(archive: &'a Archive<Cursor<Vec<u8>>>, path: &str) -> &'a Arc<Vec<Hash>> {
+  match archive.get_entry(path) {
+    Some(Entry::File(file)) => &file.integrity.as_ref().unwrap().blocks,
+    entry => panic!("unexpected entry: {entry:?}"),
+  }
+}
+
+#[tokio::test]
+async fn intern_integrity_when_parsing() {
+  let options = ArchiveOptions {
+    intern_integrity: true,
+    ..Default::default()
+  };
+  let data = duplicated_files().await;
+  let archive = Archive::new_with_options(Cursor::new(data.clone()), options)
+    .await
+    .unwrap();
+  assert!(Arc::ptr_eq(blocks(&archive, "a"), blocks(&archive, "b")));
+  assert!(Arc::ptr_eq(
+    blocks(&archive, "a"),
+    blocks(&archive, "dir/c")
+  ));
+  assert!(!Arc::ptr_eq(blocks(&archive, "a"), blocks(&archive, "d")));
+
+  let archive = Archive::new(Cursor::new(data)).await.unwrap();
+  assert!(!Arc::ptr_eq(blocks(&archive, "a"), blocks(&archive, "b")));
+}
+
+fn raw_archive(header: &str, content: &[u8]) -> Vec<u8> {
+  let header_len = header.len() as u32;
+  let mut archive = format::encode_prefix(header_len).to_vec();
+  archive.extend_from_slice(header.as_bytes());
+  archive.resize(format::content_offset(header_len) as usize, 0);
+  archive.extend_from_slice(content);
+  archive
+}
+
+#[tokio::test]
+async fn oversized_offset_saturates_content_len() {
+  let header = r#"{"files":{"a":{"size":5,"offset":"18446744073709551610"}}}"#;
+  let mut archive = Archive::new(Cursor::new(raw_archive(header, b"trailing")))
+    .await
+    .unwrap();
+  assert_eq!(archive.content_len(), u64::MAX);
+  assert!(!archive.is_complete());
+  assert_eq!(archive.trailing_len(), 0);
+  let mut trailing = Vec::new();
+  archive
+    .trailing_reader()
+    .await
+    .unwrap()
+    .read_to_end(&mut trailing)
+    .await
+    .unwrap();
+  assert!(trailing.is_empty());
+}
+
+#[tokio::test]
+async fn oversized_offset_is_truncated() {
+  let header = concat!(
+    r#"{"files":{"a":{"size":5,"offset":"18446744073709551610"},"#,
+    r#""b":{"size":0,"offset":"18446744073709551615"}}}"#,
+  );
+  let mut archive = Archive::new(Cursor::new(raw_archive(header, b"")))
+    .await
+    .unwrap();
+  for path in ["a", "b"] {
+    let Err(error) = archive.get(path).await else {
+      panic!("{path}: expected an error");
+    };
+    let error = error.get_ref().and_then(|x| x.downcast_ref::<Error>());
+    assert!(
+      matches!(error, Some(Error::Truncated { available: 0, .. })),
+      "{path}: {error:?}"
+    );
+  }
+}
+
+const BLOCKED: &[u8] = b"0123456789abcdefghij";
+
+/// Returns an archive holding `BLOCKED` as "f", with 4-byte integrity
+/// blocks, whose byte at `corrupt` is flipped.
+async fn blocked_archive(corrupt: Option<usize>) -> Archive<Cursor<Vec<u8>>> {
+  let (size, integrity) = Integrity::compute_async(&mut Cursor::new(BLOCKED), 4)
+    .await
+    .unwrap();
+  let mut writer = Writer::new();
+  writer
+    .add_with_integrity("f", Cursor::new(BLOCKED.to_vec()), size, integrity)
+    .unwrap();
+  let mut data = Vec::new();
+  writer.write(&mut data).await.unwrap();
+  if let Some(index) = corrupt {
+    let start = data.len() - BLOCKED.len();
+    data[start + index] = !data[start + index];
+  }
+  Archive::new(Cursor::new(data)).await.unwrap()
+}
+
+fn assert_mismatch(error: &std::io::Error, expected: Option<usize>) {
+  let error = error.get_ref().and_then(|x| x.downcast_ref::<Error>());
+  assert!(
+    matches!(error, Some(Error::IntegrityMismatch { path, block }) if &**path == "f" && *block == expected),
+    "{error:?}"
+  );
+}
+
+#[tokio::test]
+async fn verified_file_reads_intact_content() {
+  let mut archive = blocked_archive(None).await;
+  let mut content = Vec::new();
+  let mut file = archive.read_verified("f").await.unwrap();
+  file.read_to_end(&mut content).await.unwrap();
+  assert_eq!(content, BLOCKED);
+}
+
+#[tokio::test]
+async fn verified_file_rejects_corrupt_block() {
+  let mut archive = blocked_archive(Some(9)).await;
+  let mut file = archive.read_verified("f").await.unwrap();
+  let mut verified = [0; 8];
+  file.read_exact(&mut verified).await.unwrap();
+  assert_eq!(verified, BLOCKED[..8]);
+  let error = file.read(&mut [0; 4]).await.unwrap_err();
+  assert_mismatch(&error, Some(2));
+}
+
+#[tokio::test]
+async fn read_range_verified_within_blocks() {
+  // Starts in the middle of block 1 and ends in the middle of block 3.
+  let mut archive = blocked_archive(None).await;
+  let mut file = archive.get("f").await.unwrap();
+  let content = file.read_range_verified(5..14).await.unwrap();
+  assert_eq!(content, BLOCKED[5..14]);
+
+  // Corruption outside of the blocks touched is not noticed.
+  let mut archive = blocked_archive(Some(17)).await;
+  let mut file = archive.get("f").await.unwrap();
+  let content = file.read_range_verified(5..14).await.unwrap();
+  assert_eq!(content, BLOCKED[5..14]);
+
+  let mut archive = blocked_archive(Some(9)).await;
+  let mut file = archive.get("f").await.unwrap();
+  let error = file.read_range_verified(5..14).await.unwrap_err();
+  assert_mismatch(&error, Some(2));
+}
+
+#[tokio::test]
+async fn listing_follows_sort_order() {
+  let mut writer = Writer::<Cursor<Vec<u8>>>::new();
+  for path in ["b10", "B2", "a", "b2", "c/y10", "c/y9"] {
+    writer.add_empty_file(path);
+  }
+  let mut data = Vec::new();
+  writer.write(&mut data).await.unwrap();
+  let archive = Archive::new(Cursor::new(data)).await.unwrap();
+
+  let orders = [
+    (
+      SortOrder::Bytewise,
+      ["B2", "a", "b10", "b2", "c", "c/y10", "c/y9"],
+    ),
+    (
+      SortOrder::Natural,
+      ["B2", "a", "b2", "b10", "c", "c/y9", "c/y10"],
+    ),
+    (
+      SortOrder::CaseInsensitive,
+      ["a", "b10", "B2", "b2", "c", "c/y10", "c/y9"],
+    ),
+  ];
+  for (order, expected) in orders {
+    let listing = Listing::new(archive.header(), ListFormat::Short).sorted(order);
+    let paths: Vec<_> = listing.entries().iter().map(|x| &*x.path).collect();
+    assert_eq!(paths, expected, "{order:?}");
+  }
+}
+
+#[tokio::test]
+async fn archive_paths_are_used_as_is() {
+  let path = ArchivePathBuf::normalize("./dir//file/");
+  let mut writer = Writer::new();
+  writer.add(&path, Cursor::new(b"content".to_vec()), 7);
+  let mut data = Vec::new();
+  writer.write(&mut data).await.unwrap();
+  let mut archive = Archive::new(Cursor::new(data)).await.unwrap();
+
+  assert!(archive.get_entry(&*path).is_some());
+  assert!(archive.get_entry("dir/../dir/file").is_some());
+  let file = archive
+    .get(ArchivePath::new("dir/file").unwrap())
+    .await
+    .unwrap();
+  assert_eq!(file.path(), path.as_str());
+}
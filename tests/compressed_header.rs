@@ -0,0 +1,105 @@
+#![cfg(feature = "compressed-header")]
+
+use hive_asar::format::{self, HeaderCodec};
+use hive_asar::{Archive, ArchiveOptions, Writer};
+use std::io::{self, Cursor, ErrorKind};
+use tokio::io::AsyncReadExt;
+
+/// Run-length encoding as pairs of count and byte.
+#[derive(Debug)]
+struct RunLength;
+
+impl HeaderCodec for RunLength {
+  fn compress(&self, header: &[u8]) -> io::Result<Vec<u8>> {
+    let mut result = Vec::new();
+    for run in header.chunk_by(|a, b| a == b) {
+      for chunk in run.chunks(255) {
+        result.extend_from_slice(&[chunk.len() as u8, chunk[0]]);
+      }
+    }
+    Ok(result)
+  }
+
+  fn decompress(&self, data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+    let mut result = Vec::new();
+    for pair in data.chunks(2) {
+      let &[len, byte] = pair else {
+        return Err(ErrorKind::InvalidData.into());
+      };
+      if result.len() + len as usize > max_len {
+        return Err(io::Error::new(ErrorKind::InvalidData, "header too long"));
+      }
+      result.resize(result.len() + len as usize, byte);
+    }
+    Ok(result)
+  }
+}
+
+/// Never makes headers smaller.
+#[derive(Debug)]
+struct Identity;
+
+impl HeaderCodec for Identity {
+  fn compress(&self, header: &[u8]) -> io::Result<Vec<u8>> {
+    Ok(header.to_vec())
+  }
+
+  fn decompress(&self, data: &[u8], _max_len: usize) -> io::Result<Vec<u8>> {
+    Ok(data.to_vec())
+  }
+}
+
+async fn write(codec: &dyn HeaderCodec) -> Vec<u8> {
+  let mut writer = Writer::new();
+  let path = format!("{}/file", "d".repeat(1000));
+  writer.add(&path, Cursor::new(b"content".to_vec()), 7);
+  let mut data = Vec::new();
+  writer
+    .write_with_header_codec(&mut data, codec)
+    .await
+    .unwrap();
+  data
+}
+
+fn options(max_header_len: Option<u32>) -> ArchiveOptions {
+  ArchiveOptions {
+    max_header_len,
+    header_codec: Some(&RunLength),
+    ..Default::default()
+  }
+}
+
+#[tokio::test]
+async fn compressed_header_round_trips() {
+  let data = write(&RunLength).await;
+  let prefix = data[..16].try_into().unwrap();
+  let compressed_len = format::decode_compressed_prefix(prefix).unwrap();
+  assert!(compressed_len < 200, "{compressed_len}");
+
+  let archive = Archive::new_with_options(Cursor::new(data), options(None));
+  let mut archive = archive.await.unwrap();
+  let mut content = String::new();
+  let path = format!("{}/file", "d".repeat(1000));
+  let mut file = archive.get(&path).await.unwrap();
+  file.read_to_string(&mut content).await.unwrap();
+  assert_eq!(content, "content");
+  assert_eq!(archive.trailing_len(), 0);
+}
+
+#[tokio::test]
+async fn decompressed_header_is_limited() {
+  let data = write(&RunLength).await;
+  let archive = Archive::new_with_options(Cursor::new(data), options(Some(512)));
+  let Err(error) = archive.await else {
+    panic!("expected an error");
+  };
+  assert_eq!(error.kind(), ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn incompressible_header_falls_back_to_standard_layout() {
+  let data = write(&Identity).await;
+  let prefix = data[..16].try_into().unwrap();
+  assert!(format::decode_prefix(prefix).is_some());
+  Archive::new(Cursor::new(data)).await.unwrap();
+}
@@ -0,0 +1,112 @@
+use hive_asar::header::Entry;
+use hive_asar::{Archive, EditableArchive, Writer};
+use std::io::Cursor;
+use tokio::io::AsyncReadExt;
+
+async fn fixture() -> EditableArchive<Cursor<Vec<u8>>> {
+  let mut writer = Writer::new();
+  for (path, content) in [
+    ("a.txt", "hello"),
+    ("dir/b.txt", "world"),
+    ("secret", "hunter2"),
+  ] {
+    let (size, content) = (
+      content.len() as u64,
+      Cursor::new(content.as_bytes().to_vec()),
+    );
+    #[cfg(feature = "integrity")]
+    let _ = size;
+    #[cfg(feature = "integrity")]
+    writer
+      .add_sized_with_integrity(path, content)
+      .await
+      .unwrap();
+    #[cfg(not(feature = "integrity"))]
+    writer.add(path, content, size);
+  }
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  EditableArchive::new(Cursor::new(archive)).await.unwrap()
+}
+
+async fn reopen(archive: EditableArchive<Cursor<Vec<u8>>>) -> Archive<Cursor<Vec<u8>>> {
+  let data = archive.into_inner().into_reader().into_inner();
+  Archive::new(Cursor::new(data)).await.unwrap()
+}
+
+async fn read(archive: &mut Archive<Cursor<Vec<u8>>>, path: &str) -> Vec<u8> {
+  let mut content = Vec::new();
+  let mut file = archive.get(path).await.unwrap();
+  file.read_to_end(&mut content).await.unwrap();
+  content
+}
+
+#[tokio::test]
+async fn remove_and_commit() {
+  let mut archive = fixture().await;
+  let header_len = archive.raw_header().await.unwrap().len();
+  assert!(matches!(
+    archive.remove("secret").await.unwrap(),
+    Entry::File(_)
+  ));
+  assert!(archive.get_entry("secret").is_none());
+  assert_eq!(archive.pending_changes().len(), 1);
+  let changes = archive.commit().await.unwrap();
+  assert!(archive.pending_changes().is_empty());
+  assert_eq!(changes.len(), 1);
+  assert_eq!(changes[0].path, "secret");
+  assert!(matches!(&changes[0].before, Entry::File(file) if file.size == 7));
+  assert!(changes[0].after.is_none());
+
+  let mut archive = reopen(archive).await;
+  assert!(archive.get_entry("secret").is_none());
+  assert_eq!(archive.raw_header().await.unwrap().len(), header_len);
+  assert_eq!(read(&mut archive, "dir/b.txt").await, b"world");
+}
+
+#[tokio::test]
+async fn uncommitted_removal_is_not_written() {
+  let mut archive = fixture().await;
+  archive.remove("dir").await.unwrap();
+  let archive = reopen(archive).await;
+  assert!(archive.get_entry("dir/b.txt").is_some());
+}
+
+#[tokio::test]
+async fn overwrite_and_commit() {
+  let mut archive = fixture().await;
+  archive.overwrite("a.txt", &b"HELLO"[..]).await.unwrap();
+  let error = archive
+    .overwrite("a.txt", &b"too long"[..])
+    .await
+    .unwrap_err();
+  assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+  archive.overwrite("a.txt", &b"HELLO"[..]).await.unwrap();
+  let changes = archive.commit().await.unwrap();
+  assert_eq!(changes.len(), 2);
+  assert!(changes.iter().all(|x| x.path == "a.txt"));
+  #[cfg(feature = "integrity")]
+  {
+    let hash = |entry: &Entry| match entry {
+      Entry::File(file) => file.integrity.as_ref().unwrap().hash.clone(),
+      _ => panic!("not a file"),
+    };
+    assert_ne!(
+      hash(&changes[0].before),
+      hash(changes[0].after.as_ref().unwrap())
+    );
+    assert_eq!(
+      hash(changes[0].after.as_ref().unwrap()),
+      hash(&changes[1].before)
+    );
+  }
+
+  let mut archive = reopen(archive).await;
+  assert_eq!(read(&mut archive, "a.txt").await, b"HELLO");
+  #[cfg(feature = "integrity")]
+  {
+    let mut file = archive.get("a.txt").await.unwrap();
+    assert!(file.metadata().integrity.is_some());
+    assert!(file.check_integrity().await.unwrap());
+  }
+}
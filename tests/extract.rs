@@ -0,0 +1,30 @@
+#![cfg(feature = "fs")]
+
+use hive_asar::{Archive, Error, Writer};
+use std::io::Cursor;
+
+#[tokio::test]
+async fn truncated_content() {
+  let mut writer = Writer::<Cursor<Vec<u8>>>::new();
+  writer.add("a/b.txt", Cursor::new(b"hello world".to_vec()), 11);
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  archive.truncate(archive.len() - 5);
+
+  let dest = std::env::temp_dir().join(format!("hive-asar-{}-truncated", std::process::id()));
+  tokio::fs::create_dir_all(&dest).await.unwrap();
+  let mut archive = Archive::new(Cursor::new(archive)).await.unwrap();
+  let error = archive.extract(&dest).await.unwrap_err();
+  let content = tokio::fs::read(dest.join("a/b.txt")).await.unwrap();
+  tokio::fs::remove_dir_all(&dest).await.unwrap();
+
+  assert_eq!(content, b"hello ");
+  match error.get_ref().and_then(|x| x.downcast_ref::<Error>()) {
+    Some(Error::Truncated {
+      path,
+      expected,
+      available,
+    }) => assert_eq!((&**path, *expected, *available), ("a/b.txt", 11, 6)),
+    _ => panic!("unexpected error: {error:?}"),
+  }
+}
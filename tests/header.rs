@@ -0,0 +1,59 @@
+use hive_asar::header::Hash;
+use hive_asar::{format, Archive, Error};
+use std::io::Cursor;
+
+fn parse(hex: &str) -> Hash {
+  serde_json::from_str(&format!("\"{hex}\"")).unwrap()
+}
+
+#[test]
+fn hash_of_other_length_stays_raw() {
+  let hex = "00112233445566778899aabbccddeeff";
+  let hash = parse(hex);
+  assert!(matches!(&hash, Hash::Raw(x) if x.len() == 16));
+  assert_eq!(serde_json::to_string(&hash).unwrap(), format!("\"{hex}\""));
+}
+
+#[test]
+fn uppercase_hash_round_trips() {
+  let hex = "0123456789abcdef".repeat(4);
+  let hash = parse(&hex.to_uppercase());
+  assert!(matches!(hash, Hash::Sha256(_)));
+  assert_eq!(hash, parse(&hex));
+  assert_eq!(serde_json::to_string(&hash).unwrap(), format!("\"{hex}\""));
+}
+
+#[test]
+fn raw_and_sha256_hashes_compare_by_bytes() {
+  let bytes = [0xab; 32];
+  let raw = Hash::Raw(bytes.into());
+  assert_eq!(raw, Hash::Sha256(bytes));
+  assert_eq!(Hash::Sha256(bytes), raw);
+  assert_ne!(raw, Hash::Sha256([0xcd; 32]));
+  assert_ne!(Hash::Raw(bytes[..31].into()), Hash::Sha256(bytes));
+}
+
+/// Returns a single-file archive whose header is marked compressed, but
+/// stored as is.
+fn flagged_archive() -> Vec<u8> {
+  let header = br#"{"files":{"a":{"offset":"0","size":1}}}"#;
+  let mut archive = format::encode_compressed_prefix(header.len() as u32).to_vec();
+  archive.extend_from_slice(header);
+  archive.resize(format::content_offset(header.len() as u32) as usize, 0);
+  archive.push(b'a');
+  archive
+}
+
+#[tokio::test]
+async fn compressed_header_without_codec_is_unsupported() {
+  let data = flagged_archive();
+  assert_eq!(format::decode_prefix(data[..16].try_into().unwrap()), None);
+  let Err(error) = Archive::new(Cursor::new(data)).await else {
+    panic!("expected an error");
+  };
+  let error = error.get_ref().and_then(|x| x.downcast_ref::<Error>());
+  assert!(
+    matches!(error, Some(Error::UnsupportedCompressedHeader)),
+    "{error:?}"
+  );
+}
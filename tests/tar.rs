@@ -0,0 +1,47 @@
+use hive_asar::Writer;
+use std::io::ErrorKind;
+
+fn tar_header(name: &str, kind: u8, size: usize) -> [u8; 512] {
+  let mut header = [0; 512];
+  header[..name.len()].copy_from_slice(name.as_bytes());
+  header[100..108].copy_from_slice(b"0000644\0");
+  header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+  header[156] = kind;
+  header[257..263].copy_from_slice(b"ustar\0");
+  header[263..265].copy_from_slice(b"00");
+  header[148..156].fill(b' ');
+  let checksum: u32 = header.iter().map(|x| *x as u32).sum();
+  header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+  header
+}
+
+fn tar_with_pax(records: &[u8]) -> Vec<u8> {
+  let mut tar = tar_header("pax", b'x', records.len()).to_vec();
+  tar.extend_from_slice(records);
+  tar.resize(tar.len().next_multiple_of(512), 0);
+  tar.extend_from_slice(&tar_header("a.txt", b'0', 0));
+  tar.extend_from_slice(&[0; 1024]);
+  tar
+}
+
+#[tokio::test]
+async fn pax_path() {
+  let tar = tar_with_pax(b"14 path=b.txt\n");
+  let writer = Writer::from_tar(&tar[..]).await.unwrap();
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  let archive = hive_asar::Archive::new(std::io::Cursor::new(archive))
+    .await
+    .unwrap();
+  assert!(archive.get_entry("b.txt").is_some());
+  assert!(archive.get_entry("a.txt").is_none());
+}
+
+#[tokio::test]
+async fn malformed_pax_record() {
+  for records in [&b"2 "[..], b"3 x", b"4 a=b", b"9 path=ab\n"] {
+    let tar = tar_with_pax(records);
+    let error = Writer::from_tar(&tar[..]).await.unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData, "{records:?}");
+  }
+}
@@ -0,0 +1,19 @@
+#![cfg(feature = "watch")]
+
+use hive_asar::watch::{pack_on_change, WatchOptions};
+use std::io::ErrorKind;
+
+#[tokio::test]
+async fn dest_inside_dir() {
+  let dir = std::env::temp_dir().join(format!("hive-asar-{}-watch", std::process::id()));
+  tokio::fs::create_dir_all(dir.join("sub")).await.unwrap();
+  for dest in [dir.join("app.asar"), dir.join("sub/../sub/app.asar")] {
+    let error = pack_on_change(&dir, &dest, WatchOptions::default())
+      .await
+      .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput, "{}", dest.display());
+  }
+  let entries = std::fs::read_dir(&dir).unwrap().count();
+  tokio::fs::remove_dir_all(&dir).await.unwrap();
+  assert_eq!(entries, 1);
+}
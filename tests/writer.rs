@@ -0,0 +1,175 @@
+use hive_asar::header::{Entry, FileMetadata, FilePosition};
+use hive_asar::{Archive, PackOrder, Writer};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncReadExt;
+
+/// Writes `writer`, checks the prefix and header bytes against `header`, and
+/// parses the result.
+async fn write_and_parse(
+  writer: Writer<Cursor<Vec<u8>>>,
+  header: &[u8],
+) -> Archive<Cursor<Vec<u8>>> {
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  let header_len = header.len() as u32;
+  let padded = header_len.next_multiple_of(4);
+  let mut expected = Vec::new();
+  for x in [4, padded + 8, padded + 4, header_len] {
+    expected.extend_from_slice(&x.to_le_bytes());
+  }
+  expected.extend_from_slice(header);
+  expected.resize(16 + padded as usize, 0);
+  assert_eq!(archive, expected);
+  Archive::new(Cursor::new(archive)).await.unwrap()
+}
+
+#[tokio::test]
+async fn empty_archive() {
+  let archive = write_and_parse(Writer::new(), br#"{"files":{}}"#).await;
+  assert!(archive.header().files.is_empty());
+}
+
+#[tokio::test]
+async fn single_empty_dir() {
+  let mut writer = Writer::new();
+  writer.add_empty_folder("a");
+  let archive = write_and_parse(writer, br#"{"files":{"a":{"files":{}}}}"#).await;
+  assert_eq!(archive.header().files.len(), 1);
+  match archive.get_entry("a") {
+    Some(Entry::Directory(dir)) => assert!(dir.files.is_empty()),
+    entry => panic!("unexpected entry: {entry:?}"),
+  }
+}
+
+#[cfg(all(feature = "fs", feature = "integrity"))]
+fn temp_path(name: &str) -> std::path::PathBuf {
+  std::env::temp_dir().join(format!("hive-asar-{}-{name}", std::process::id()))
+}
+
+#[cfg(all(feature = "fs", feature = "integrity"))]
+#[tokio::test]
+async fn plan_then_write_resolves_paths_again() {
+  use hive_asar::header::Integrity;
+  use hive_asar::PathOptions;
+  use tokio::io::AsyncReadExt;
+
+  let path = temp_path("plan-then-write");
+  tokio::fs::write(&path, b"abc").await.unwrap();
+  let mut writer = Writer::<Cursor<Vec<u8>>>::new();
+  let options = PathOptions {
+    integrity: true,
+    ..Default::default()
+  };
+  writer.add_path("a.txt", &path, options);
+  let plan = writer.plan().await.unwrap();
+  assert_eq!(plan.content_len, 3);
+
+  tokio::fs::write(&path, b"abcdefghij").await.unwrap();
+  let mut archive = Vec::new();
+  writer.write(&mut archive).await.unwrap();
+  tokio::fs::remove_file(&path).await.unwrap();
+
+  let mut archive = Archive::new(Cursor::new(archive)).await.unwrap();
+  let mut file = archive.get("a.txt").await.unwrap();
+  let metadata = file.metadata().clone();
+  let mut content = Vec::new();
+  file.read_to_end(&mut content).await.unwrap();
+  assert_eq!(content, b"abcdefghij");
+  assert_eq!(metadata.size, 10);
+  let (_, expected) = Integrity::compute_async(&mut &content[..], 4_194_304)
+    .await
+    .unwrap();
+  assert_eq!(metadata.integrity.unwrap().hash, expected.hash);
+}
+
+#[cfg(feature = "fs")]
+#[tokio::test]
+async fn write_file_error_has_path() {
+  use hive_asar::Error;
+
+  let dest = std::env::temp_dir().join("hive-asar-missing-dir/app.asar");
+  let error = (Writer::<Cursor<Vec<u8>>>::new().write_file(&dest).await).unwrap_err();
+  match error.get_ref().and_then(|x| x.downcast_ref::<Error>()) {
+    Some(Error::Io { path, .. }) => assert_eq!(*path, dest.with_extension("asar.tmp")),
+    _ => panic!("unexpected error: {error:?}"),
+  }
+}
+
+fn offset(archive: &Archive<Cursor<Vec<u8>>>, path: &str) -> u64 {
+  match archive.get_entry(path) {
+    Some(Entry::File(FileMetadata {
+      pos: FilePosition::Offset(offset),
+      ..
+    })) => *offset,
+    entry => panic!("unexpected entry: {entry:?}"),
+  }
+}
+
+#[tokio::test]
+async fn offsets_follow_pack_order() {
+  let files = [("a.js", 5), ("b.css", 1), ("c", 3), ("d.js", 2)];
+  let orders = [
+    (PackOrder::Insertion, [0, 5, 6, 9]),
+    (PackOrder::SmallFirst, [6, 0, 3, 1]),
+    (PackOrder::ByExtension, [4, 3, 0, 9]),
+    (
+      PackOrder::Profile(vec!["d.js".into(), "/b.css".into()]),
+      [3, 2, 8, 0],
+    ),
+  ];
+  for (order, expected) in orders {
+    let mut writer = Writer::new();
+    for (path, size) in files {
+      writer.add(path, Cursor::new(vec![size as u8; size]), size as u64);
+    }
+    writer.set_order(order.clone());
+    let mut data = Vec::new();
+    writer.write(&mut data).await.unwrap();
+    let mut archive = Archive::new(Cursor::new(data)).await.unwrap();
+    for ((path, size), expected) in files.into_iter().zip(expected) {
+      assert_eq!(offset(&archive, path), expected, "{path} in {order:?}");
+      let mut content = Vec::new();
+      let mut file = archive.get(path).await.unwrap();
+      file.read_to_end(&mut content).await.unwrap();
+      assert_eq!(content, vec![size as u8; size], "{path} in {order:?}");
+    }
+  }
+}
+
+#[tokio::test]
+async fn progress_is_monotonic_and_complete() {
+  let files = [("big", 150_000), ("empty", 0), ("small", 10)];
+  let mut writer = Writer::new();
+  for (path, size) in files {
+    writer.add(path, Cursor::new(vec![0; size]), size as u64);
+  }
+  let reports = Arc::new(Mutex::new(Vec::new()));
+  let reports2 = reports.clone();
+  writer.on_progress(move |path, copied, size| {
+    reports2
+      .lock()
+      .unwrap()
+      .push((path.to_owned(), copied, size));
+  });
+  writer.write(&mut Vec::new()).await.unwrap();
+
+  let reports = reports.lock().unwrap();
+  for (path, size) in files {
+    let reports: Vec<_> = (reports.iter())
+      .filter(|(x, ..)| x == path)
+      .map(|(_, copied, total)| (*copied, *total))
+      .collect();
+    if size == 0 {
+      assert!(reports.is_empty(), "{path}: {reports:?}");
+      continue;
+    }
+    assert!(reports.iter().all(|(_, total)| *total == size as u64));
+    assert!(
+      reports.windows(2).all(|x| x[0].0 < x[1].0),
+      "{path}: {reports:?}"
+    );
+    assert_eq!(reports.last().unwrap().0, size as u64, "{path}");
+  }
+  assert!(reports.iter().filter(|(x, ..)| x == "big").count() > 1);
+}